@@ -0,0 +1,87 @@
+//! A complex number, with associated functions
+
+use std::ops;
+
+/// A complex number.
+#[derive(Copy, Clone)]
+pub struct Complex {
+	/// The real part
+	pub re: f64,
+	/// The imaginary part
+	pub im: f64,
+}
+
+#[allow(dead_code)]
+impl Complex {
+	/// Create a new complex number with given values.
+	pub fn new(re: f64, im: f64) -> Self {
+		Complex{re, im}
+	}
+
+	/// Create a new 0.
+	pub fn zero() -> Self {
+		Complex{re: 0.0, im: 0.0}
+	}
+
+	/// The complex number e^(i phase).
+	pub fn from_phase(phase: f64) -> Self {
+		Complex{re: phase.cos(), im: phase.sin()}
+	}
+
+	/// The complex conjugate.
+	pub fn conj(&self) -> Self {
+		Complex{re: self.re, im: -self.im}
+	}
+
+	/// The squared absolute value.
+	pub fn sqabs(&self) -> f64 {
+		self.re * self.re + self.im * self.im
+	}
+}
+
+impl ops::Add<Complex> for Complex {
+	type Output = Complex;
+	fn add(self, other: Complex) -> Complex {
+		Complex{
+			re: self.re + other.re,
+			im: self.im + other.im,
+		}
+	}
+}
+
+impl ops::AddAssign for Complex {
+	fn add_assign(&mut self, other: Self) {
+		self.re += other.re;
+		self.im += other.im;
+	}
+}
+
+impl ops::Sub<Complex> for Complex {
+	type Output = Complex;
+	fn sub(self, other: Complex) -> Complex {
+		Complex{
+			re: self.re - other.re,
+			im: self.im - other.im,
+		}
+	}
+}
+
+impl ops::Mul<Complex> for Complex {
+	type Output = Complex;
+	fn mul(self, other: Complex) -> Complex {
+		Complex{
+			re: self.re * other.re - self.im * other.im,
+			im: self.re * other.im + self.im * other.re,
+		}
+	}
+}
+
+impl ops::Mul<f64> for Complex {
+	type Output = Complex;
+	fn mul(self, other: f64) -> Complex {
+		Complex{
+			re: self.re * other,
+			im: self.im * other,
+		}
+	}
+}
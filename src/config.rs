@@ -0,0 +1,2146 @@
+/*! Runtime configuration of a simulation, read from a TOML file.
+
+All settings default to the values in constants, except for the particles, which must be given.
+Values are given in the unit system of simulation.units, and converted to the internal units (see units) when the file is read.
+A minimal configuration file looks like:
+
+```toml
+[simulation]
+time_step = 0.001
+length = 150.0
+integrator = "verlet"
+
+[box]
+x = 10.0
+y = 10.0
+z = 10.0
+periodic = false
+
+[thermostat]
+target = 0.0
+coupling = 5.0
+start = 50.0
+
+[[particle]]
+position = [1.0, 1.0, 1.0]
+radius = 1.0
+mass = 3.0
+charge = 0.0
+```
+
+Instead of a radius, mass and charge, particles can refer to a species by name:
+
+```toml
+[[species]]
+name = "argon"
+sigma = 1.0
+epsilon = 1.0
+mass = 1.0
+
+[[particle]]
+position = [1.0, 1.0, 1.0]
+species = "argon"
+```
+*/
+
+use std::fmt;
+use std::error::Error;
+use std::rc::Rc;
+use std::path::Path;
+use std::str::FromStr;
+use serde::Deserialize;
+use serde::de::IntoDeserializer;
+
+use crate::constants::{TIME_STEP, SIM_LEN, BORDER_X, BORDER_Y, BORDER_Z, BORDER_4_EPSILON, BORDER_RANGE, LJ_4_EPSILON, TABLE_TOLERANCE, EWALD_ACCURACY, PME_ORDER, NOSE_HOOVER_CHAIN, BAROSTAT_COMPRESSIBILITY, LANGEVIN_FRICTION, MINIMIZE_MAX_FORCE, MINIMIZE_ENERGY_CHANGE, MINIMIZE_STEPS, MINIMIZE_STEP, EXCLUSIONS, SCALE_14, CONSTRAINT_TOLERANCE, CONSTRAINT_ITERATIONS, INSERTION_ATTEMPTS, MAX_LATTICE_SITES, SEED, W, H, FRAME_RATE};
+use crate::vectors::Vector;
+use crate::units::{Units, Dimension};
+use crate::particles::Particle;
+use crate::species::{SpeciesTable, Species, PairParameters, MixingRule};
+use crate::forcefield::{ForceField, Electrostatics};
+use crate::forcefield::ewald::Ewald;
+use crate::forcefield::pme::Pme;
+use crate::forcefield::borders::{Borders, WallPotential, Container, Shape};
+use crate::forcefield::external::{External, ElectricField};
+use crate::forcefield::bonded::{Topology, Bond, BondPotential, BondError, Angle, Dihedral, DihedralPotential};
+use crate::forcefield::vanderwaals::{Cutoff, CutoffScheme};
+use crate::forcefield::potentials::{PairPotential, Wca, Buckingham, Morse, Yukawa, SoftSphere};
+use crate::forcefield::tabulated::Tabulated;
+use crate::forcefield::temperature::{self, Thermostat, Berendsen, NoseHooverChain, VelocityRescale, Andersen};
+use crate::forcefield::pressure::{self, Barostat, Mtk};
+use crate::integrators::{Integrator, Euler, VelocityVerlet, Leapfrog, Langevin, Boris};
+use crate::minimizers::{self, Minimizer, SteepestDescent, ConjugateGradient, Fire, Criteria, Minimization};
+use crate::constraints::{Constraints, Constraint, Settle, ConstraintError};
+use crate::simulation::{Simulation, SimulationError};
+use crate::simbox::SimBox;
+use crate::generators::{self, Lattice};
+use crate::random::Random;
+use crate::neighbors::NeighborList;
+
+/// An error while reading or validating a configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+	/// The file could not be read
+	Io(std::io::Error),
+	/// The file is not valid TOML, has missing or unknown keys, or values of the wrong type
+	Parse(toml::de::Error),
+	/// A value is outside of its valid range
+	Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ConfigError::Io(e) => write!(f, "Could not read the configuration: {}", e),
+			ConfigError::Parse(e) => write!(f, "Invalid configuration: {}", e),
+			ConfigError::Invalid(e) => write!(f, "Invalid configuration: {}", e),
+		}
+	}
+}
+
+impl Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+	fn from(e: std::io::Error) -> Self {
+		ConfigError::Io(e)
+	}
+}
+
+impl From<toml::de::Error> for ConfigError {
+	fn from(e: toml::de::Error) -> Self {
+		ConfigError::Parse(e)
+	}
+}
+
+/// Settings for stepping through time.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+	/// Timestep of the simulation
+	pub time_step: f64,
+	/// Length of the simulation (time)
+	pub length: f64,
+	/// The integrator
+	pub integrator: IntegratorMethod,
+	/// The seed of all random numbers, so runs can be reproduced
+	pub seed: u64,
+	/// The unit system of all values in the configuration and the data log
+	pub units: UnitsConfig,
+}
+
+/// The integrators, see the integrators module.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegratorMethod {
+	/// Semi-implicit Euler
+	Euler,
+	/// Velocity Verlet
+	Verlet,
+	/// Leapfrog
+	Leapfrog,
+	/// Langevin dynamics, which needs [langevin]
+	Langevin,
+	/// The Boris pusher, for field.magnetic
+	Boris,
+}
+
+impl FromStr for IntegratorMethod {
+	type Err = ConfigError;
+
+	/// Parse an integrator by the name it has in the configuration.
+	fn from_str(name: &str) -> Result<Self, ConfigError> {
+		Self::deserialize(name.into_deserializer()).map_err(|e: serde::de::value::Error| ConfigError::Invalid(format!("integrator: {}", e)))
+	}
+}
+
+/// The unit systems, see Units.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitsConfig {
+	/// Lennard-Jones reduced units
+	Lj,
+	/// Angstrom, femtoseconds, kcal/mol, g/mol, kelvin and elementary charges
+	Real,
+	/// SI units, with charges in elementary charges
+	Si,
+}
+
+/// The size of the system, and either the strength of its borders or periodic boundaries, unless the boundaries are given per axis in [walls].
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BoxConfig {
+	/// The border of the system in the x direction. The other border is at the origin.
+	pub x: f64,
+	/// The border of the system in the y direction. The other border is at the origin.
+	pub y: f64,
+	/// The border of the system in the z direction. The other border is at the origin.
+	pub z: f64,
+	/// Whether the box is periodic along all axes, instead of having borders
+	pub periodic: bool,
+	/// 4 times the repulsion strength of the borders.
+	pub border_4_epsilon: f64,
+	/// The range at which the border potential is calculated.
+	pub border_range: f64,
+}
+
+/// Parameters of the force terms.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForceFieldConfig {
+	/// 4 * the well depth of the Lennard-Jones potential, for particles without a species
+	pub lj_4_epsilon: f64,
+	/// Elementary charge in the internal units, defaults to the value of the unit system
+	pub elementary_charge: Option<f64>,
+	/// The mixing rule of the Lennard-Jones parameters of different species
+	pub mixing: MixingConfig,
+}
+
+/// The mixing rules, see MixingRule.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MixingConfig {
+	/// The arithmetic mean of the sigmas, and the geometric mean of the epsilons
+	LorentzBerthelot,
+	/// The geometric means of both the sigmas and the epsilons
+	Geometric,
+}
+
+/// A particle species.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpeciesConfig {
+	/// The name, by which particles refer to the species
+	pub name: String,
+	/// The size in the Lennard-Jones potential, which is also the radius
+	pub sigma: f64,
+	/// The well depth of the Lennard-Jones potential
+	pub epsilon: f64,
+	/// Mass
+	pub mass: f64,
+	/// Charge, in elementary charges
+	#[serde(default)]
+	pub charge: f64,
+}
+
+/// The pair potentials, see the potentials module.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PotentialType {
+	/// The Lennard-Jones 12-6 potential
+	LennardJones,
+	/// The repulsive part of the Lennard-Jones potential
+	Wca,
+	/// A exp(-r / rho) - C / r^6
+	Buckingham,
+	/// D ((1 - exp(-a (r - r0)))^2 - 1)
+	Morse,
+	/// The screened Coulomb potential, A exp(-kappa r) / r
+	Yukawa,
+	/// epsilon (sigma / r)^n
+	SoftSphere,
+	/// Interpolated from a table of energies and forces
+	Tabulated,
+}
+
+/// The potential of a pair of species, instead of the Lennard-Jones potential with the parameters following from the mixing rule.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PairConfig {
+	/// The names of the two species
+	pub species: [String; 2],
+	/// The potential
+	#[serde(default = "default_potential")]
+	pub potential: PotentialType,
+	/// The size of the pair, for the lennard_jones, wca and soft_sphere potentials, the last two default to the mixing rule
+	pub sigma: Option<f64>,
+	/// The well depth of the pair, for the lennard_jones, wca and soft_sphere potentials, the last two default to the mixing rule
+	pub epsilon: Option<f64>,
+	/// The strength of the repulsion of the buckingham potential
+	pub a: Option<f64>,
+	/// The range of the repulsion of the buckingham potential
+	pub rho: Option<f64>,
+	/// The strength of the attraction of the buckingham potential
+	pub c: Option<f64>,
+	/// The well depth of the morse potential
+	pub depth: Option<f64>,
+	/// The inverse width of the well of the morse potential
+	pub width: Option<f64>,
+	/// The distance of the minimum of the morse potential
+	pub length: Option<f64>,
+	/// The strength of the yukawa potential, positive if the particles repel
+	pub strength: Option<f64>,
+	/// The inverse screening length of the yukawa potential
+	pub kappa: Option<f64>,
+	/// The exponent of the soft_sphere potential
+	pub exponent: Option<f64>,
+	/// The file of the tabulated potential, relative to the configuration file, see the tabulated module for its format
+	pub table: Option<String>,
+	/// The largest difference between the integrated forces and the energies of the tabulated potential, relative to the range of its energies
+	pub tolerance: Option<f64>,
+	/// The tabulated potential read from the table, once the configuration is loaded
+	#[serde(skip)]
+	tabulated: Option<Rc<Tabulated>>,
+}
+
+/// The default pair potential.
+fn default_potential() -> PotentialType {
+	PotentialType::LennardJones
+}
+
+/// Settings of the non-bonded interactions between bonded particles.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TopologyConfig {
+	/// Pairs of particles separated by at most this many bonds have no non-bonded interactions
+	pub exclusions: usize,
+	/// The scaling of the van der Waals interactions of pairs three bonds apart, unless they are excluded
+	pub scale_14_vdw: f64,
+	/// The scaling of the electrostatic interactions of pairs three bonds apart, unless they are excluded
+	pub scale_14_electric: f64,
+}
+
+/// The potentials of bonds, see BondPotential.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BondType {
+	/// A harmonic spring
+	Harmonic,
+	/// A finitely extensible nonlinear elastic bond
+	Fene,
+}
+
+/// A bond between two particles.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BondConfig {
+	/// The indices of the particles, in the order they are given
+	pub particles: [usize; 2],
+	/// The potential
+	#[serde(default = "default_bond")]
+	pub potential: BondType,
+	/// The force constant
+	pub k: f64,
+	/// The equilibrium length of a harmonic bond
+	pub length: Option<f64>,
+	/// The maximum length of a FENE bond
+	pub max_length: Option<f64>,
+}
+
+/// The default bond potential.
+fn default_bond() -> BondType {
+	BondType::Harmonic
+}
+
+/// A harmonic angle between three particles, with the middle particle at the vertex.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AngleConfig {
+	/// The indices of the particles
+	pub particles: [usize; 3],
+	/// The force constant
+	pub k: f64,
+	/// The equilibrium angle, in degrees
+	pub angle: f64,
+}
+
+/// The potentials of dihedrals, see DihedralPotential.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DihedralType {
+	/// k (1 + cos(multiplicity phi - phase))
+	Periodic,
+	/// The Ryckaert-Bellemans polynomial in cos(phi - 180)
+	RyckaertBellemans,
+}
+
+/// A dihedral between four particles, bonded in order.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DihedralConfig {
+	/// The indices of the particles
+	pub particles: [usize; 4],
+	/// The potential
+	#[serde(default = "default_dihedral")]
+	pub potential: DihedralType,
+	/// The force constant of a periodic dihedral
+	pub k: Option<f64>,
+	/// The number of minima in a full turn of a periodic dihedral
+	pub multiplicity: Option<f64>,
+	/// The phase of a periodic dihedral in degrees, defaults to 0
+	pub phase: Option<f64>,
+	/// The six coefficients of a Ryckaert-Bellemans dihedral
+	pub coefficients: Option<[f64; 6]>,
+}
+
+/// The default dihedral potential.
+fn default_dihedral() -> DihedralType {
+	DihedralType::Periodic
+}
+
+/// Settings of the constraints.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConstraintsConfig {
+	/// The largest relative error at which the constraints are solved
+	pub tolerance: f64,
+	/// The maximum number of iterations of SHAKE and RATTLE
+	pub max_iterations: usize,
+	/// Whether to constrain all harmonic bonds at their length, instead of using their potential
+	pub bonds: bool,
+}
+
+/// A constraint, keeping two particles at a fixed distance.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConstraintConfig {
+	/// The indices of the particles
+	pub particles: [usize; 2],
+	/// The distance between the particles
+	pub length: f64,
+}
+
+/// A rigid three-site water molecule, solved with SETTLE.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SettleConfig {
+	/// The indices of the oxygen and the two hydrogens, which must have the same mass
+	pub particles: [usize; 3],
+	/// The distance between the oxygen and each hydrogen
+	pub oh: f64,
+	/// The distance between the hydrogens
+	pub hh: f64,
+}
+
+/// The methods to calculate the electrostatic interactions, see Electrostatics.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ElectrostaticsMethod {
+	/// No electrostatic interactions
+	None,
+	/// Coulomb's law between each pair, only valid without periodic boundaries
+	Coulomb,
+	/// Ewald summation, for a periodic box
+	Ewald,
+	/// Particle-mesh Ewald summation, for large systems in a periodic box
+	Pme,
+}
+
+/// The boundaries along an axis of the box.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WallType {
+	/// Periodic boundaries
+	Periodic,
+	/// No walls, for the axes across a container
+	None,
+	/// The repulsive walls of box.border_4_epsilon and box.border_range
+	Repulsive,
+	/// The 9-3 Lennard-Jones wall
+	Lj93,
+	/// A harmonic wall
+	Harmonic,
+	/// Specular reflection
+	Reflective,
+}
+
+/// The boundaries along each axis, instead of box.periodic, with the parameters of the walls.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WallsConfig {
+	/// The boundaries along the x axis
+	#[serde(default = "default_wall")]
+	pub x: WallType,
+	/// The boundaries along the y axis
+	#[serde(default = "default_wall")]
+	pub y: WallType,
+	/// The boundaries along the z axis
+	#[serde(default = "default_wall")]
+	pub z: WallType,
+	/// The strength of the lj93 walls
+	pub epsilon: Option<f64>,
+	/// The size of the particles of the lj93 walls
+	pub sigma: Option<f64>,
+	/// The distance beyond which the potential of the lj93 walls is zero
+	pub cutoff: Option<f64>,
+	/// The force constant of the harmonic walls
+	pub k: Option<f64>,
+	/// The distance beyond which the potential of the harmonic walls is zero
+	pub range: Option<f64>,
+}
+
+/// The default boundaries along an axis.
+fn default_wall() -> WallType {
+	WallType::Repulsive
+}
+
+/// The shapes of containers, see Shape.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ShapeConfig {
+	/// A sphere
+	Sphere,
+	/// An infinite cylinder along an axis
+	Cylinder,
+}
+
+/// The axes of the box.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AxisConfig {
+	/// The x axis
+	X,
+	/// The y axis
+	Y,
+	/// The z axis
+	Z,
+}
+
+/// A spherical or cylindrical container, with a wall on its surface.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContainerConfig {
+	/// The shape
+	pub shape: ShapeConfig,
+	/// The radius
+	pub radius: f64,
+	/// The centre of the sphere, or a point on the axis of the cylinder, defaults to the centre of the box
+	pub centre: Option<[f64; 3]>,
+	/// The axis of the cylinder
+	pub axis: Option<AxisConfig>,
+	/// The wall, which cannot be periodic or none
+	pub wall: WallType,
+	/// The strength of an lj93 wall
+	pub epsilon: Option<f64>,
+	/// The size of the particles of an lj93 wall
+	pub sigma: Option<f64>,
+	/// The distance beyond which the potential of an lj93 wall is zero
+	pub cutoff: Option<f64>,
+	/// The force constant of a harmonic wall
+	pub k: Option<f64>,
+	/// The distance beyond which the potential of a harmonic wall is zero
+	pub range: Option<f64>,
+}
+
+/// The external fields, see External.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FieldConfig {
+	/// The constant part of the electric field
+	pub electric: [f64; 3],
+	/// The amplitude of the oscillating part of the electric field
+	pub amplitude: [f64; 3],
+	/// The angular frequency of the oscillating part of the electric field
+	pub frequency: f64,
+	/// The phase of the oscillating part of the electric field, in degrees
+	pub phase: f64,
+	/// The gravitational accelleration
+	pub gravity: [f64; 3],
+	/// The magnetic field, which needs the boris integrator
+	pub magnetic: [f64; 3],
+}
+
+/// Settings of the electrostatic interactions.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ElectrostaticsConfig {
+	/// The method used
+	pub method: ElectrostaticsMethod,
+	/// The relative accuracy of the (particle-mesh) Ewald sum, which sets its alpha and kmax or grid
+	pub accuracy: f64,
+	/// The real space cutoff of the (particle-mesh) Ewald sum, defaults to the neighbor list cutoff or else half the box size
+	pub cutoff: Option<f64>,
+	/// The order of the B-splines of particle-mesh Ewald, defaults to PME_ORDER
+	pub order: Option<usize>,
+}
+
+/// The ways the pair potentials can go to zero at the cutoff, see CutoffScheme.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CutoffSchemeConfig {
+	/// Set the potential to zero beyond the cutoff
+	Truncated,
+	/// Shift the potential to zero at the cutoff
+	Shifted,
+	/// Shift the force to zero at the cutoff
+	ForceShifted,
+	/// Switch the potential off smoothly, from switch_start to the cutoff
+	Switched,
+}
+
+/// Settings of the cutoff of the pair potentials.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CutoffConfig {
+	/// The distance beyond which the potential is zero
+	pub radius: f64,
+	/// How the potential goes to zero at the cutoff
+	#[serde(default = "default_scheme")]
+	pub scheme: CutoffSchemeConfig,
+	/// The distance at which the switching starts, for the switched scheme
+	pub switch_start: Option<f64>,
+	/// Whether to calculate the long-range tail corrections
+	#[serde(default)]
+	pub tail_correction: bool,
+}
+
+/// The default cutoff scheme.
+fn default_scheme() -> CutoffSchemeConfig {
+	CutoffSchemeConfig::Shifted
+}
+
+/// Settings of the neighbor list.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NeighborConfig {
+	/// The distance beyond which pairs do not interact
+	pub cutoff: f64,
+	/// The extra distance included in the list
+	#[serde(default = "default_skin")]
+	pub skin: f64,
+}
+
+/// The default skin of a neighbor list.
+fn default_skin() -> f64 {
+	0.3
+}
+
+/// The available thermostats.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThermostatMethod {
+	/// Berendsen weak coupling
+	Berendsen,
+	/// A Nose-Hoover chain
+	NoseHoover,
+	/// Canonical sampling through velocity rescaling (Bussi)
+	VelocityRescale,
+	/// Andersen collisions
+	Andersen,
+}
+
+/// Settings of the thermostat.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThermostatConfig {
+	/// The thermostat used
+	#[serde(default = "default_thermostat")]
+	pub method: ThermostatMethod,
+	/// The target temperature
+	pub target: f64,
+	/// The coupling constant: the period of the temperature oscillations for a Nose-Hoover chain,
+	/// the relaxation time of the kinetic energy for velocity rescaling, and the average time between collisions for Andersen
+	pub coupling: f64,
+	/// The time at which the thermostat is switched on
+	#[serde(default)]
+	pub start: f64,
+	/// The length of a Nose-Hoover chain, defaults to NOSE_HOOVER_CHAIN
+	pub chain: Option<usize>,
+}
+
+/// The default thermostat.
+fn default_thermostat() -> ThermostatMethod {
+	ThermostatMethod::Berendsen
+}
+
+/// The available barostats.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BarostatMethod {
+	/// Berendsen weak coupling
+	Berendsen,
+	/// Martyna-Tobias-Klein
+	Mtk,
+}
+
+/// Settings of the barostat.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BarostatConfig {
+	/// The barostat used
+	#[serde(default = "default_barostat")]
+	pub method: BarostatMethod,
+	/// The target pressure
+	pub target: f64,
+	/// The coupling constant: the relaxation time of the pressure for Berendsen, and the period of the volume oscillations for MTK
+	pub coupling: f64,
+	/// The time at which the barostat is switched on
+	#[serde(default)]
+	pub start: f64,
+	/// The compressibility of the system for Berendsen, defaults to BAROSTAT_COMPRESSIBILITY
+	pub compressibility: Option<f64>,
+	/// The temperature setting the mass of an MTK barostat, defaults to the target of the thermostat or the temperature of the langevin integrator
+	pub temperature: Option<f64>,
+}
+
+/// The default barostat.
+fn default_barostat() -> BarostatMethod {
+	BarostatMethod::Berendsen
+}
+
+/// Settings of the Langevin integrator.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LangevinConfig {
+	/// The target temperature
+	pub temperature: f64,
+	/// The friction coefficient of particles that do not set their own
+	#[serde(default = "default_friction")]
+	pub friction: f64,
+}
+
+/// The default friction coefficient of the Langevin integrator.
+fn default_friction() -> f64 {
+	LANGEVIN_FRICTION
+}
+
+/// The minimizers, see the minimizers module.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MinimizeMethod {
+	/// Steepest descent with a line search
+	SteepestDescent,
+	/// Polak-Ribiere conjugate gradient with a line search
+	ConjugateGradient,
+	/// The fast inertial relaxation engine
+	Fire,
+}
+
+/// Settings of the energy minimization before the dynamics start.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MinimizeConfig {
+	/// The minimizer
+	pub method: MinimizeMethod,
+	/// Converged once the largest force on any particle is below this
+	pub max_force: f64,
+	/// Converged once the potential energy changes by at most this in a step
+	pub energy_change: f64,
+	/// The maximum number of steps
+	pub steps: usize,
+	/// The largest displacement of a particle in a step
+	pub step: f64,
+	/// The initial time step of fire, defaults to 10 times simulation.time_step
+	pub time_step: Option<f64>,
+}
+
+/// Settings of the windows.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisplayConfig {
+	/// Width of the windows
+	pub width: usize,
+	/// Height of the windows
+	pub height: usize,
+	/// Frame rate of the windows
+	pub frame_rate: f64,
+}
+
+/// The ways of generating particles, see generators.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GenerateMethod {
+	/// A simple cubic lattice
+	Sc,
+	/// A body-centred cubic lattice
+	Bcc,
+	/// A face-centred cubic lattice
+	Fcc,
+	/// A hexagonal close-packed lattice
+	Hcp,
+	/// Random insertion without overlaps
+	Random,
+}
+
+/// Particles generated on a lattice or at random positions, added after the given particles.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenerateConfig {
+	/// The way the particles are generated
+	pub method: GenerateMethod,
+	/// The names of the species of the particles
+	pub species: Vec<String>,
+	/// The mole fraction of each species, defaults to equal fractions
+	pub fractions: Option<Vec<f64>>,
+	/// The number density of a lattice
+	pub density: Option<f64>,
+	/// The number of particles inserted at random
+	pub count: Option<usize>,
+	/// The smallest distance between particles inserted at random, on top of the minimum distance of their pair potential
+	pub min_distance: Option<f64>,
+	/// The number of random positions tried for each particle, defaults to INSERTION_ATTEMPTS
+	pub attempts: Option<usize>,
+}
+
+/// Initial velocities drawn from the Maxwell-Boltzmann distribution, replacing those of the particles.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VelocitiesConfig {
+	/// The temperature, to which the velocities are scaled exactly
+	pub temperature: f64,
+	/// Whether the angular momentum is removed as well as the momentum, which needs a box without periodic axes
+	#[serde(default)]
+	pub remove_rotation: bool,
+}
+
+/// The initial state of a particle.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParticleConfig {
+	/// Position
+	pub position: [f64; 3],
+	/// The name of the species, which sets the radius, mass and charge
+	pub species: Option<String>,
+	/// Radius, if there is no species
+	pub radius: Option<f64>,
+	/// Mass, if there is no species
+	pub mass: Option<f64>,
+	/// Charge in elementary charges, if there is no species, defaults to 0
+	pub charge: Option<f64>,
+	/// Initial velocity
+	#[serde(default)]
+	pub velocity: [f64; 3],
+	/// The friction coefficient of the Langevin integrator, defaults to langevin.friction
+	pub friction: Option<f64>,
+}
+
+/// A complete configuration of a simulation.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+	/// Settings for stepping through time
+	#[serde(default)]
+	pub simulation: SimulationConfig,
+	/// The size of the system
+	#[serde(default, rename = "box")]
+	pub simbox: BoxConfig,
+	/// Parameters of the force terms
+	#[serde(default)]
+	pub forcefield: ForceFieldConfig,
+	/// Settings of the electrostatic interactions
+	#[serde(default)]
+	pub electrostatics: ElectrostaticsConfig,
+	/// The cutoff of the pair potentials, if any
+	pub cutoff: Option<CutoffConfig>,
+	/// The neighbor list, if any
+	pub neighbors: Option<NeighborConfig>,
+	/// The boundaries along each axis, if not given by box.periodic
+	pub walls: Option<WallsConfig>,
+	/// The container, if any
+	pub container: Option<ContainerConfig>,
+	/// The external fields, if any
+	pub field: Option<FieldConfig>,
+	/// The thermostat, if any
+	pub thermostat: Option<ThermostatConfig>,
+	/// The barostat, if any
+	pub barostat: Option<BarostatConfig>,
+	/// Settings of the Langevin integrator, which must be given when it is used
+	pub langevin: Option<LangevinConfig>,
+	/// The energy minimization before the dynamics start, if any
+	pub minimize: Option<MinimizeConfig>,
+	/// Settings of the windows
+	#[serde(default)]
+	pub display: DisplayConfig,
+	/// The particle species
+	#[serde(default)]
+	pub species: Vec<SpeciesConfig>,
+	/// The potentials of pairs of species that do not use the Lennard-Jones potential with the parameters from the mixing rule
+	#[serde(default, rename = "pair")]
+	pub pairs: Vec<PairConfig>,
+	/// The non-bonded interactions between bonded particles
+	#[serde(default)]
+	pub topology: TopologyConfig,
+	/// The bonds
+	#[serde(default, rename = "bond")]
+	pub bonds: Vec<BondConfig>,
+	/// The angles
+	#[serde(default, rename = "angle")]
+	pub angles: Vec<AngleConfig>,
+	/// The dihedrals
+	#[serde(default, rename = "dihedral")]
+	pub dihedrals: Vec<DihedralConfig>,
+	/// Settings of the constraints
+	#[serde(default)]
+	pub constraints: ConstraintsConfig,
+	/// The constraints between pairs of particles
+	#[serde(default, rename = "constraint")]
+	pub constraint_pairs: Vec<ConstraintConfig>,
+	/// The rigid water molecules
+	#[serde(default, rename = "settle")]
+	pub settles: Vec<SettleConfig>,
+	/// The particles generated on a lattice or at random, if any
+	pub generate: Option<GenerateConfig>,
+	/// The initial velocities, if not given by the particles
+	pub velocities: Option<VelocitiesConfig>,
+	/// The initial particles
+	#[serde(default, rename = "particle")]
+	pub particles: Vec<ParticleConfig>,
+}
+
+impl Default for SimulationConfig {
+	fn default() -> Self {
+		SimulationConfig{
+			time_step: TIME_STEP,
+			length: SIM_LEN,
+			integrator: IntegratorMethod::Verlet,
+			seed: SEED,
+			units: UnitsConfig::Lj,
+		}
+	}
+}
+
+impl Default for BoxConfig {
+	fn default() -> Self {
+		BoxConfig{
+			x: BORDER_X,
+			y: BORDER_Y,
+			z: BORDER_Z,
+			periodic: false,
+			border_4_epsilon: BORDER_4_EPSILON,
+			border_range: BORDER_RANGE,
+		}
+	}
+}
+
+impl Default for ForceFieldConfig {
+	fn default() -> Self {
+		ForceFieldConfig{
+			lj_4_epsilon: LJ_4_EPSILON,
+			elementary_charge: None,
+			mixing: MixingConfig::LorentzBerthelot,
+		}
+	}
+}
+
+impl Default for TopologyConfig {
+	fn default() -> Self {
+		TopologyConfig{
+			exclusions: EXCLUSIONS,
+			scale_14_vdw: SCALE_14,
+			scale_14_electric: SCALE_14,
+		}
+	}
+}
+
+impl Default for ConstraintsConfig {
+	fn default() -> Self {
+		ConstraintsConfig{
+			tolerance: CONSTRAINT_TOLERANCE,
+			max_iterations: CONSTRAINT_ITERATIONS,
+			bonds: false,
+		}
+	}
+}
+
+impl Default for ElectrostaticsConfig {
+	fn default() -> Self {
+		ElectrostaticsConfig{
+			method: ElectrostaticsMethod::Coulomb,
+			accuracy: EWALD_ACCURACY,
+			cutoff: None,
+			order: None,
+		}
+	}
+}
+
+impl Default for FieldConfig {
+	fn default() -> Self {
+		FieldConfig{
+			electric: [0.0; 3],
+			amplitude: [0.0; 3],
+			frequency: 0.0,
+			phase: 0.0,
+			gravity: [0.0; 3],
+			magnetic: [0.0; 3],
+		}
+	}
+}
+
+impl Default for MinimizeConfig {
+	fn default() -> Self {
+		MinimizeConfig{
+			method: MinimizeMethod::Fire,
+			max_force: MINIMIZE_MAX_FORCE,
+			energy_change: MINIMIZE_ENERGY_CHANGE,
+			steps: MINIMIZE_STEPS,
+			step: MINIMIZE_STEP,
+			time_step: None,
+		}
+	}
+}
+
+impl Default for DisplayConfig {
+	fn default() -> Self {
+		DisplayConfig{
+			width: W,
+			height: H,
+			frame_rate: FRAME_RATE,
+		}
+	}
+}
+
+impl ParticleConfig {
+	/// Create a particle at rest.
+	fn new(position: [f64; 3], radius: f64, mass: f64, charge: f64) -> Self {
+		ParticleConfig{ position, species: None, radius: Some(radius), mass: Some(mass), charge: Some(charge), velocity: [0.0; 3], friction: None }
+	}
+}
+
+impl Default for Config {
+	/// The default system, with a few neutral particles.
+	fn default() -> Self {
+		let mut particles = vec![ParticleConfig::new([1.0, 1.0, 1.0], 1.0, 3.0, 0.0),
+		                         ParticleConfig::new([4.0, 3.0, 1.0], 1.0, 1.0, 0.0),
+		                         ParticleConfig::new([1.0, 5.0, 1.0], 1.5, 1.0, 0.0)];
+		for i in 0..3 {
+			particles.push(ParticleConfig::new([1.0 + 2.0 * i as f64, 7.0, 9.0 - 2.0 * i as f64], 1.0, 1.0, 0.0));
+		}
+
+		Config{
+			simulation: SimulationConfig::default(),
+			simbox: BoxConfig::default(),
+			forcefield: ForceFieldConfig::default(),
+			electrostatics: ElectrostaticsConfig::default(),
+			cutoff: None,
+			neighbors: None,
+			walls: None,
+			container: None,
+			field: None,
+			thermostat: Some(ThermostatConfig{ method: ThermostatMethod::Berendsen, target: 0.0, coupling: 5.0, start: 50.0, chain: None }),
+			barostat: None,
+			langevin: None,
+			minimize: None,
+			display: DisplayConfig::default(),
+			species: Vec::new(),
+			pairs: Vec::new(),
+			topology: TopologyConfig::default(),
+			bonds: Vec::new(),
+			angles: Vec::new(),
+			dihedrals: Vec::new(),
+			constraints: ConstraintsConfig::default(),
+			constraint_pairs: Vec::new(),
+			settles: Vec::new(),
+			generate: None,
+			velocities: None,
+			particles,
+		}
+	}
+}
+
+/// Returns an error with the given message if the condition does not hold.
+fn check(condition: bool, message: String) -> Result<(), ConfigError> {
+	if condition { Ok(()) } else { Err(ConfigError::Invalid(message)) }
+}
+
+/// The names of the parameters of the walls, in the order of WallsConfig::parameters() and ContainerConfig::parameters().
+const WALL_PARAMETERS: [&str; 5] = ["epsilon", "sigma", "cutoff", "k", "range"];
+
+/// Check that the parameters of a section are given for all of its walls, and only for those, and are in their valid range.
+fn check_walls(section: &str, walls: &[WallType], parameters: [Option<f64>; 5]) -> Result<(), ConfigError> {
+	let mut required = Vec::new();
+	for wall in walls {
+		required.extend_from_slice(match wall {
+			WallType::Lj93 => &["epsilon", "sigma", "cutoff"],
+			WallType::Harmonic => &["k", "range"],
+			_ => &[],
+		});
+	}
+	for (name, value) in WALL_PARAMETERS.into_iter().zip(parameters) {
+		match value {
+			Some(value) => {
+				check(required.contains(&name), format!("{}.{} is not used by its walls", section, name))?;
+				let positive = name == "sigma" || name == "cutoff" || name == "range";
+				check(value > 0.0 || (!positive && value == 0.0), format!("{}.{} must be {}, got {}", section, name, if positive { "positive" } else { "not negative" }, value))?;
+			}
+			None => check(!required.contains(&name), format!("{}.{} must be given for its walls", section, name))?,
+		}
+	}
+	Ok(())
+}
+
+impl WallsConfig {
+	/// The boundaries along each axis.
+	fn axes(&self) -> [WallType; 3] {
+		[self.x, self.y, self.z]
+	}
+
+	/// The parameters of the walls, in the order of WALL_PARAMETERS.
+	fn parameters(&self) -> [Option<f64>; 5] {
+		[self.epsilon, self.sigma, self.cutoff, self.k, self.range]
+	}
+}
+
+impl ContainerConfig {
+	/// The parameters of the wall, in the order of WALL_PARAMETERS.
+	fn parameters(&self) -> [Option<f64>; 5] {
+		[self.epsilon, self.sigma, self.cutoff, self.k, self.range]
+	}
+
+	/// The index of the axis of a cylinder.
+	fn axis_index(&self) -> Option<usize> {
+		self.axis.map(|axis| match axis {
+			AxisConfig::X => 0,
+			AxisConfig::Y => 1,
+			AxisConfig::Z => 2,
+		})
+	}
+}
+
+impl Config {
+	/// Read and validate a configuration file. The files it refers to are relative to the directory of the configuration file.
+	pub fn from_file(filename: &str) -> Result<Self, ConfigError> {
+		let contents = std::fs::read_to_string(filename)?;
+		Self::from_toml_in(&contents, Path::new(filename).parent().unwrap_or(Path::new("")))
+	}
+
+	/// Parse and validate a configuration from a TOML string, and convert it to the internal units.
+	/// The files it refers to are relative to the current directory.
+	pub fn from_toml(contents: &str) -> Result<Self, ConfigError> {
+		Self::from_toml_in(contents, Path::new(""))
+	}
+
+	/// Parse and validate a configuration from a TOML string, with the files it refers to relative to a directory, see from_toml().
+	pub fn from_toml_in(contents: &str, directory: &Path) -> Result<Self, ConfigError> {
+		let mut config: Config = toml::from_str(contents)?;
+		// The bonds and constraints may refer to the generated particles, so only the parts needed to generate them are checked first
+		config.check_species()?;
+		config.read_tables(directory)?;
+		config.generate_particles()?;
+		config.validate()?;
+		config.check_overlaps()?;
+		config.convert_units();
+		Ok(config)
+	}
+
+	/// The number of time steps of the simulation, rounded so adding up the time steps does not give one step too many or too few.
+	pub fn steps(&self) -> usize {
+		(self.simulation.length / self.simulation.time_step).round() as usize
+	}
+
+	/// The unit system of the configuration.
+	pub fn units(&self) -> Units {
+		match self.simulation.units {
+			UnitsConfig::Lj => Units::Lj,
+			UnitsConfig::Real => Units::Real,
+			UnitsConfig::Si => Units::Si,
+		}
+	}
+
+	/// Convert the values that differ between the unit system and the internal units, see units.
+	///
+	/// Lengths, masses, energies and charges are the same in both, so only times, temperatures and the fields are converted.
+	fn convert_units(&mut self) {
+		let units = self.units();
+		let convert = |value: &mut f64, dimension: Dimension| *value = units.to_internal(*value, dimension);
+		let convert_vector = |value: &mut [f64; 3], dimension: Dimension| value.iter_mut().for_each(|x| convert(x, dimension));
+
+		convert(&mut self.simulation.time_step, Dimension::Time);
+		convert(&mut self.simulation.length, Dimension::Time);
+		if let Some(t) = &mut self.thermostat {
+			convert(&mut t.target, Dimension::Temperature);
+			convert(&mut t.coupling, Dimension::Time);
+			convert(&mut t.start, Dimension::Time);
+		}
+		if let Some(b) = &mut self.barostat {
+			convert(&mut b.coupling, Dimension::Time);
+			convert(&mut b.start, Dimension::Time);
+			if let Some(temperature) = &mut b.temperature {
+				convert(temperature, Dimension::Temperature);
+			}
+		}
+		if let Some(l) = &mut self.langevin {
+			convert(&mut l.temperature, Dimension::Temperature);
+			convert(&mut l.friction, Dimension::Rate);
+		}
+		if let Some(time_step) = self.minimize.as_mut().and_then(|m| m.time_step.as_mut()) {
+			convert(time_step, Dimension::Time);
+		}
+		if let Some(v) = &mut self.velocities {
+			convert(&mut v.temperature, Dimension::Temperature);
+		}
+		if let Some(f) = &mut self.field {
+			convert_vector(&mut f.electric, Dimension::ElectricField);
+			convert_vector(&mut f.amplitude, Dimension::ElectricField);
+			convert(&mut f.frequency, Dimension::Rate);
+			convert_vector(&mut f.gravity, Dimension::Accelleration);
+			convert_vector(&mut f.magnetic, Dimension::MagneticField);
+		}
+		for p in self.particles.iter_mut() {
+			convert_vector(&mut p.velocity, Dimension::Velocity);
+			if let Some(friction) = &mut p.friction {
+				convert(friction, Dimension::Rate);
+			}
+		}
+	}
+
+	/// The elementary charge in the internal units.
+	fn elementary_charge(&self) -> f64 {
+		self.forcefield.elementary_charge.unwrap_or(self.units().elementary_charge())
+	}
+
+	/// Check that all values are within their valid range.
+	pub fn validate(&self) -> Result<(), ConfigError> {
+		let s = &self.simulation;
+		check(s.time_step > 0.0, format!("simulation.time_step must be positive, got {}", s.time_step))?;
+		check(s.length >= 0.0, format!("simulation.length must not be negative, got {}", s.length))?;
+		let langevin = s.integrator == IntegratorMethod::Langevin;
+		match &self.langevin {
+			Some(l) => {
+				check(langevin, String::from("[langevin] is only used by the langevin integrator"))?;
+				check(l.temperature >= 0.0, format!("langevin.temperature must not be negative, got {}", l.temperature))?;
+				check(l.friction >= 0.0, format!("langevin.friction must not be negative, got {}", l.friction))?;
+			}
+			None => check(!langevin, String::from("the langevin integrator needs a [langevin] section"))?,
+		}
+
+		let f = &self.forcefield;
+		check(f.lj_4_epsilon >= 0.0, format!("forcefield.lj_4_epsilon must not be negative, got {}", f.lj_4_epsilon))?;
+		check(f.elementary_charge.is_none_or(|e| e > 0.0), format!("forcefield.elementary_charge must be positive, got {}", f.elementary_charge.unwrap_or(0.0)))?;
+
+		self.check_species()?;
+
+		let b = &self.simbox;
+		check(!self.particles.is_empty() || self.generate.is_some(), String::from("at least one [[particle]] or [generate] must be given"))?;
+		for (i, p) in self.particles.iter().enumerate() {
+			check(self.velocities.is_none() || p.velocity == [0.0; 3], format!("particle {}: velocity is set by [velocities]", i))?;
+			if let Some(friction) = p.friction {
+				check(langevin, format!("particle {}: friction is only used by the langevin integrator", i))?;
+				check(friction >= 0.0, format!("particle {}: friction must not be negative, got {}", i, friction))?;
+			}
+			check(p.position[0] >= 0.0 && p.position[0] < b.x &&
+			      p.position[1] >= 0.0 && p.position[1] < b.y &&
+			      p.position[2] >= 0.0 && p.position[2] < b.z,
+			      format!("particle {}: position {:?} is outside of the box", i, p.position))?;
+		}
+
+		let periodic = self.periodic();
+		if let Some(v) = &self.velocities {
+			check(v.temperature >= 0.0, format!("velocities.temperature must not be negative, got {}", v.temperature))?;
+			check(!v.remove_rotation || periodic.iter().all(|&p| !p), String::from("velocities.remove_rotation needs a box without periodic axes"))?;
+		}
+		if let Some(container) = self.container() {
+			for (i, p) in self.particles.iter().enumerate() {
+				let distance = container.distance(&Vector::new(p.position[0], p.position[1], p.position[2])).0;
+				check(distance > 0.0, format!("particle {}: position {:?} is outside of the container", i, p.position))?;
+			}
+		}
+		// The shortest periodic axis, which limits the cutoffs
+		let half = [b.x, b.y, b.z].into_iter().zip(periodic).filter(|&(_, p)| p).map(|(size, _)| size / 2.0).fold(f64::INFINITY, f64::min);
+
+		if let Some(f) = &self.field {
+			check(f.frequency >= 0.0, format!("field.frequency must not be negative, got {}", f.frequency))?;
+			check(f.magnetic == [0.0; 3] || s.integrator == IntegratorMethod::Boris, String::from("field.magnetic needs the boris integrator"))?;
+		}
+
+		let t = &self.topology;
+		check(t.exclusions <= 3, format!("topology.exclusions must be at most 3, got {}", t.exclusions))?;
+		check(t.scale_14_vdw >= 0.0, format!("topology.scale_14_vdw must not be negative, got {}", t.scale_14_vdw))?;
+		check(t.scale_14_electric >= 0.0, format!("topology.scale_14_electric must not be negative, got {}", t.scale_14_electric))?;
+		let n = self.particles.len();
+		let distinct = |particles: &[usize]| particles.iter().enumerate().all(|(k, a)| particles[..k].iter().all(|b| a != b));
+		for (i, bond) in self.bonds.iter().enumerate() {
+			check(bond.particles.iter().all(|&p| p < n) && distinct(&bond.particles), format!("bond {}: particles {:?} must be distinct indices of particles", i, bond.particles))?;
+			check(bond.k >= 0.0, format!("bond {}: k must not be negative, got {}", i, bond.k))?;
+			match bond.potential {
+				BondType::Harmonic => {
+					check(bond.length.is_some_and(|length| length > 0.0), format!("bond {}: length must be given and positive", i))?;
+					check(bond.max_length.is_none(), format!("bond {}: max_length is only used by the fene potential", i))?;
+				}
+				BondType::Fene => {
+					let max_length = bond.max_length.unwrap_or(0.0);
+					check(max_length > 0.0, format!("bond {}: max_length must be given and positive", i))?;
+					check(bond.length.is_none(), format!("bond {}: length is only used by the harmonic potential", i))?;
+					let [a, b] = bond.particles.map(|p| self.particles[p].position);
+					let distance = self.simbox().separation(&Vector::new(a[0], a[1], a[2]), &Vector::new(b[0], b[1], b[2])).len();
+					check(distance < max_length, format!("bond {}: the particles are further apart than max_length", i))?;
+				}
+			}
+		}
+		for (i, angle) in self.angles.iter().enumerate() {
+			check(angle.particles.iter().all(|&p| p < n) && distinct(&angle.particles), format!("angle {}: particles {:?} must be distinct indices of particles", i, angle.particles))?;
+			check(angle.k >= 0.0, format!("angle {}: k must not be negative, got {}", i, angle.k))?;
+			check(angle.angle >= 0.0 && angle.angle <= 180.0, format!("angle {}: angle must be between 0 and 180 degrees, got {}", i, angle.angle))?;
+		}
+		for (i, dihedral) in self.dihedrals.iter().enumerate() {
+			check(dihedral.particles.iter().all(|&p| p < n) && distinct(&dihedral.particles), format!("dihedral {}: particles {:?} must be distinct indices of particles", i, dihedral.particles))?;
+			match dihedral.potential {
+				DihedralType::Periodic => {
+					check(dihedral.k.is_some() && dihedral.multiplicity.is_some(), format!("dihedral {}: k and multiplicity must be given", i))?;
+					check(dihedral.coefficients.is_none(), format!("dihedral {}: coefficients are only used by the ryckaert_bellemans potential", i))?;
+				}
+				DihedralType::RyckaertBellemans => {
+					check(dihedral.coefficients.is_some(), format!("dihedral {}: coefficients must be given", i))?;
+					check(dihedral.k.is_none() && dihedral.multiplicity.is_none() && dihedral.phase.is_none(), format!("dihedral {}: k, multiplicity and phase are only used by the periodic potential", i))?;
+				}
+			}
+		}
+
+		let c = &self.constraints;
+		check(c.tolerance > 0.0, format!("constraints.tolerance must be positive, got {}", c.tolerance))?;
+		check(c.max_iterations > 0, String::from("constraints.max_iterations must be at least 1"))?;
+		for (i, constraint) in self.constraint_pairs.iter().enumerate() {
+			check(constraint.particles.iter().all(|&p| p < n) && distinct(&constraint.particles), format!("constraint {}: particles {:?} must be distinct indices of particles", i, constraint.particles))?;
+			check(constraint.length > 0.0, format!("constraint {}: length must be positive, got {}", i, constraint.length))?;
+		}
+		let masses: Vec<f64> = if self.settles.is_empty() { Vec::new() } else { self.particles().iter().map(|p| p.m).collect() };
+		for (i, settle) in self.settles.iter().enumerate() {
+			check(settle.particles.iter().all(|&p| p < n) && distinct(&settle.particles), format!("settle {}: particles {:?} must be distinct indices of particles", i, settle.particles))?;
+			check(settle.oh > 0.0 && settle.hh > 0.0 && settle.hh < 2.0 * settle.oh, format!("settle {}: oh and hh must be positive, with hh less than twice oh", i))?;
+			check(masses[settle.particles[1]] == masses[settle.particles[2]], format!("settle {}: the hydrogens must have the same mass", i))?;
+			let constrained = self.constrained_pairs().into_iter().flat_map(|c| c.particles)
+				.chain(self.settles.iter().enumerate().filter(|&(j, _)| j != i).flat_map(|(_, s)| s.particles));
+			check(constrained.into_iter().all(|p| !settle.particles.contains(&p)), format!("settle {}: the particles have other constraints", i))?;
+		}
+		if self.constraints().is_some() {
+			check(s.integrator == IntegratorMethod::Verlet || langevin, String::from("constraints need the verlet or langevin integrator"))?;
+			check(self.thermostat.as_ref().is_none_or(|t| t.method != ThermostatMethod::Andersen), String::from("the andersen thermostat does not keep the constraints"))?;
+			check(self.minimize.is_none(), String::from("the energy minimization does not keep the constraints"))?;
+		}
+
+		if let Some(c) = &self.cutoff {
+			check(c.radius > 0.0, format!("cutoff.radius must be positive, got {}", c.radius))?;
+			check(c.radius <= half, format!("cutoff.radius must be at most half the box size ({}) along the periodic axes", half))?;
+			match (c.scheme, c.switch_start) {
+				(CutoffSchemeConfig::Switched, Some(start)) => check(start > 0.0 && start < c.radius, format!("cutoff.switch_start must be between 0 and cutoff.radius, got {}", start))?,
+				(CutoffSchemeConfig::Switched, None) => check(false, String::from("cutoff.switch_start must be given for the switched scheme"))?,
+				(_, Some(_)) => check(false, String::from("cutoff.switch_start is only used by the switched scheme"))?,
+				(_, None) => {}
+			}
+		}
+
+		if let Some(n) = &self.neighbors {
+			check(n.cutoff > 0.0, format!("neighbors.cutoff must be positive, got {}", n.cutoff))?;
+			check(n.skin >= 0.0, format!("neighbors.skin must not be negative, got {}", n.skin))?;
+			check(n.cutoff + n.skin <= half, format!("neighbors.cutoff + neighbors.skin must be at most half the box size ({}) along the periodic axes", half))?;
+			if let Some(c) = &self.cutoff {
+				check(n.cutoff >= c.radius, format!("neighbors.cutoff must be at least cutoff.radius ({}), got {}", c.radius, n.cutoff))?;
+			}
+		}
+
+		let e = &self.electrostatics;
+		match e.method {
+			ElectrostaticsMethod::Ewald | ElectrostaticsMethod::Pme => {
+				check(periodic.iter().all(|&p| p), String::from("electrostatics.method = \"ewald\" or \"pme\" needs a box that is periodic along all axes"))?;
+				check(e.accuracy > 0.0 && e.accuracy < 1.0, format!("electrostatics.accuracy must be between 0 and 1, got {}", e.accuracy))?;
+				let half = b.x.min(b.y).min(b.z) / 2.0;
+				let cutoff = self.ewald_cutoff();
+				check(cutoff > 0.0 && cutoff <= half, format!("electrostatics.cutoff must be positive and at most half the box size ({}), got {}", half, cutoff))?;
+				if let Some(n) = &self.neighbors {
+					check(n.cutoff >= cutoff, format!("neighbors.cutoff must be at least electrostatics.cutoff ({}), got {}", cutoff, n.cutoff))?;
+				}
+			}
+			ElectrostaticsMethod::Coulomb => {
+				let charged = self.particles().iter().any(|p| p.q != 0.0);
+				check(!periodic.iter().any(|&p| p) || !charged,
+				      String::from("electrostatics.method = \"coulomb\" does not converge in a periodic box with charged particles, use \"ewald\" or \"pme\""))?;
+				// The neighbor list would silently truncate the Coulomb interactions at its cutoff, making the energy jump whenever a pair crosses it
+				check(self.neighbors.is_none() || !charged,
+				      String::from("electrostatics.method = \"coulomb\" cannot use [neighbors] with charged particles, as it would drop the pairs beyond neighbors.cutoff"))?;
+			}
+			ElectrostaticsMethod::None => {}
+		}
+		let ewald = e.method == ElectrostaticsMethod::Ewald || e.method == ElectrostaticsMethod::Pme;
+		check(e.cutoff.is_none() || ewald, String::from("electrostatics.cutoff is only used by the ewald and pme methods"))?;
+		match e.order {
+			Some(order) if e.method == ElectrostaticsMethod::Pme => check(order >= 3, format!("electrostatics.order must be at least 3, got {}", order))?,
+			Some(_) => check(false, String::from("electrostatics.order is only used by the pme method"))?,
+			None => {}
+		}
+
+		if let Some(t) = &self.thermostat {
+			check(t.target >= 0.0, format!("thermostat.target must not be negative, got {}", t.target))?;
+			check(t.coupling >= s.time_step, format!("thermostat.coupling must be at least the time step, got {}", t.coupling))?;
+			match (t.method, t.chain) {
+				(ThermostatMethod::NoseHoover, Some(chain)) => check(chain > 0, String::from("thermostat.chain must be at least 1"))?,
+				(_, Some(_)) => check(false, String::from("thermostat.chain is only used by the nose_hoover method"))?,
+				(_, None) => {}
+			}
+		}
+
+		if let Some(p) = &self.barostat {
+			check(periodic.iter().all(|&p| p) && self.container.is_none(), String::from("[barostat] needs a box that is periodic along all axes, without a container"))?;
+			check(p.coupling >= s.time_step, format!("barostat.coupling must be at least the time step, got {}", p.coupling))?;
+			match (p.method, p.compressibility) {
+				(BarostatMethod::Berendsen, Some(compressibility)) => check(compressibility > 0.0, format!("barostat.compressibility must be positive, got {}", compressibility))?,
+				(_, Some(_)) => check(false, String::from("barostat.compressibility is only used by the berendsen method"))?,
+				(_, None) => {}
+			}
+			match (p.method, self.barostat_temperature()) {
+				(BarostatMethod::Mtk, Some(temperature)) => check(temperature > 0.0, format!("the temperature of the mtk barostat must be positive, got {}", temperature))?,
+				(BarostatMethod::Mtk, None) => check(false, String::from("the mtk barostat needs barostat.temperature, a [thermostat], or a [langevin] section"))?,
+				(_, _) => check(p.temperature.is_none(), String::from("barostat.temperature is only used by the mtk method"))?,
+			}
+		}
+
+		if let Some(m) = &self.minimize {
+			check(m.max_force >= 0.0, format!("minimize.max_force must not be negative, got {}", m.max_force))?;
+			check(m.energy_change >= 0.0, format!("minimize.energy_change must not be negative, got {}", m.energy_change))?;
+			check(m.step > 0.0, format!("minimize.step must be positive, got {}", m.step))?;
+			if let Some(time_step) = m.time_step {
+				check(m.method == MinimizeMethod::Fire, String::from("minimize.time_step is only used by the fire method"))?;
+				check(time_step > 0.0, format!("minimize.time_step must be positive, got {}", time_step))?;
+			}
+		}
+
+		let d = &self.display;
+		check(d.width > 0 && d.height > 0, format!("display.width and display.height must be positive, got {}x{}", d.width, d.height))?;
+		check(d.frame_rate > 0.0, format!("display.frame_rate must be positive, got {}", d.frame_rate))?;
+
+		Ok(())
+	}
+
+	/// Check that no particles overlap, as the pair potentials are not valid closer than their minimum distance,
+	/// such as the sigma of the Lennard-Jones potential, unless the pair is excluded.
+	///
+	/// Only the pairs within the largest minimum distance are compared, using a neighbor list.
+	fn check_overlaps(&self) -> Result<(), ConfigError> {
+		let particles = self.particles();
+		let simbox = self.simbox();
+		let species = self.species();
+		let topology = self.topology();
+		let count = species.species().len();
+		let largest = (0..count).flat_map(|a| (0..count).map(move |b| (a, b)))
+			.map(|(a, b)| species.get_potential(a, b).min_distance())
+			.fold(0.0, f64::max);
+		if largest <= 0.0 {
+			return Ok(());
+		}
+		// A small skin, so pairs at exactly the minimum distance are in the list
+		let mut neighbors = NeighborList::new(largest, 1e-6 * largest);
+		neighbors.update(&simbox, &particles);
+		for &(i, j) in neighbors.pairs() {
+			if topology.scaling(i, j).0 == 0.0 {
+				continue;
+			}
+			let min_distance = species.get_potential(particles[i].species, particles[j].species).min_distance();
+			let distance = simbox.separation(&particles[i].pos, &particles[j].pos).len();
+			check(distance > min_distance, format!("particles {} and {} overlap", i, j))?;
+		}
+		Ok(())
+	}
+
+	/// Check the box, walls and container, the species, the pair potentials between them, the species of the given particles, and the particles to generate,
+	/// which are all needed to generate the particles.
+	fn check_species(&self) -> Result<(), ConfigError> {
+		let b = &self.simbox;
+		for (name, size) in [("x", b.x), ("y", b.y), ("z", b.z)] {
+			check(size > 0.0, format!("box.{} must be positive, got {}", name, size))?;
+		}
+		check(b.border_4_epsilon >= 0.0, format!("box.border_4_epsilon must not be negative, got {}", b.border_4_epsilon))?;
+		check(b.border_range > 0.0, format!("box.border_range must be positive, got {}", b.border_range))?;
+		if let Some(w) = &self.walls {
+			check(!b.periodic, String::from("box.periodic cannot be combined with [walls], make the axes \"periodic\" instead"))?;
+			check_walls("walls", &w.axes(), w.parameters())?;
+		}
+		if let Some(c) = &self.container {
+			check(c.radius > 0.0, format!("container.radius must be positive, got {}", c.radius))?;
+			check(c.wall != WallType::Periodic && c.wall != WallType::None, String::from("container.wall must be \"repulsive\", \"lj93\", \"harmonic\", or \"reflective\""))?;
+			check_walls("container", &[c.wall], c.parameters())?;
+			match c.shape {
+				ShapeConfig::Sphere => check(c.axis.is_none(), String::from("container.axis is only used by the cylinder shape"))?,
+				ShapeConfig::Cylinder => check(c.axis.is_some(), String::from("container.axis must be given for the cylinder shape"))?,
+			}
+			let container = self.container().expect("The container has been given");
+			let size = [b.x, b.y, b.z];
+			let centre = [container.centre.x, container.centre.y, container.centre.z];
+			for k in (0..3).filter(|&k| Some(k) != c.axis_index()) {
+				check(centre[k] - c.radius >= 0.0 && centre[k] + c.radius <= size[k], String::from("the container must fit inside the box"))?;
+			}
+		}
+
+		for (i, t) in self.species.iter().enumerate() {
+			check(!t.name.is_empty(), format!("species {}: name must not be empty", i))?;
+			check(self.species[..i].iter().all(|other| other.name != t.name), format!("species {}: name \"{}\" is already used", i, t.name))?;
+			check(t.sigma > 0.0, format!("species {}: sigma must be positive, got {}", t.name, t.sigma))?;
+			check(t.epsilon >= 0.0, format!("species {}: epsilon must not be negative, got {}", t.name, t.epsilon))?;
+			check(t.mass > 0.0, format!("species {}: mass must be positive, got {}", t.name, t.mass))?;
+		}
+		for p in self.pairs.iter() {
+			for name in p.species.iter() {
+				check(self.species.iter().any(|t| &t.name == name), format!("pair {:?}: unknown species \"{}\"", p.species, name))?;
+			}
+			let (required, optional): (&[&str], &[&str]) = match p.potential {
+				PotentialType::LennardJones => (&["sigma", "epsilon"], &[]),
+				PotentialType::Wca => (&[], &["sigma", "epsilon"]),
+				PotentialType::Buckingham => (&["a", "rho", "c"], &[]),
+				PotentialType::Morse => (&["depth", "width", "length"], &[]),
+				PotentialType::Yukawa => (&["strength", "kappa"], &[]),
+				PotentialType::SoftSphere => (&["exponent"], &["sigma", "epsilon"]),
+				PotentialType::Tabulated => (&[], &["tolerance"]),
+			};
+			let parameters = [("sigma", p.sigma), ("epsilon", p.epsilon), ("a", p.a), ("rho", p.rho), ("c", p.c), ("depth", p.depth),
+				("width", p.width), ("length", p.length), ("strength", p.strength), ("kappa", p.kappa), ("exponent", p.exponent), ("tolerance", p.tolerance)];
+			for (name, value) in parameters {
+				match value {
+					Some(_) => check(required.contains(&name) || optional.contains(&name), format!("pair {:?}: {} is not used by its potential", p.species, name))?,
+					None => check(!required.contains(&name), format!("pair {:?}: {} must be given for its potential", p.species, name))?,
+				}
+			}
+			for (name, value) in [("sigma", p.sigma), ("rho", p.rho), ("width", p.width), ("length", p.length), ("kappa", p.kappa), ("exponent", p.exponent), ("tolerance", p.tolerance)] {
+				check(value.is_none_or(|x| x > 0.0), format!("pair {:?}: {} must be positive, got {}", p.species, name, value.unwrap_or(0.0)))?;
+			}
+			for (name, value) in [("epsilon", p.epsilon), ("a", p.a), ("c", p.c), ("depth", p.depth)] {
+				check(value.is_none_or(|x| x >= 0.0), format!("pair {:?}: {} must not be negative, got {}", p.species, name, value.unwrap_or(0.0)))?;
+			}
+			if self.cutoff.as_ref().is_some_and(|c| c.tail_correction) {
+				check(p.exponent.is_none_or(|n| n > 3.0), format!("pair {:?}: the tail correction needs an exponent larger than 3", p.species))?;
+			}
+			match (&p.table, p.potential) {
+				(None, PotentialType::Tabulated) => check(false, format!("pair {:?}: table must be given for its potential", p.species))?,
+				(Some(_), PotentialType::Tabulated) | (None, _) => {}
+				(Some(_), _) => check(false, format!("pair {:?}: table is not used by its potential", p.species))?,
+			}
+		}
+
+		for (i, p) in self.particles.iter().enumerate() {
+			match &p.species {
+				Some(name) => {
+					check(self.species.iter().any(|t| &t.name == name), format!("particle {}: unknown species \"{}\"", i, name))?;
+					check(p.radius.is_none() && p.mass.is_none() && p.charge.is_none(), format!("particle {}: radius, mass and charge are set by its species", i))?;
+				}
+				None => {
+					let (radius, mass) = (p.radius.unwrap_or(0.0), p.mass.unwrap_or(0.0));
+					check(radius > 0.0, format!("particle {}: radius must be given and positive, got {}", i, radius))?;
+					check(mass > 0.0, format!("particle {}: mass must be given and positive, got {}", i, mass))?;
+				}
+			}
+		}
+
+		if let Some(g) = &self.generate {
+			check(!g.species.is_empty(), String::from("generate.species must not be empty"))?;
+			for name in g.species.iter() {
+				check(self.species.iter().any(|t| &t.name == name), format!("generate: unknown species \"{}\"", name))?;
+			}
+			if let Some(fractions) = &g.fractions {
+				check(fractions.len() == g.species.len(), format!("generate.fractions must have one fraction per species, got {} for {}", fractions.len(), g.species.len()))?;
+				check(fractions.iter().all(|&x| x >= 0.0) && fractions.iter().any(|&x| x > 0.0), String::from("generate.fractions must not be negative, and not all zero"))?;
+			}
+			let random = g.method == GenerateMethod::Random;
+			check(g.density.is_some() != random, format!("generate.density must {}", if random { "not be given for random insertion" } else { "be given for a lattice" }))?;
+			check(g.density.is_none_or(|x| x > 0.0), format!("generate.density must be positive, got {}", g.density.unwrap_or(0.0)))?;
+			check(g.count.is_some() == random, format!("generate.count must {}", if random { "be given for random insertion" } else { "not be given for a lattice" }))?;
+			check(random || (g.min_distance.is_none() && g.attempts.is_none()), String::from("generate.min_distance and generate.attempts are only used by random insertion"))?;
+			check(g.min_distance.is_none_or(|x| x >= 0.0), format!("generate.min_distance must not be negative, got {}", g.min_distance.unwrap_or(0.0)))?;
+			check(g.attempts.is_none_or(|n| n > 0), String::from("generate.attempts must be at least 1"))?;
+		}
+		Ok(())
+	}
+
+	/// Read and check the table of each tabulated pair potential, relative to a directory, keeping them for species().
+	fn read_tables(&mut self, directory: &Path) -> Result<(), ConfigError> {
+		for p in self.pairs.iter_mut().filter(|p| p.potential == PotentialType::Tabulated) {
+			let table = p.table.as_ref().expect("The configuration has not been validated");
+			let tabulated = Tabulated::from_file(&directory.join(table), p.tolerance.unwrap_or(TABLE_TOLERANCE))
+				.map_err(|e| ConfigError::Invalid(format!("pair {:?}: {}", p.species, e)))?;
+			p.tabulated = Some(Rc::new(tabulated));
+		}
+		Ok(())
+	}
+
+	/// The name of the species of a particle without a species, shared by all such particles with the same radius, mass and charge.
+	fn anonymous_species(c: &ParticleConfig) -> String {
+		format!("radius {} mass {} charge {}", c.radius.unwrap_or(0.0), c.mass.unwrap_or(0.0), c.charge.unwrap_or(0.0))
+	}
+
+	/// Create the table of species, including a species for each combination of radius, mass and charge of the particles without a species.
+	pub fn species(&self) -> SpeciesTable {
+		let mut table = SpeciesTable::new(match self.forcefield.mixing {
+			MixingConfig::LorentzBerthelot => MixingRule::LorentzBerthelot,
+			MixingConfig::Geometric => MixingRule::Geometric,
+		});
+		for t in self.species.iter() {
+			table.add(Species{ name: t.name.clone(), sigma: t.sigma, epsilon: t.epsilon, mass: t.mass, charge: t.charge });
+		}
+		for p in self.pairs.iter() {
+			let [a, b] = [0, 1].map(|k| table.find(&p.species[k]).expect("The configuration has not been validated"));
+			let mixed = table.get(a, b);
+			let (sigma, epsilon_4) = (p.sigma.unwrap_or(mixed.sigma), p.epsilon.map_or(mixed.epsilon_4, |epsilon| 4.0 * epsilon));
+			let parameter = |value: Option<f64>| value.expect("The configuration has not been validated");
+			let potential: Rc<dyn PairPotential> = match p.potential {
+				PotentialType::LennardJones => {
+					table.set_pair(a, b, PairParameters{ sigma, epsilon_4 });
+					continue;
+				}
+				PotentialType::Wca => Rc::new(Wca{ epsilon_4, sigma }),
+				PotentialType::Buckingham => Rc::new(Buckingham{ a: parameter(p.a), rho: parameter(p.rho), c: parameter(p.c) }),
+				PotentialType::Morse => Rc::new(Morse{ depth: parameter(p.depth), width: parameter(p.width), length: parameter(p.length) }),
+				PotentialType::Yukawa => Rc::new(Yukawa{ strength: parameter(p.strength), kappa: parameter(p.kappa) }),
+				PotentialType::SoftSphere => Rc::new(SoftSphere{ epsilon: epsilon_4 / 4.0, sigma, exponent: parameter(p.exponent) }),
+				PotentialType::Tabulated => p.tabulated.clone().expect("The configuration has not been validated"),
+			};
+			table.set_potential(a, b, potential);
+		}
+
+		for c in self.particles.iter().filter(|c| c.species.is_none()) {
+			let name = Self::anonymous_species(c);
+			if table.find(&name).is_none() {
+				table.add(Species{
+					name,
+					sigma: c.radius.unwrap_or(0.0),
+					epsilon: self.forcefield.lj_4_epsilon / 4.0,
+					mass: c.mass.unwrap_or(0.0),
+					charge: c.charge.unwrap_or(0.0),
+				});
+			}
+		}
+		table
+	}
+
+	/// Create the initial particles.
+	pub fn particles(&self) -> Vec<Particle> {
+		let table = self.species();
+		self.particles.iter().map(|c| {
+			let name = c.species.clone().unwrap_or_else(|| Self::anonymous_species(c));
+			let species = table.find(&name).expect("The configuration has not been validated");
+			let mut p = table.particle(species, &Vector::new(c.position[0], c.position[1], c.position[2]));
+			p.v = Vector::new(c.velocity[0], c.velocity[1], c.velocity[2]);
+			p
+		}).collect()
+	}
+
+	/// Create the topology, with all bonded terms.
+	pub fn topology(&self) -> Topology {
+		let mut topology = Topology::new();
+		topology.exclusions = self.topology.exclusions;
+		topology.scale_14_vdw = self.topology.scale_14_vdw;
+		topology.scale_14_electric = self.topology.scale_14_electric;
+		for b in self.bonds.iter() {
+			if self.constraints.bonds && b.potential == BondType::Harmonic {
+				topology.connect(b.particles[0], b.particles[1]);
+				continue;
+			}
+			let potential = match b.potential {
+				BondType::Harmonic => BondPotential::Harmonic{ k: b.k, length: b.length.unwrap_or(0.0) },
+				BondType::Fene => BondPotential::Fene{ k: b.k, max_length: b.max_length.unwrap_or(0.0) },
+			};
+			topology.add_bond(Bond{ particles: b.particles, potential });
+		}
+		for c in self.constraint_pairs.iter() {
+			topology.connect(c.particles[0], c.particles[1]);
+		}
+		for s in self.settles.iter() {
+			topology.connect(s.particles[0], s.particles[1]);
+			topology.connect(s.particles[0], s.particles[2]);
+		}
+		for a in self.angles.iter() {
+			topology.add_angle(Angle{ particles: a.particles, k: a.k, angle: a.angle.to_radians() });
+		}
+		for d in self.dihedrals.iter() {
+			let potential = match d.potential {
+				DihedralType::Periodic => DihedralPotential::Periodic{
+					k: d.k.unwrap_or(0.0),
+					multiplicity: d.multiplicity.unwrap_or(0.0),
+					phase: d.phase.unwrap_or(0.0).to_radians(),
+				},
+				DihedralType::RyckaertBellemans => DihedralPotential::RyckaertBellemans{ coefficients: d.coefficients.unwrap_or([0.0; 6]) },
+			};
+			topology.add_dihedral(Dihedral{ particles: d.particles, potential });
+		}
+		topology
+	}
+
+	/// The constraints between pairs of particles, including the constrained bonds.
+	fn constrained_pairs(&self) -> Vec<Constraint> {
+		let bonds = self.bonds.iter()
+			.filter(|b| self.constraints.bonds && b.potential == BondType::Harmonic)
+			.map(|b| Constraint{ particles: b.particles, length: b.length.unwrap_or(0.0) });
+		self.constraint_pairs.iter().map(|c| Constraint{ particles: c.particles, length: c.length }).chain(bonds).collect()
+	}
+
+	/// Create the constraints, if any.
+	pub fn constraints(&self) -> Option<Constraints> {
+		let mut constraints = Constraints::new(self.constraints.tolerance, self.constraints.max_iterations);
+		constraints.pairs = self.constrained_pairs();
+		constraints.settles = self.settles.iter().map(|s| Settle{ particles: s.particles, oh: s.oh, hh: s.hh }).collect();
+		if constraints.count() == 0 { None } else { Some(constraints) }
+	}
+
+	/// Add the particles of [generate] after the given particles, with the seed of the simulation.
+	///
+	/// Lattices may overlap the given particles, which the validation catches, while random insertion avoids them, and fails when the box is too full.
+	fn generate_particles(&mut self) -> Result<(), ConfigError> {
+		let Some(g) = &self.generate else {
+			return Ok(());
+		};
+		let table = self.species();
+		let fractions: Vec<(usize, f64)> = g.species.iter().enumerate()
+			.map(|(i, name)| (table.find(name).expect("The configuration has not been validated"), g.fractions.as_ref().map_or(1.0, |x| x[i])))
+			.collect();
+		let simbox = self.simbox();
+		let container = self.container();
+		let mut random = Random::new(self.simulation.seed.wrapping_add(2));
+		let lattice = match g.method {
+			GenerateMethod::Sc => Some(Lattice::SimpleCubic),
+			GenerateMethod::Bcc => Some(Lattice::BodyCentredCubic),
+			GenerateMethod::Fcc => Some(Lattice::FaceCentredCubic),
+			GenerateMethod::Hcp => Some(Lattice::HexagonalClosePacked),
+			GenerateMethod::Random => None,
+		};
+		let particles = match lattice {
+			Some(lattice) => {
+				let density = g.density.unwrap_or(0.0);
+				let sites = lattice.sites(&simbox, density, generators::largest_radius(&fractions, &table));
+				check(sites <= MAX_LATTICE_SITES as f64, format!("generate: the lattice would have {} sites, more than {}, check generate.density", sites, MAX_LATTICE_SITES))?;
+				generators::fill_lattice(lattice, &simbox, container.as_ref(), density, &fractions, &table, &mut random)
+			}
+			None => {
+				let species = generators::mixture(&fractions, g.count.unwrap_or(0), &mut random);
+				generators::insert_random(&species, &self.particles(), &simbox, container.as_ref(), g.min_distance.unwrap_or(0.0), g.attempts.unwrap_or(INSERTION_ATTEMPTS), &table, &mut random)
+					.map_err(|e| ConfigError::Invalid(format!("generate: {}", e)))?
+			}
+		};
+		for p in particles {
+			self.particles.push(ParticleConfig{
+				position: [p.pos.x, p.pos.y, p.pos.z],
+				species: Some(table.species()[p.species].name.clone()),
+				radius: None,
+				mass: None,
+				charge: None,
+				velocity: [0.0; 3],
+				friction: None,
+			});
+		}
+		Ok(())
+	}
+
+	/// The number of degrees of freedom removed from the initial velocities with the momentum, and the angular momentum if asked.
+	fn removed_momentum(&self) -> usize {
+		self.velocities.as_ref().map_or(0, |v| if v.remove_rotation { 6 } else { 3 })
+	}
+
+	/// The number of degrees of freedom removed by the constraints and from the initial velocities, which do not count towards the temperature.
+	fn removed_degrees_of_freedom(&self) -> usize {
+		self.constraints().map_or(0, |c| c.count()) + self.removed_momentum()
+	}
+
+	/// Whether the box is periodic along each axis.
+	fn periodic(&self) -> [bool; 3] {
+		match &self.walls {
+			Some(w) => w.axes().map(|wall| wall == WallType::Periodic),
+			None => [self.simbox.periodic; 3],
+		}
+	}
+
+	/// Create the box containing the system.
+	pub fn simbox(&self) -> SimBox {
+		SimBox::with_axes(Vector::new(self.simbox.x, self.simbox.y, self.simbox.z), self.periodic())
+	}
+
+	/// Create the potential of a wall with the given parameters, None for periodic and open boundaries.
+	fn wall(&self, wall: WallType, parameters: [Option<f64>; 5]) -> Option<WallPotential> {
+		let [epsilon, sigma, cutoff, k, range] = parameters.map(|p| p.unwrap_or(0.0));
+		match wall {
+			WallType::Periodic | WallType::None => None,
+			WallType::Repulsive => Some(WallPotential::Repulsive{ epsilon_4: self.simbox.border_4_epsilon, range: self.simbox.border_range }),
+			WallType::Lj93 => Some(WallPotential::Lj93{ epsilon, sigma, cutoff }),
+			WallType::Harmonic => Some(WallPotential::Harmonic{ k, range }),
+			WallType::Reflective => Some(WallPotential::Reflective),
+		}
+	}
+
+	/// Create the container, if any.
+	fn container(&self) -> Option<Container> {
+		let c = self.container.as_ref()?;
+		let centre = c.centre.unwrap_or([self.simbox.x / 2.0, self.simbox.y / 2.0, self.simbox.z / 2.0]);
+		Some(Container{
+			shape: match c.shape {
+				ShapeConfig::Sphere => Shape::Sphere,
+				ShapeConfig::Cylinder => Shape::Cylinder{ axis: c.axis_index().unwrap_or(2) },
+			},
+			centre: Vector::new(centre[0], centre[1], centre[2]),
+			radius: c.radius,
+			wall: self.wall(c.wall, c.parameters()).unwrap_or(WallPotential::Reflective),
+		})
+	}
+
+	/// Create the walls and the container, None if all axes are periodic or open and there is no container.
+	pub fn borders(&self) -> Option<Borders> {
+		let walls = match &self.walls {
+			Some(w) => w.axes().map(|wall| self.wall(wall, w.parameters())),
+			None => [if self.simbox.periodic { WallType::Periodic } else { WallType::Repulsive }; 3].map(|wall| self.wall(wall, [None; 5])),
+		};
+		let container = self.container();
+		if walls.iter().all(|w| w.is_none()) && container.is_none() {
+			return None;
+		}
+		Some(Borders{ walls, container })
+	}
+
+	/// Create the forcefield.
+	pub fn forcefield(&self) -> ForceField {
+		let mut forcefield = ForceField::new();
+		forcefield.simbox = self.simbox();
+		forcefield.borders = self.borders();
+		forcefield.cutoff = self.cutoff.as_ref().map(|c| Cutoff{
+			radius: c.radius,
+			scheme: match c.scheme {
+				CutoffSchemeConfig::Truncated => CutoffScheme::Truncated,
+				CutoffSchemeConfig::Shifted => CutoffScheme::EnergyShifted,
+				CutoffSchemeConfig::ForceShifted => CutoffScheme::ForceShifted,
+				CutoffSchemeConfig::Switched => CutoffScheme::Switched{ start: c.switch_start.unwrap_or(c.radius) },
+			},
+			tail_correction: c.tail_correction,
+		});
+		forcefield.neighbors = self.neighbors.as_ref().map(|n| NeighborList::new(n.cutoff, n.skin));
+		forcefield.electrostatic = match self.electrostatics.method {
+			ElectrostaticsMethod::None => Electrostatics::Off,
+			ElectrostaticsMethod::Coulomb => Electrostatics::Coulomb,
+			ElectrostaticsMethod::Ewald => Electrostatics::Ewald(Ewald::from_accuracy(self.electrostatics.accuracy, self.ewald_cutoff(), &forcefield.simbox.size)),
+			ElectrostaticsMethod::Pme => Electrostatics::Pme(Pme::from_accuracy(self.electrostatics.accuracy, self.ewald_cutoff(), self.electrostatics.order.unwrap_or(PME_ORDER), &forcefield.simbox.size)),
+		};
+		forcefield.species = self.species();
+		forcefield.topology = self.topology();
+		forcefield.elementary_charge = self.elementary_charge();
+		forcefield.external = self.field.as_ref().map(|f| External{
+			electric: ElectricField{
+				constant: Vector::new(f.electric[0], f.electric[1], f.electric[2]),
+				amplitude: Vector::new(f.amplitude[0], f.amplitude[1], f.amplitude[2]),
+				frequency: f.frequency,
+				phase: f.phase.to_radians(),
+			},
+			gravity: Vector::new(f.gravity[0], f.gravity[1], f.gravity[2]),
+		});
+		forcefield
+	}
+
+	/// The real space cutoff of the (particle-mesh) Ewald sum.
+	fn ewald_cutoff(&self) -> f64 {
+		match (self.electrostatics.cutoff, &self.neighbors) {
+			(Some(cutoff), _) => cutoff,
+			(None, Some(n)) => n.cutoff,
+			(None, None) => self.simbox.x.min(self.simbox.y).min(self.simbox.z) / 2.0,
+		}
+	}
+
+	/// Create the thermostat, if any.
+	pub fn thermostat(&self) -> Option<Box<dyn Thermostat>> {
+		self.thermostat.as_ref().map(|t| -> Box<dyn Thermostat> { match t.method {
+			ThermostatMethod::Berendsen => Box::new(Berendsen::new(t.target, t.coupling, t.start, self.removed_degrees_of_freedom())),
+			ThermostatMethod::NoseHoover => Box::new(NoseHooverChain::new(t.target, t.coupling, t.start, t.chain.unwrap_or(NOSE_HOOVER_CHAIN), self.particles.len(), self.removed_degrees_of_freedom())),
+			// Use different random numbers than the integrator
+			ThermostatMethod::VelocityRescale => Box::new(VelocityRescale::new(t.target, t.coupling, t.start, self.removed_degrees_of_freedom(), self.simulation.seed.wrapping_add(1))),
+			ThermostatMethod::Andersen => Box::new(Andersen::new(t.target, t.coupling, t.start, self.simulation.seed.wrapping_add(1))),
+		}})
+	}
+
+	/// The temperature setting the mass of an MTK barostat.
+	fn barostat_temperature(&self) -> Option<f64> {
+		self.barostat.as_ref().and_then(|p| p.temperature)
+			.or(self.thermostat.as_ref().map(|t| t.target))
+			.or(self.langevin.as_ref().map(|l| l.temperature))
+	}
+
+	/// Create the barostat, if any.
+	pub fn barostat(&self) -> Option<Box<dyn Barostat>> {
+		self.barostat.as_ref().map(|p| -> Box<dyn Barostat> { match p.method {
+			BarostatMethod::Berendsen => Box::new(pressure::Berendsen::new(p.target, p.coupling, p.compressibility.unwrap_or(BAROSTAT_COMPRESSIBILITY), p.start)),
+			BarostatMethod::Mtk => {
+				let temperature = self.barostat_temperature().expect("The configuration has not been validated");
+				Box::new(Mtk::new(p.target, p.coupling, p.start, temperature, self.particles.len(), self.removed_degrees_of_freedom()))
+			},
+		}})
+	}
+
+	/// Create the integrator.
+	pub fn integrator(&self) -> Box<dyn Integrator> {
+		match self.simulation.integrator {
+			IntegratorMethod::Euler => Box::new(Euler),
+			IntegratorMethod::Verlet => Box::new(VelocityVerlet),
+			IntegratorMethod::Leapfrog => Box::new(Leapfrog),
+			IntegratorMethod::Langevin => {
+				let l = self.langevin.as_ref().expect("The configuration has not been validated");
+				let friction = self.particles.iter().map(|p| p.friction.unwrap_or(l.friction)).collect();
+				Box::new(Langevin::new(l.temperature, friction, self.simulation.seed))
+			}
+			IntegratorMethod::Boris => {
+				let field = self.field.as_ref().map_or([0.0; 3], |f| f.magnetic);
+				Box::new(Boris::new(Vector::new(field[0], field[1], field[2]), self.elementary_charge()))
+			}
+		}
+	}
+
+	/// Minimize the energy of the particles, if a [minimize] section is given. Fails if a bond is stretched too far at the start.
+	pub fn minimize(&self, particles: &mut [Particle], forcefield: &mut ForceField) -> Result<Option<Minimization>, BondError> {
+		self.minimize.as_ref().map(|m| {
+			let time_step = m.time_step.unwrap_or(10.0 * self.simulation.time_step);
+			let mut minimizer: Box<dyn Minimizer> = match m.method {
+				MinimizeMethod::SteepestDescent => Box::new(SteepestDescent::new(m.step)),
+				MinimizeMethod::ConjugateGradient => Box::new(ConjugateGradient::new(m.step)),
+				MinimizeMethod::Fire => Box::new(Fire::new(time_step, m.step)),
+			};
+			let criteria = Criteria{ max_force: m.max_force, energy_change: m.energy_change, steps: m.steps };
+			minimizers::minimize(minimizer.as_mut(), particles, forcefield, &criteria)
+		}).transpose()
+	}
+
+	/// Draw the velocities of the particles, if a [velocities] section is given, with the seed of the simulation.
+	///
+	/// The components along the constraints are projected out and the momentum, and the angular momentum if asked, are removed,
+	/// after which the velocities are scaled so the temperature, without the removed degrees of freedom, is exactly the target.
+	/// Fails if the particles cannot be moved onto the constraints.
+	pub fn velocities(&self, particles: &mut [Particle]) -> Result<(), ConstraintError> {
+		let Some(v) = &self.velocities else {
+			return Ok(());
+		};
+		let mut random = Random::new(self.simulation.seed.wrapping_add(3));
+		temperature::maxwell_boltzmann(particles, v.temperature, &mut random);
+		if let Some(mut c) = self.constraints() {
+			c.project(&self.simbox(), particles, self.simulation.time_step)?;
+		}
+		// Moving or rotating the whole system does not change the lengths of the constraints
+		temperature::remove_momentum(particles);
+		if v.remove_rotation {
+			temperature::remove_angular_momentum(particles);
+		}
+		temperature::rescale(particles, self.removed_degrees_of_freedom(), v.temperature);
+		Ok(())
+	}
+
+	/// Create a simulation from this configuration, minimizing the energy of the particles first if a [minimize] section is given.
+	///
+	/// Also returns the result of the minimization, if any. Fails if the particles cannot be moved onto the constraints or a bond is stretched too far.
+	pub fn simulation<'a>(&self) -> Result<(Simulation<'a>, Option<Minimization>), SimulationError> {
+		let mut particles = self.particles();
+		let mut forcefield = self.forcefield();
+		let minimization = self.minimize(&mut particles, &mut forcefield)?;
+		self.velocities(&mut particles)?;
+		let mut sim = Simulation::new(particles, forcefield, self.integrator(), self.thermostat(), self.barostat(), self.constraints(), self.removed_momentum(), self.simulation.time_step)?;
+		sim.data.units = self.units();
+		Ok((sim, minimization))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A configuration of two particles with opposite charges in an open box.
+	const CHARGED: &str = "
+		[box]
+		periodic = false
+		[[particle]]
+		position = [2.0, 5.0, 5.0]
+		radius = 1.0
+		mass = 1.0
+		charge = 1.0
+		[[particle]]
+		position = [6.0, 5.0, 5.0]
+		radius = 1.0
+		mass = 1.0
+		charge = -1.0
+	";
+
+	/// The message of the error of an invalid configuration.
+	fn invalid(contents: &str) -> String {
+		match Config::from_toml(contents) {
+			Err(ConfigError::Invalid(message)) => message,
+			Err(e) => panic!("expected an invalid configuration, got {}", e),
+			Ok(_) => panic!("expected an invalid configuration"),
+		}
+	}
+
+	#[test]
+	fn tables_are_relative_to_the_configuration() {
+		let directory = std::env::temp_dir().join(format!("config_tables_{}", std::process::id()));
+		std::fs::create_dir_all(&directory).unwrap();
+		let rows: String = (0..=10).map(|i| {
+			let r = 0.5 + 0.15 * i as f64;
+			format!("{} {} {}\n", r, (2.0 - r).powi(3), 3.0 * (2.0 - r).powi(2))
+		}).collect();
+		std::fs::write(directory.join("pair.table"), rows).unwrap();
+		let contents = "
+			[[species]]
+			name = \"a\"
+			sigma = 1.0
+			epsilon = 1.0
+			mass = 1.0
+			[[pair]]
+			species = [\"a\", \"a\"]
+			potential = \"tabulated\"
+			table = \"pair.table\"
+			[[particle]]
+			position = [1.0, 1.0, 1.0]
+			species = \"a\"
+		";
+		let filename = directory.join("config.toml");
+		std::fs::write(&filename, contents).unwrap();
+
+		let config = Config::from_file(filename.to_str().unwrap());
+		// The table is read once, so the species do not depend on the file any more
+		std::fs::remove_dir_all(&directory).unwrap();
+		let config = config.unwrap();
+		let energy = config.species().get_potential(0, 0).get_energy(1.0);
+		assert!((energy - 1.0).abs() < 1e-12);
+		// Not relative to the current directory
+		assert!(invalid(contents).contains("Could not read the table"));
+	}
+
+	#[test]
+	fn coulomb_is_not_cut_off() {
+		assert!(Config::from_toml(CHARGED).is_ok());
+		let neighbors = format!("[neighbors]\ncutoff = 3.0\nskin = 0.3\n{}", CHARGED);
+		assert!(invalid(&neighbors).contains("[neighbors]"));
+		let periodic = CHARGED.replace("periodic = false", "periodic = true");
+		assert!(invalid(&periodic).contains("periodic box"));
+	}
+
+	#[test]
+	fn velocities_have_the_temperature_without_the_momentum() {
+		let contents = "
+			[box]
+			periodic = false
+			[[species]]
+			name = \"a\"
+			sigma = 1.0
+			epsilon = 1.0
+			mass = 2.0
+			[generate]
+			method = \"sc\"
+			species = [\"a\"]
+			density = 0.1
+			[velocities]
+			temperature = 1.5
+			remove_rotation = true
+		";
+		let config = Config::from_toml(contents).unwrap();
+		let mut particles = config.particles();
+		config.velocities(&mut particles).unwrap();
+		let momentum = particles.iter().fold(Vector::zero(), |sum, p| sum + p.v * p.m);
+		assert!(momentum.len() < 1e-10);
+		let double_kinetic: f64 = particles.iter().map(|p| p.m * p.v.sqlen()).sum();
+		assert!((double_kinetic / (3.0 * particles.len() as f64 - 6.0) - 1.5).abs() < 1e-12);
+
+		let (sim, _) = config.simulation().unwrap();
+		let temperature = *sim.data.global.get("temperature").last().unwrap();
+		assert!((temperature - 1.5).abs() < 1e-12);
+	}
+
+	#[test]
+	fn pairs_take_precedence_over_the_mixing_rule() {
+		let contents = "
+			[forcefield]
+			mixing = \"geometric\"
+			[[species]]
+			name = \"a\"
+			sigma = 1.0
+			epsilon = 1.0
+			mass = 1.0
+			[[species]]
+			name = \"b\"
+			sigma = 4.0
+			epsilon = 4.0
+			mass = 1.0
+			[[species]]
+			name = \"c\"
+			sigma = 9.0
+			epsilon = 1.0
+			mass = 1.0
+			[[pair]]
+			species = [\"b\", \"a\"]
+			sigma = 3.0
+			epsilon = 0.5
+			[[pair]]
+			species = [\"a\", \"c\"]
+			potential = \"wca\"
+			[[particle]]
+			position = [5.0, 5.0, 5.0]
+			species = \"a\"
+		";
+		let table = Config::from_toml(contents).unwrap().species();
+		let [a, b, c] = ["a", "b", "c"].map(|name| table.find(name).unwrap());
+		assert_eq!((table.get(a, b).sigma, table.get(a, b).epsilon_4), (3.0, 2.0));
+		assert_eq!((table.get(b, c).sigma, table.get(b, c).epsilon_4), (6.0, 8.0));
+		// The parameters of a potential without them follow from the mixing rule
+		let wca = Wca{ epsilon_4: 4.0, sigma: 3.0 };
+		assert!((table.get_potential(c, a).get_energy(3.2) - wca.get_energy(3.2)).abs() < 1e-12);
+	}
+
+	#[test]
+	fn integrators_are_parsed_by_their_configuration_names() {
+		assert!("leapfrog".parse::<IntegratorMethod>().is_ok_and(|m| m == IntegratorMethod::Leapfrog));
+		assert!(matches!("rk4".parse::<IntegratorMethod>(), Err(ConfigError::Invalid(e)) if e.contains("unknown variant `rk4`")));
+		let contents = format!("[simulation]\nintegrator = \"rk4\"\n{}", CHARGED);
+		assert!(matches!(Config::from_toml(&contents), Err(ConfigError::Parse(_))));
+		let contents = format!("[minimize]\nmethod = \"newton\"\n{}", CHARGED);
+		assert!(matches!(Config::from_toml(&contents), Err(ConfigError::Parse(_))));
+	}
+
+	#[test]
+	fn steps_cover_the_length() {
+		let mut config = Config::from_toml(CHARGED).unwrap();
+		config.simulation.length = 1.0;
+		config.simulation.time_step = 0.1;
+		// Ten time steps of 0.1 add up to slightly less than 1
+		assert!((0..10).fold(0.0, |t, _| t + 0.1) < 1.0);
+		assert_eq!(config.steps(), 10);
+		config.simulation.time_step = 0.3;
+		assert_eq!(config.steps(), 3);
+	}
+
+	#[test]
+	fn overlaps_are_found_across_the_boundary() {
+		let contents = "
+			[box]
+			periodic = true
+			[[particle]]
+			position = [0.2, 5.0, 5.0]
+			radius = 1.0
+			mass = 1.0
+			[[particle]]
+			position = [5.0, 5.0, 5.0]
+			radius = 1.0
+			mass = 1.0
+			[[particle]]
+			position = [9.5, 5.0, 5.0]
+			radius = 1.0
+			mass = 1.0
+		";
+		assert_eq!(invalid(contents), "particles 0 and 2 overlap");
+		assert!(Config::from_toml(&contents.replace("periodic = true", "periodic = false")).is_ok());
+	}
+
+	#[test]
+	fn generation_checks_the_box_first() {
+		let contents = "
+			[box]
+			x = -10.0
+			[[species]]
+			name = \"a\"
+			sigma = 1.0
+			epsilon = 1.0
+			mass = 1.0
+			[generate]
+			method = \"random\"
+			species = [\"a\"]
+			count = 10
+		";
+		assert!(invalid(contents).contains("box.x must be positive"));
+		let dense = contents.replace("x = -10.0", "").replace("\"random\"", "\"fcc\"").replace("count = 10", "density = 1e9");
+		assert!(invalid(&dense).contains("sites"));
+	}
+
+	#[test]
+	fn random_insertion_avoids_the_given_particles() {
+		let contents = "
+			[box]
+			x = 6.0
+			y = 6.0
+			z = 6.0
+			[[species]]
+			name = \"a\"
+			sigma = 1.0
+			epsilon = 1.0
+			mass = 1.0
+			[generate]
+			method = \"random\"
+			species = [\"a\"]
+			count = 20
+			[[particle]]
+			position = [3.0, 3.0, 3.0]
+			species = \"a\"
+		";
+		let config = Config::from_toml(contents).unwrap();
+		let particles = config.particles();
+		assert_eq!(particles.len(), 21);
+		let simbox = config.simbox();
+		assert!(particles[1..].iter().all(|p| simbox.separation(&particles[0].pos, &p.pos).len() > 1.0));
+	}
+}
@@ -1,24 +1,41 @@
-
-//! Several (natural) constants used in several places
+//! Several (natural) constants used in several places, and the defaults of the configuration (see config)
 
 /* FORCEFIELD */
 // Temperature 
-/// Boltzmann Constant
-pub const BOLTZMANN_CONST: f64 = 1.0; 
+/// Boltzmann Constant, in the internal units where temperatures are given as energies (see units)
+pub const BOLTZMANN_CONST: f64 = 1.0;
 /// Fallback temperature, to avoid divide by 0 
 pub const FALLBACK_TEMPERATURE: f64 = 0.001;
+/// Default length of a Nose-Hoover chain
+pub const NOSE_HOOVER_CHAIN: usize = 3;
+/// Default compressibility used by the Berendsen barostat
+pub const BAROSTAT_COMPRESSIBILITY: f64 = 0.1;
 
 // Van der Waals
 /// 4 * the well depth of the Lennard-Jones potential. 
 pub const LJ_4_EPSILON: f64 = 4.0;
+/// Default largest difference between the integrated forces and the energies of a tabulated potential, relative to the range of its energies
+pub const TABLE_TOLERANCE: f64 = 0.01;
 
 // Electrostatic
-// This should result in the relative strengths of the electrostatic and VdWs forces being roughly correct
-// Even though the actual values are totally wrong
-/// Elementary charge
-pub const ELEMENTARY_CHARGE: f64 = 130.0;
-/// Dielectric permittivity of a vacuum
+/// Elementary charge in reduced units, sqrt(4 pi), so the Coulomb energy of two reduced charges is q_i q_j / r
+pub const ELEMENTARY_CHARGE: f64 = 3.5449077018110318;
+/// Dielectric permittivity of a vacuum, in the internal units where the elementary charge includes the Coulomb constant (see units)
 pub const PERMITTIVITY_VACUUM: f64 = 1.0;
+/// Relative accuracy of the Ewald sum
+pub const EWALD_ACCURACY: f64 = 1e-5;
+/// Order of the B-splines of particle-mesh Ewald
+pub const PME_ORDER: usize = 6;
+
+// Bonded
+/// Default number of bonds separating pairs of bonded particles without non-bonded interactions, 2 excludes the 1-2 and 1-3 pairs
+pub const EXCLUSIONS: usize = 2;
+/// Default scaling of the non-bonded interactions of 1-4 pairs, which are three bonds apart
+pub const SCALE_14: f64 = 1.0;
+/// Default relative tolerance of the constraints
+pub const CONSTRAINT_TOLERANCE: f64 = 1e-8;
+/// Default maximum number of iterations of SHAKE and RATTLE
+pub const CONSTRAINT_ITERATIONS: usize = 500;
 
 // Borders
 /// The border of the system in the x direction. The other border is at the origin.
@@ -32,6 +49,18 @@ pub const BORDER_4_EPSILON: f64 = 0.1;
 /// The range at which the border potential is calculated.
 pub const BORDER_RANGE: f64 = 1.0;
 
+// Units
+/// Boltzmann constant, in J/K
+pub const BOLTZMANN_SI: f64 = 1.380649e-23;
+/// Elementary charge, in C
+pub const ELEMENTARY_CHARGE_SI: f64 = 1.602176634e-19;
+/// Dielectric permittivity of a vacuum, in F/m
+pub const PERMITTIVITY_VACUUM_SI: f64 = 8.8541878128e-12;
+/// Avogadro constant, in 1/mol
+pub const AVOGADRO: f64 = 6.02214076e23;
+/// A kilocalorie, in J
+pub const KCAL: f64 = 4184.0;
+
 /* MAIN */
 // Display
 /// Width of the graph
@@ -46,3 +75,21 @@ pub const FRAME_RATE: f64 = 5.0;
 pub const SIM_LEN: f64 = 150.0;
 /// Timestep of the simulation
 pub const TIME_STEP: f64 = 0.001;
+/// Default friction coefficient of the Langevin integrator
+pub const LANGEVIN_FRICTION: f64 = 1.0;
+/// Default largest force at which a minimization has converged
+pub const MINIMIZE_MAX_FORCE: f64 = 0.01;
+/// Default change in energy in a step at which a minimization has converged
+pub const MINIMIZE_ENERGY_CHANGE: f64 = 1e-10;
+/// Default maximum number of steps of a minimization
+pub const MINIMIZE_STEPS: usize = 10000;
+/// Default largest displacement of a particle in a step of a minimization
+pub const MINIMIZE_STEP: f64 = 0.01;
+/// Default number of random positions tried for each particle inserted at random
+pub const INSERTION_ATTEMPTS: usize = 1000;
+/// Largest number of sites of a generated lattice, so a wrong density fails instead of exhausting the memory
+pub const MAX_LATTICE_SITES: usize = 10_000_000;
+/// Default seed of the random numbers
+pub const SEED: u64 = 0;
+/// Default file the data is written to when running headless
+pub const OUTPUT_FILE: &str = "out.csv";
@@ -0,0 +1,370 @@
+/*! Holonomic constraints, which keep the distances between pairs of particles fixed, so the fast vibrations of stiff bonds do not limit the time step.
+
+The constraints are applied around the steps of the integrator, following the RATTLE scheme for velocity Verlet:
+after the positions have been updated, SHAKE moves them back onto the constraints along the constrained directions of the previous step,
+and after the second half kick the components of the velocities along the constraints are removed.
+Rigid three-site water molecules can use SETTLE instead, which solves their positions analytically, see Miyamoto and Kollman, J. Comput. Chem. 13, 952 (1992).
+*/
+
+use std::fmt;
+use std::error::Error;
+
+use crate::particles::Particle;
+use crate::simbox::SimBox;
+use crate::vectors::{Vector, Tensor};
+
+/// A constraint, keeping two particles at a fixed distance.
+#[derive(Clone, Copy)]
+pub struct Constraint {
+	/// The indices of the particles
+	pub particles: [usize; 2],
+	/// The distance between the particles
+	pub length: f64,
+}
+
+/// A rigid three-site water molecule, where the hydrogens have the same mass.
+#[derive(Clone, Copy)]
+pub struct Settle {
+	/// The indices of the oxygen and the two hydrogens
+	pub particles: [usize; 3],
+	/// The distance between the oxygen and each hydrogen
+	pub oh: f64,
+	/// The distance between the hydrogens
+	pub hh: f64,
+}
+
+/// An error while applying the constraints.
+#[derive(Debug)]
+pub enum ConstraintError {
+	/// The constraints did not converge within the maximum number of iterations, with the largest remaining relative error
+	NotConverged(f64),
+	/// A constrained pair rotated too far in one step for SHAKE, or a water molecule was too distorted for SETTLE
+	Failed([usize; 2]),
+}
+
+impl fmt::Display for ConstraintError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ConstraintError::NotConverged(error) => write!(f, "The constraints did not converge, the largest relative error is {}", error),
+			ConstraintError::Failed([i, j]) => write!(f, "The constraint between particles {} and {} failed, as they moved too far in one step", i, j),
+		}
+	}
+}
+
+impl Error for ConstraintError {}
+
+/// The constraints of a system, and the tolerance to which they are solved.
+pub struct Constraints {
+	/// The constraints between pairs of particles, solved iteratively
+	pub pairs: Vec<Constraint>,
+	/// The rigid water molecules, solved analytically
+	pub settles: Vec<Settle>,
+	/// The largest relative error of the distances, and of the velocities along the constraints per time step, at which the constraints are solved
+	pub tolerance: f64,
+	/// The maximum number of iterations of SHAKE and RATTLE
+	pub max_iterations: usize,
+	/// The virial of the constraint forces during the last velocity update
+	pub virial: Tensor,
+	reference: Vec<Vector>,
+}
+
+impl Constraints {
+	/// Create an empty set of constraints, solved to a tolerance within a maximum number of iterations.
+	pub fn new(tolerance: f64, max_iterations: usize) -> Self {
+		Constraints{
+			pairs: Vec::new(),
+			settles: Vec::new(),
+			tolerance,
+			max_iterations,
+			virial: Tensor::zero(),
+			reference: Vec::new(),
+		}
+	}
+
+	/// The number of degrees of freedom removed by the constraints.
+	pub fn count(&self) -> usize {
+		self.pairs.len() + 3 * self.settles.len()
+	}
+
+	/// Store the positions of the particles before the integrator moves them, which satisfy the constraints.
+	pub fn save(&mut self, particles: &[Particle]) {
+		self.reference = particles.iter().map(|p| p.pos).collect();
+	}
+
+	/// Move the particles back onto the constraints after the integrator moved them from the saved positions,
+	/// and correct their velocities for the displacement over the time step dt.
+	pub fn constrain_positions(&mut self, simbox: &SimBox, particles: &mut [Particle], dt: f64) -> Result<(), ConstraintError> {
+		let unconstrained: Vec<Vector> = particles.iter().map(|p| p.pos).collect();
+		self.shake(simbox, particles)?;
+		for settle in self.settles.iter() {
+			self.settle(simbox, particles, settle)?;
+		}
+		for (p, old) in particles.iter_mut().zip(unconstrained) {
+			p.v += (p.pos - old) / dt;
+		}
+		Ok(())
+	}
+
+	/// Remove the components of the velocities along the constraints, and store the virial of the constraint forces of a half kick of dt / 2.
+	pub fn constrain_velocities(&mut self, simbox: &SimBox, particles: &mut [Particle], dt: f64) -> Result<(), ConstraintError> {
+		self.virial = Tensor::zero();
+		let pairs = self.all_pairs();
+		for _ in 0..self.max_iterations {
+			let mut error: f64 = 0.0;
+			for c in pairs.iter() {
+				let [i, j] = c.particles;
+				let s = simbox.separation(&particles[j].pos, &particles[i].pos);
+				let v = particles[i].v - particles[j].v;
+				// The relative change in length over a time step
+				error = error.max((s.dot(&v) * dt / s.sqlen()).abs());
+
+				let k = s.dot(&v) / (s.sqlen() * (1.0 / particles[i].m + 1.0 / particles[j].m));
+				particles[i].v -= s * (k / particles[i].m);
+				particles[j].v += s * (k / particles[j].m);
+				// The constraint force on i is -2 k s / dt, as it acts during half a step
+				self.virial += Tensor::outer(&s, &s) * (-2.0 * k / dt);
+			}
+			if error <= self.tolerance {
+				return Ok(());
+			}
+		}
+		Err(ConstraintError::NotConverged(self.velocity_error(simbox, particles, &pairs, dt)))
+	}
+
+	/// Move the particles onto the constraints, and remove the components of their velocities along them, so an initial configuration satisfies them.
+	pub fn project(&mut self, simbox: &SimBox, particles: &mut [Particle], dt: f64) -> Result<(), ConstraintError> {
+		self.save(particles);
+		let velocities: Vec<Vector> = particles.iter().map(|p| p.v).collect();
+		self.constrain_positions(simbox, particles, dt)?;
+		for (p, v) in particles.iter_mut().zip(velocities) {
+			p.v = v;
+		}
+		self.constrain_velocities(simbox, particles, dt)
+	}
+
+	/// The pair constraints and the three distances of each water molecule, which are all solved by RATTLE.
+	fn all_pairs(&self) -> Vec<Constraint> {
+		let mut pairs = self.pairs.clone();
+		for s in self.settles.iter() {
+			let [o, h1, h2] = s.particles;
+			pairs.push(Constraint{ particles: [o, h1], length: s.oh });
+			pairs.push(Constraint{ particles: [o, h2], length: s.oh });
+			pairs.push(Constraint{ particles: [h1, h2], length: s.hh });
+		}
+		pairs
+	}
+
+	/// The largest relative change in length over a time step of the constraints.
+	fn velocity_error(&self, simbox: &SimBox, particles: &[Particle], pairs: &[Constraint], dt: f64) -> f64 {
+		pairs.iter().map(|c| {
+			let [i, j] = c.particles;
+			let s = simbox.separation(&particles[j].pos, &particles[i].pos);
+			(s.dot(&(particles[i].v - particles[j].v)) * dt / s.sqlen()).abs()
+		}).fold(0.0, f64::max)
+	}
+
+	/// Iteratively correct the positions of the pair constraints along their directions at the saved positions.
+	fn shake(&self, simbox: &SimBox, particles: &mut [Particle]) -> Result<(), ConstraintError> {
+		if self.pairs.is_empty() {
+			return Ok(());
+		}
+		for _ in 0..self.max_iterations {
+			let mut error: f64 = 0.0;
+			for c in self.pairs.iter() {
+				let [i, j] = c.particles;
+				let s = simbox.separation(&particles[j].pos, &particles[i].pos);
+				let d_sq = c.length * c.length;
+				let diff = d_sq - s.sqlen();
+				error = error.max((diff / (2.0 * d_sq)).abs());
+
+				let reference = simbox.separation(&self.reference[j], &self.reference[i]);
+				let projection = s.dot(&reference);
+				if projection < 1e-6 * d_sq {
+					return Err(ConstraintError::Failed(c.particles));
+				}
+				let g = diff / (2.0 * projection * (1.0 / particles[i].m + 1.0 / particles[j].m));
+				particles[i].pos += reference * (g / particles[i].m);
+				particles[j].pos -= reference * (g / particles[j].m);
+			}
+			if error <= self.tolerance {
+				return Ok(());
+			}
+		}
+		let error = self.pairs.iter().map(|c| {
+			let s = simbox.separation(&particles[c.particles[1]].pos, &particles[c.particles[0]].pos);
+			((s.sqlen() - c.length * c.length) / (2.0 * c.length * c.length)).abs()
+		}).fold(0.0, f64::max);
+		Err(ConstraintError::NotConverged(error))
+	}
+
+	/// Move a water molecule onto its constraints analytically, by rotating the rigid molecule at the saved positions onto the new positions.
+	fn settle(&self, simbox: &SimBox, particles: &mut [Particle], settle: &Settle) -> Result<(), ConstraintError> {
+		let [o, h1, h2] = settle.particles;
+		let (m_o, m_h) = (particles[o].m, particles[h1].m);
+		let total = m_o + 2.0 * m_h;
+
+		// The rigid molecule, with the oxygen at (0, ra) and the hydrogens at (-+rc, -rb) around its centre of mass
+		let rc = settle.hh / 2.0;
+		let height = (settle.oh * settle.oh - rc * rc).sqrt();
+		let ra = 2.0 * m_h * height / total;
+		let rb = height - ra;
+
+		// The old molecule relative to its oxygen, and the new one relative to its centre of mass, using the nearest images of the hydrogens
+		let b0 = simbox.separation(&self.reference[o], &self.reference[h1]);
+		let c0 = simbox.separation(&self.reference[o], &self.reference[h2]);
+		let b = simbox.separation(&particles[o].pos, &particles[h1].pos);
+		let c = simbox.separation(&particles[o].pos, &particles[h2].pos);
+		let com = (b + c) * (m_h / total);
+		let (a1, b1, c1) = (-com, b - com, c - com);
+
+		// A frame with z normal to the old plane, and x normal to the new oxygen
+		let ez = b0.cross(&c0).norm();
+		let ex = a1.cross(&ez).norm();
+		let ey = ez.cross(&ex);
+		let local = |v: &Vector| Vector::new(ex.dot(v), ey.dot(v), ez.dot(v));
+		let (b0, c0, a1, b1, c1) = (local(&b0), local(&c0), local(&a1), local(&b1), local(&c1));
+
+		// Tilt the rigid molecule out of the plane to match the new heights
+		let sin_phi = a1.z / ra;
+		let cos_phi_sq = 1.0 - sin_phi * sin_phi;
+		if cos_phi_sq <= 0.0 {
+			return Err(ConstraintError::Failed([o, h1]));
+		}
+		let cos_phi = cos_phi_sq.sqrt();
+		let sin_psi = (b1.z - c1.z) / (2.0 * rc * cos_phi);
+		let cos_psi_sq = 1.0 - sin_psi * sin_psi;
+		if cos_psi_sq <= 0.0 {
+			return Err(ConstraintError::Failed([h1, h2]));
+		}
+		let cos_psi = cos_psi_sq.sqrt();
+
+		let a2_y = ra * cos_phi;
+		let b2_x = -rc * cos_psi;
+		let t1 = -rb * cos_phi;
+		let t2 = rc * sin_psi * sin_phi;
+		let (b2_y, c2_y) = (t1 - t2, t1 + t2);
+
+		// Rotate it in the plane, so the angular momentum about z is conserved
+		let alpha = b2_x * (b0.x - c0.x) + b0.y * b2_y + c0.y * c2_y;
+		let beta = b2_x * (c0.y - b0.y) + b0.x * b2_y + c0.x * c2_y;
+		let gamma = b0.x * b1.y - b1.x * b0.y + c0.x * c1.y - c1.x * c0.y;
+		let alpha_beta = alpha * alpha + beta * beta;
+		let root = alpha_beta - gamma * gamma;
+		if root < 0.0 {
+			return Err(ConstraintError::Failed([o, h1]));
+		}
+		let sin_theta = (alpha * gamma - beta * root.sqrt()) / alpha_beta;
+		let cos_theta = (1.0 - sin_theta * sin_theta).sqrt();
+
+		let a3 = Vector::new(-a2_y * sin_theta, a2_y * cos_theta, a1.z);
+		let b3 = Vector::new(b2_x * cos_theta - b2_y * sin_theta, b2_x * sin_theta + b2_y * cos_theta, b1.z);
+		let c3 = Vector::new(-b2_x * cos_theta - c2_y * sin_theta, -b2_x * sin_theta + c2_y * cos_theta, c1.z);
+
+		// Back to the original frame, moving each particle from its current position so its periodic image is kept
+		let global = |v: &Vector| ex * v.x + ey * v.y + ez * v.z;
+		particles[o].pos += global(&(a3 - a1));
+		particles[h1].pos += global(&(b3 - b1));
+		particles[h2].pos += global(&(c3 - c1));
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::random::Random;
+
+	/// A random vector with components between -scale and scale.
+	fn random_vector(random: &mut Random, scale: f64) -> Vector {
+		Vector::new(random.uniform() - 0.5, random.uniform() - 0.5, random.uniform() - 0.5) * (2.0 * scale)
+	}
+
+	/// The total momentum of the particles.
+	fn momentum(particles: &[Particle]) -> Vector {
+		particles.iter().fold(Vector::zero(), |total, p| total + p.v * p.m)
+	}
+
+	/// The largest relative errors of the lengths, and of the velocities along the constraints per time step.
+	fn errors(constraints: &Constraints, simbox: &SimBox, particles: &[Particle], dt: f64) -> (f64, f64) {
+		let pairs = constraints.all_pairs();
+		let length = pairs.iter().map(|c| {
+			let s = simbox.separation(&particles[c.particles[1]].pos, &particles[c.particles[0]].pos);
+			(s.len() - c.length).abs() / c.length
+		}).fold(0.0, f64::max);
+		(length, constraints.velocity_error(simbox, particles, &pairs, dt))
+	}
+
+	/// Take an unconstrained step from the saved positions, moving each particle with its velocity and a random kick.
+	fn step(constraints: &mut Constraints, particles: &mut [Particle], random: &mut Random, dt: f64) {
+		constraints.save(particles);
+		for p in particles.iter_mut() {
+			p.v += random_vector(random, 1.0);
+			p.pos += p.v * dt;
+		}
+	}
+
+	#[test]
+	fn shake_and_rattle_satisfy_the_constraints() {
+		// A bent chain of four particles, the last one across the periodic boundary
+		let simbox = SimBox::new(Vector::new(5.0, 5.0, 5.0), true);
+		let positions = [Vector::new(3.5, 2.0, 2.0), Vector::new(4.5, 2.0, 2.0), Vector::new(4.5, 3.0, 2.0), Vector::new(0.5, 3.0, 2.0)];
+		let mut particles: Vec<Particle> = positions.iter().zip([1.0, 2.0, 3.0, 1.5]).map(|(pos, m)| Particle::new(pos, 0.5, m, 0.0)).collect();
+		let mut constraints = Constraints::new(1e-10, 1000);
+		for i in 0..3 {
+			constraints.pairs.push(Constraint{ particles: [i, i + 1], length: 1.0 });
+		}
+
+		let (dt, mut random) = (0.01, Random::new(1));
+		for _ in 0..10 {
+			step(&mut constraints, &mut particles, &mut random, dt);
+			let before = momentum(&particles);
+			constraints.constrain_positions(&simbox, &mut particles, dt).unwrap();
+			assert!(errors(&constraints, &simbox, &particles, dt).0 < 1e-9);
+			constraints.constrain_velocities(&simbox, &mut particles, dt).unwrap();
+			let (length, velocity) = errors(&constraints, &simbox, &particles, dt);
+			assert!(length < 1e-9 && velocity <= 1e-10);
+			// The constraint forces are internal, so they do not change the momentum
+			assert!((momentum(&particles) - before).len() < 1e-10);
+		}
+	}
+
+	#[test]
+	fn settle_satisfies_the_constraints() {
+		let simbox = SimBox::new(Vector::new(5.0, 5.0, 5.0), true);
+		let (oh, angle) = (1.0, 109.47_f64.to_radians());
+		let hh = 2.0 * oh * (angle / 2.0).sin();
+		let o = Vector::new(0.2, 2.0, 2.0);
+		let h1 = o + Vector::new((angle / 2.0).sin(), (angle / 2.0).cos(), 0.0) * oh;
+		let h2 = o + Vector::new(-(angle / 2.0).sin(), (angle / 2.0).cos(), 0.0) * oh;
+		let mut particles = vec![Particle::new(&o, 0.5, 16.0, 0.0), Particle::new(&h1, 0.3, 1.0, 0.0), Particle::new(&h2, 0.3, 1.0, 0.0)];
+		let mut constraints = Constraints::new(1e-10, 1000);
+		constraints.settles.push(Settle{ particles: [0, 1, 2], oh, hh });
+
+		let (dt, mut random) = (0.01, Random::new(2));
+		for _ in 0..10 {
+			step(&mut constraints, &mut particles, &mut random, dt);
+			let before = momentum(&particles);
+			constraints.constrain_positions(&simbox, &mut particles, dt).unwrap();
+			assert!(errors(&constraints, &simbox, &particles, dt).0 < 1e-12);
+			assert!((momentum(&particles) - before).len() < 1e-10);
+			constraints.constrain_velocities(&simbox, &mut particles, dt).unwrap();
+			let (length, velocity) = errors(&constraints, &simbox, &particles, dt);
+			assert!(length < 1e-12 && velocity <= 1e-10);
+			for p in particles.iter_mut() {
+				simbox.wrap(p);
+			}
+		}
+	}
+
+	#[test]
+	fn project_moves_onto_the_constraints() {
+		let simbox = SimBox::new(Vector::new(5.0, 5.0, 5.0), false);
+		let mut particles = vec![Particle::new(&Vector::new(1.0, 1.0, 1.0), 0.5, 1.0, 0.0), Particle::new(&Vector::new(2.3, 1.1, 1.0), 0.5, 2.0, 0.0)];
+		particles[0].v = Vector::new(1.0, -0.5, 0.2);
+		let mut constraints = Constraints::new(1e-10, 1000);
+		constraints.pairs.push(Constraint{ particles: [0, 1], length: 1.0 });
+		constraints.project(&simbox, &mut particles, 0.01).unwrap();
+		let (length, velocity) = errors(&constraints, &simbox, &particles, 0.01);
+		assert!(length < 1e-9 && velocity <= 1e-10);
+	}
+}
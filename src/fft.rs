@@ -0,0 +1,79 @@
+/*! Fast Fourier transforms of complex data.
+
+Uses the iterative radix-2 Cooley-Tukey algorithm, so all lengths must be powers of two.
+The transforms are not normalized: a forward transform followed by an inverse transform multiplies the data by its length.
+*/
+
+use crate::complex::Complex;
+
+use std::f64::consts::PI;
+
+/** Transform data in place.
+
+The forward transform is X(m) = sum_k x(k) exp(-2pi i m k / N), the inverse transform uses exp(+2pi i m k / N).
+Panics if the length is not a power of two.
+*/
+pub fn fft(data: &mut [Complex], inverse: bool) {
+	let n = data.len();
+	assert!(n.is_power_of_two(), "The length of an FFT must be a power of two, got {}", n);
+
+	// Bit reversal permutation
+	let mut j = 0;
+	for i in 1..n {
+		let mut bit = n >> 1;
+		while j & bit != 0 {
+			j ^= bit;
+			bit >>= 1;
+		}
+		j |= bit;
+		if i < j {
+			data.swap(i, j);
+		}
+	}
+
+	let sign = if inverse { 1.0 } else { -1.0 };
+	let mut len = 2;
+	while len <= n {
+		let step = Complex::from_phase(sign * 2.0 * PI / len as f64);
+		for start in (0..n).step_by(len) {
+			let mut w = Complex::new(1.0, 0.0);
+			for k in 0..len / 2 {
+				let a = data[start + k];
+				let b = data[start + k + len / 2] * w;
+				data[start + k] = a + b;
+				data[start + k + len / 2] = a - b;
+				w = w * step;
+			}
+		}
+		len <<= 1;
+	}
+}
+
+/** Transform three-dimensional data in place, stored with the last axis contiguous, so point (x, y, z) is at (x * size[1] + y) * size[2] + z.
+
+Panics if a size is not a power of two.
+*/
+pub fn fft_3d(data: &mut [Complex], size: [usize; 3], inverse: bool) {
+	assert_eq!(data.len(), size[0] * size[1] * size[2]);
+	let strides = [size[1] * size[2], size[2], 1];
+
+	let mut line = Vec::new();
+	for axis in 0..3 {
+		let (a, b) = match axis {
+			0 => (1, 2),
+			1 => (0, 2),
+			_ => (0, 1),
+		};
+		for i in 0..size[a] {
+			for j in 0..size[b] {
+				let offset = i * strides[a] + j * strides[b];
+				line.clear();
+				line.extend((0..size[axis]).map(|k| data[offset + k * strides[axis]]));
+				fft(&mut line, inverse);
+				for (k, value) in line.iter().enumerate() {
+					data[offset + k * strides[axis]] = *value;
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,435 @@
+/*! Bonded interactions: bonds, angles and dihedrals between the particles of a molecule.
+
+The topology lists the bonded terms by the indices of their particles.
+Pairs of particles that are a few bonds apart are excluded from the non-bonded (van der Waals and electrostatic) interactions,
+as those are already described by the bonded terms, and the pairs that are three bonds apart (1-4 pairs) can have them scaled instead.
+*/
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::error::Error;
+
+use crate::constants::{EXCLUSIONS, SCALE_14};
+use crate::particles::Particle;
+use crate::simbox::SimBox;
+use crate::vectors::{Vector, Tensor};
+use super::Contributions;
+
+/// A bond stretched beyond the maximum length of its potential.
+#[derive(Debug)]
+pub struct BondError {
+	/// The indices of the particles
+	pub particles: [usize; 2],
+	/// The distance between the particles
+	pub distance: f64,
+}
+
+impl fmt::Display for BondError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "The bond between particles {} and {} is stretched to {}, beyond the maximum length of its potential", self.particles[0], self.particles[1], self.distance)
+	}
+}
+
+impl Error for BondError {}
+
+/// The potential of a bond, as a function of the distance between its two particles.
+#[derive(Clone, Copy)]
+pub enum BondPotential {
+	/// k/2 (r - length)^2
+	Harmonic {
+		/// The force constant
+		k: f64,
+		/// The equilibrium length
+		length: f64,
+	},
+	/// The finitely extensible nonlinear elastic potential -k/2 max_length^2 ln(1 - (r / max_length)^2), which diverges at the maximum length
+	Fene {
+		/// The force constant
+		k: f64,
+		/// The maximum length
+		max_length: f64,
+	},
+}
+
+impl BondPotential {
+	/// Gets the energy and its derivative to the distance, at a distance, or None if a FENE bond is stretched to its maximum length or beyond.
+	pub fn get(&self, distance: f64) -> Option<(f64, f64)> {
+		match *self {
+			BondPotential::Harmonic{ k, length } => Some((0.5 * k * (distance - length).powi(2), k * (distance - length))),
+			BondPotential::Fene{ k, max_length } => {
+				let x = (distance / max_length).powi(2);
+				(x < 1.0).then(|| (-0.5 * k * max_length * max_length * (1.0 - x).ln(), k * distance / (1.0 - x)))
+			}
+		}
+	}
+}
+
+/// A bond between two particles.
+#[derive(Clone, Copy)]
+pub struct Bond {
+	/// The indices of the particles
+	pub particles: [usize; 2],
+	/// The potential
+	pub potential: BondPotential,
+}
+
+/// A harmonic angle between three particles, with the middle particle at the vertex: k/2 (theta - angle)^2.
+#[derive(Clone, Copy)]
+pub struct Angle {
+	/// The indices of the particles
+	pub particles: [usize; 3],
+	/// The force constant
+	pub k: f64,
+	/// The equilibrium angle, in radians
+	pub angle: f64,
+}
+
+/// The potential of a dihedral, as a function of the dihedral angle phi, which is 0 when the outer particles are on the same side (cis) and pi when opposite (trans).
+#[derive(Clone, Copy)]
+pub enum DihedralPotential {
+	/// k (1 + cos(multiplicity phi - phase))
+	Periodic {
+		/// The force constant
+		k: f64,
+		/// The number of minima in a full turn
+		multiplicity: f64,
+		/// The phase, in radians
+		phase: f64,
+	},
+	/// The Ryckaert-Bellemans polynomial, sum over n of coefficients[n] cos^n(phi - pi)
+	RyckaertBellemans {
+		/// The coefficient of each power of the cosine
+		coefficients: [f64; 6],
+	},
+}
+
+impl DihedralPotential {
+	/// Gets the energy and its derivative to the dihedral angle, at a dihedral angle.
+	pub fn get(&self, phi: f64) -> (f64, f64) {
+		match *self {
+			DihedralPotential::Periodic{ k, multiplicity, phase } => {
+				let x = multiplicity * phi - phase;
+				(k * (1.0 + x.cos()), -k * multiplicity * x.sin())
+			}
+			DihedralPotential::RyckaertBellemans{ coefficients } => {
+				// cos(phi - pi) = -cos(phi), and its derivative is sin(phi)
+				let cos = -phi.cos();
+				let mut energy = 0.0;
+				let mut derivative = 0.0;
+				let (mut previous, mut power) = (0.0, 1.0);
+				for (n, c) in coefficients.iter().enumerate() {
+					energy += c * power;
+					derivative += n as f64 * c * previous;
+					previous = power;
+					power *= cos;
+				}
+				(energy, derivative * phi.sin())
+			}
+		}
+	}
+}
+
+/// A dihedral between four particles, bonded in order, from the angle between the planes of the first three and the last three particles.
+#[derive(Clone, Copy)]
+pub struct Dihedral {
+	/// The indices of the particles
+	pub particles: [usize; 4],
+	/// The potential
+	pub potential: DihedralPotential,
+}
+
+/// The bonded terms of a system, and the pairs of particles whose non-bonded interactions are excluded or scaled.
+#[derive(Clone)]
+pub struct Topology {
+	/// Pairs of particles separated by at most this many bonds have no non-bonded interactions
+	pub exclusions: usize,
+	/// The scaling of the van der Waals interactions of 1-4 pairs, unless they are excluded
+	pub scale_14_vdw: f64,
+	/// The scaling of the electrostatic interactions of 1-4 pairs, unless they are excluded
+	pub scale_14_electric: f64,
+	bonds: Vec<Bond>,
+	angles: Vec<Angle>,
+	dihedrals: Vec<Dihedral>,
+	bonded: Vec<Vec<usize>>,
+	separations: BTreeMap<(usize, usize), usize>,
+}
+
+impl Topology {
+	/// Create an empty topology, with the default exclusions and 1-4 scaling from constants.
+	pub fn new() -> Self {
+		Topology{
+			exclusions: EXCLUSIONS,
+			scale_14_vdw: SCALE_14,
+			scale_14_electric: SCALE_14,
+			bonds: Vec::new(),
+			angles: Vec::new(),
+			dihedrals: Vec::new(),
+			bonded: Vec::new(),
+			separations: BTreeMap::new(),
+		}
+	}
+
+	/// Whether there are no bonded terms.
+	pub fn is_empty(&self) -> bool {
+		self.bonds.is_empty() && self.angles.is_empty() && self.dihedrals.is_empty()
+	}
+
+	/// Add a bond. The bonds determine which pairs are excluded from the non-bonded interactions.
+	pub fn add_bond(&mut self, bond: Bond) {
+		self.bonds.push(bond);
+		self.connect(bond.particles[0], bond.particles[1]);
+	}
+
+	/// Connect two particles for the exclusions, like a bond without a potential, for example for a constrained pair.
+	pub fn connect(&mut self, a: usize, b: usize) {
+		assert!(a != b, "A particle cannot be bonded to itself");
+
+		// Any path that is shorter with the new bond goes through it, so only pairs within two bonds of either end change
+		let from_a = self.within(a, 2);
+		let from_b = self.within(b, 2);
+		for &(i, di) in from_a.iter() {
+			for &(j, dj) in from_b.iter() {
+				let separation = di + 1 + dj;
+				if i != j && separation <= 3 {
+					let entry = self.separations.entry((i.min(j), i.max(j))).or_insert(separation);
+					*entry = (*entry).min(separation);
+				}
+			}
+		}
+
+		let largest = a.max(b);
+		if self.bonded.len() <= largest {
+			self.bonded.resize(largest + 1, Vec::new());
+		}
+		self.bonded[a].push(b);
+		self.bonded[b].push(a);
+	}
+
+	/// Add an angle.
+	pub fn add_angle(&mut self, angle: Angle) {
+		self.angles.push(angle);
+	}
+
+	/// Add a dihedral.
+	pub fn add_dihedral(&mut self, dihedral: Dihedral) {
+		self.dihedrals.push(dihedral);
+	}
+
+	/// All bonds.
+	pub fn bonds(&self) -> &[Bond] {
+		&self.bonds
+	}
+
+	/// All angles.
+	pub fn angles(&self) -> &[Angle] {
+		&self.angles
+	}
+
+	/// All dihedrals.
+	pub fn dihedrals(&self) -> &[Dihedral] {
+		&self.dihedrals
+	}
+
+	/// The smallest number of bonds separating two particles, if it is at most three.
+	pub fn separation(&self, i: usize, j: usize) -> Option<usize> {
+		self.separations.get(&(i.min(j), i.max(j))).copied()
+	}
+
+	/// Gets the scaling of the (van der Waals, electrostatic) interactions between two particles: zero if excluded, and one if they are not affected by the topology.
+	pub fn scaling(&self, i: usize, j: usize) -> (f64, f64) {
+		match self.separation(i, j) {
+			Some(separation) if separation <= self.exclusions => (0.0, 0.0),
+			Some(3) => (self.scale_14_vdw, self.scale_14_electric),
+			_ => (1.0, 1.0),
+		}
+	}
+
+	/// All pairs of particles whose non-bonded interactions are excluded or scaled, with the scaling of their (van der Waals, electrostatic) interactions.
+	pub fn scaled_pairs(&self) -> impl Iterator<Item = (usize, usize, (f64, f64))> + '_ {
+		self.separations.keys()
+			.map(|&(i, j)| (i, j, self.scaling(i, j)))
+			.filter(|&(_, _, scaling)| scaling != (1.0, 1.0))
+	}
+
+	/// The particles within a number of bonds of a particle, with their separation in bonds, including the particle itself.
+	fn within(&self, particle: usize, bonds: usize) -> Vec<(usize, usize)> {
+		let mut found = vec![(particle, 0)];
+		let mut start = 0;
+		for separation in 1..=bonds {
+			let end = found.len();
+			for k in start..end {
+				for &next in self.bonded.get(found[k].0).into_iter().flatten() {
+					if found.iter().all(|&(p, _)| p != next) {
+						found.push((next, separation));
+					}
+				}
+			}
+			start = end;
+		}
+		found
+	}
+
+	/// Add the forces, energies and virial of all bonded terms to the contributions.
+	///
+	/// The energy of each term is split equally between its particles. Fails if a bond is stretched beyond the maximum length of its potential.
+	pub(crate) fn add_contributions(&self, simbox: &SimBox, p: &[Particle], c: &mut Contributions) -> Result<(), BondError> {
+		for bond in self.bonds.iter() {
+			let [i, j] = bond.particles;
+			let d = simbox.separation(&p[i].pos, &p[j].pos);
+			let distance = d.len();
+			let (energy, derivative) = bond.potential.get(distance).ok_or(BondError{ particles: bond.particles, distance })?;
+
+			let f = d * (-derivative / distance);
+			add_forces(c, &[i, j], &[-f, f], &[Vector::zero(), d]);
+			for k in [i, j] {
+				c.energy_bond[k] += energy / 2.0;
+			}
+		}
+
+		for angle in self.angles.iter() {
+			let [i, j, k] = angle.particles;
+			let a = simbox.separation(&p[j].pos, &p[i].pos);
+			let b = simbox.separation(&p[j].pos, &p[k].pos);
+			let (la, lb) = (a.len(), b.len());
+			let cos = (a.dot(&b) / (la * lb)).clamp(-1.0, 1.0);
+			let theta = cos.acos();
+			let sin = theta.sin().max(1e-8);
+
+			// -dE/dtheta dtheta/dr, with dtheta/dr = -1/sin(theta) dcos(theta)/dr
+			let derivative = angle.k * (theta - angle.angle);
+			let fi = (b / (la * lb) - a * (cos / (la * la))) * (derivative / sin);
+			let fk = (a / (la * lb) - b * (cos / (lb * lb))) * (derivative / sin);
+			add_forces(c, &[i, j, k], &[fi, -fi - fk, fk], &[a, Vector::zero(), b]);
+			for l in [i, j, k] {
+				c.energy_angle[l] += 0.5 * angle.k * (theta - angle.angle).powi(2) / 3.0;
+			}
+		}
+
+		for dihedral in self.dihedrals.iter() {
+			let [i, j, k, l] = dihedral.particles;
+			let r_ij = simbox.separation(&p[j].pos, &p[i].pos);
+			let r_kj = simbox.separation(&p[j].pos, &p[k].pos);
+			let r_kl = simbox.separation(&p[l].pos, &p[k].pos);
+			let m = r_ij.cross(&r_kj);
+			let n = r_kj.cross(&r_kl);
+			let kj = r_kj.len();
+			let phi = (kj * r_ij.dot(&n)).atan2(m.dot(&n));
+			let (energy, derivative) = dihedral.potential.get(phi);
+
+			// Blondel and Karplus, J. Comput. Chem. 17, 1132 (1996), which is well behaved unless three particles are on a line
+			let fi = m * (-derivative * kj / m.sqlen());
+			let fl = n * (derivative * kj / n.sqlen());
+			let s = fi * (r_ij.dot(&r_kj) / (kj * kj)) - fl * (r_kl.dot(&r_kj) / (kj * kj));
+			let fj = s - fi;
+			let fk = -fl - s;
+			add_forces(c, &[i, j, k, l], &[fi, fj, fk, fl], &[r_ij, Vector::zero(), r_kj, r_kj - r_kl]);
+			for q in [i, j, k, l] {
+				c.energy_dihedral[q] += energy / 4.0;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Add the forces of a bonded term to the contributions, with the positions of its particles relative to one of them for the virial.
+fn add_forces(c: &mut Contributions, particles: &[usize], forces: &[Vector], positions: &[Vector]) {
+	for ((&i, f), r) in particles.iter().zip(forces).zip(positions) {
+		c.force_bonded[i] += *f;
+		c.force_total[i] += *f;
+		c.virial += Tensor::outer(r, f);
+	}
+}
+
+impl Default for Topology {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Four particles in a skewed chain, so no three of them are on a line.
+	fn chain() -> Vec<Particle> {
+		[Vector::new(4.1, 5.2, 4.9), Vector::new(5.0, 5.0, 5.0), Vector::new(5.3, 5.9, 5.2), Vector::new(6.1, 6.2, 5.8)].iter()
+			.map(|pos| Particle::new(pos, 0.5, 1.0, 0.0)).collect()
+	}
+
+	/// The total energy of the bonded terms, and their contributions.
+	fn evaluate(topology: &Topology, p: &[Particle]) -> (f64, Contributions) {
+		let simbox = SimBox::new(Vector::new(10.0, 10.0, 10.0), false);
+		let mut c = Contributions::new(p.len());
+		topology.add_contributions(&simbox, p, &mut c).unwrap();
+		((0..p.len()).map(|i| c.get_potential_energy(i)).sum(), c)
+	}
+
+	/// Check the forces against the derivatives of the energy, and the trace of the virial against the change in energy when scaling all positions.
+	fn check(topology: &Topology, particles: &[Particle]) {
+		let energy = |p: &[Particle]| evaluate(topology, p).0;
+		let close = |a: f64, b: f64| (a - b).abs() < 1e-6 * (1.0 + b.abs());
+		let h = 1e-6;
+		let (_, c) = evaluate(topology, particles);
+		for i in 0..particles.len() {
+			for axis in [Vector::unit_x(), Vector::unit_y(), Vector::unit_z()] {
+				let (mut plus, mut minus) = (particles.to_vec(), particles.to_vec());
+				plus[i].pos += axis * h;
+				minus[i].pos -= axis * h;
+				let force = -(energy(&plus) - energy(&minus)) / (2.0 * h);
+				assert!(close(c.force_bonded[i].dot(&axis), force), "The force on {} is {}, not {}", i, c.force_bonded[i].dot(&axis), force);
+			}
+		}
+
+		// The trace of the virial is the sum of r.f, which is minus the derivative of the energy to a scaling of all positions
+		let scaled = |scale: f64| -> Vec<Particle> {
+			particles.iter().cloned().map(|mut p| { p.pos = p.pos * scale; p }).collect()
+		};
+		let virial = -(energy(&scaled(1.0 + h)) - energy(&scaled(1.0 - h))) / (2.0 * h);
+		assert!(close(c.virial.trace(), virial), "The virial is {}, not {}", c.virial.trace(), virial);
+	}
+
+	#[test]
+	fn bond_forces_match_energy() {
+		for potential in [BondPotential::Harmonic{ k: 100.0, length: 0.8 }, BondPotential::Fene{ k: 30.0, max_length: 1.5 }] {
+			let mut topology = Topology::new();
+			topology.add_bond(Bond{ particles: [0, 1], potential });
+			topology.add_bond(Bond{ particles: [1, 2], potential });
+			check(&topology, &chain());
+		}
+	}
+
+	#[test]
+	fn angle_forces_match_energy() {
+		let mut topology = Topology::new();
+		topology.add_angle(Angle{ particles: [0, 1, 2], k: 50.0, angle: 1.9 });
+		topology.add_angle(Angle{ particles: [1, 2, 3], k: 20.0, angle: 2.5 });
+		check(&topology, &chain());
+	}
+
+	#[test]
+	fn dihedral_forces_match_energy() {
+		let potentials = [
+			DihedralPotential::Periodic{ k: 2.0, multiplicity: 3.0, phase: 0.3 },
+			DihedralPotential::RyckaertBellemans{ coefficients: [9.28, 12.16, -13.12, -3.06, 26.24, -31.5] },
+		];
+		for potential in potentials {
+			let mut topology = Topology::new();
+			topology.add_dihedral(Dihedral{ particles: [0, 1, 2, 3], potential });
+			check(&topology, &chain());
+		}
+	}
+
+	#[test]
+	fn fene_beyond_max_length_is_an_error() {
+		let fene = BondPotential::Fene{ k: 30.0, max_length: 1.5 };
+		assert!(fene.get(1.4).is_some());
+		assert!(fene.get(1.5).is_none() && fene.get(2.0).is_none());
+
+		let mut topology = Topology::new();
+		topology.add_bond(Bond{ particles: [0, 3], potential: fene });
+		let simbox = SimBox::new(Vector::new(10.0, 10.0, 10.0), false);
+		let particles = chain();
+		let result = topology.add_contributions(&simbox, &particles, &mut Contributions::new(particles.len()));
+		assert!(matches!(result, Err(BondError{ particles: [0, 3], .. })));
+	}
+}
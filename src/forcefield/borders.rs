@@ -1,89 +1,295 @@
+/*! Enforce borders of the system: walls at the faces of the box, and spherical or cylindrical containers.
 
-//! Enforce borders of the system
+Each wall has a potential that depends on the distance between the centre of a particle and the wall, and pushes the particle away from it.
+The virial of a wall is that of a pair interaction between the particle and the nearest point of the wall, so the pressure includes the force on the walls.
+Reflective walls have no potential, instead particles that pass them are reflected back with reflect().
+*/
 
-use crate::vectors::Vector;
-use crate::constants::{BORDER_X, BORDER_Y, BORDER_Z, BORDER_4_EPSILON, BORDER_RANGE};
+use crate::vectors::{Vector, Tensor};
+use crate::particles::Particle;
 
-/// Calculate the potential at a given range.
-fn get_potential_range(radius: f64, range: f64) -> f64 {
-	BORDER_4_EPSILON * (radius / range).powf(12.0)
+/// The potential of a wall, as a function of the distance between the centre of a particle and the wall.
+#[derive(Clone, Copy)]
+pub enum WallPotential {
+	/// epsilon_4 (radius / d)^12, using the radius of the particle, shifted to zero at the range beyond which it is cut off
+	Repulsive {
+		/// 4 times the repulsion strength
+		epsilon_4: f64,
+		/// The distance beyond which the potential is zero
+		range: f64,
+	},
+	/// The 9-3 Lennard-Jones potential of a wall of Lennard-Jones particles, epsilon (2/15 (sigma / d)^9 - (sigma / d)^3), shifted to zero at the cutoff
+	Lj93 {
+		/// The strength
+		epsilon: f64,
+		/// The size of the wall particles
+		sigma: f64,
+		/// The distance beyond which the potential is zero
+		cutoff: f64,
+	},
+	/// k / 2 (range - d)^2 within the range of the wall, which also pushes back particles that have passed the wall
+	Harmonic {
+		/// The force constant
+		k: f64,
+		/// The distance beyond which the potential is zero
+		range: f64,
+	},
+	/// No potential, particles that pass the wall are reflected back
+	Reflective,
 }
 
-/// Calculate the magnitude of the force at a given range.
-fn get_force_range(radius: f64, range: f64) -> f64 {
-	BORDER_4_EPSILON * radius.powf(12.0) * 12.0 * range.powf(-13.0)
+impl WallPotential {
+	/// Gets the energy of a particle at a distance from the wall, and the force pushing it away from the wall.
+	///
+	/// The radius is the radius of the particle, only used by the repulsive wall.
+	pub fn get(&self, radius: f64, distance: f64) -> (f64, f64) {
+		match *self {
+			WallPotential::Repulsive{ epsilon_4, range } if distance < range => (
+				epsilon_4 * ((radius / distance).powf(12.0) - (radius / range).powf(12.0)),
+				epsilon_4 * radius.powf(12.0) * 12.0 * distance.powf(-13.0),
+			),
+			WallPotential::Lj93{ epsilon, sigma, cutoff } if distance < cutoff => {
+				let energy = |d: f64| epsilon * (2.0 / 15.0 * (sigma / d).powi(9) - (sigma / d).powi(3));
+				(energy(distance) - energy(cutoff),
+				 epsilon * (6.0 / 5.0 * sigma.powi(9) * distance.powi(-10) - 3.0 * sigma.powi(3) * distance.powi(-4)))
+			}
+			WallPotential::Harmonic{ k, range } if distance < range => (k / 2.0 * (range - distance) * (range - distance), k * (range - distance)),
+			_ => (0.0, 0.0),
+		}
+	}
 }
 
-/** Gets the potential for a particle near the border.
+/// The shape of a container.
+#[derive(Clone, Copy)]
+pub enum Shape {
+	/// A sphere
+	Sphere,
+	/// An infinite cylinder along an axis, which should be periodic or have walls
+	Cylinder {
+		/// The index of the axis, 0 for x, 1 for y and 2 for z
+		axis: usize,
+	},
+}
 
-The radius is the radius of the particle.
-The position is the position of the particle.
-*/
-pub fn get_potential(radius: f64, position: &Vector) -> f64 {
-	//4e ( (s/r)^12 - (s/r)^6 )
-	// r is dist, s is radius, e is well depth
-  
-	let mut potential = 0.0;
-//TODO: cutoff
-	// TODO double check signs
-	if position.x < BORDER_RANGE {
-		potential += get_potential_range(radius, position.x);
-	} else if position.x > BORDER_X - BORDER_RANGE {
-		potential += get_potential_range(radius, BORDER_X - position.x);
-	}
-	if position.y < BORDER_RANGE {
-		potential += get_potential_range(radius, position.y);
-	} else if position.y > BORDER_Y - BORDER_RANGE {
-		potential += get_potential_range(radius, BORDER_Y - position.y);
-	}
-	if position.z < BORDER_RANGE {
-		potential += get_potential_range(radius, position.z);
-	} else if position.z > BORDER_Z - BORDER_RANGE {
-		potential += get_potential_range(radius, BORDER_Z - position.z);
-	}
-	potential
-//	let distance = total_distance - radius * 2_f64.powf(1.0/6.0);
-//
-//	let attraction = (radius / distance).powf(6.0);
-//	let repulsion = attraction * attraction;
-//
-//	return LJ_4_EPSILON * (repulsion - attraction); 
+/// A spherical or cylindrical container, with a wall on its surface.
+#[derive(Clone, Copy)]
+pub struct Container {
+	/// The shape
+	pub shape: Shape,
+	/// The centre of the sphere, or a point on the axis of the cylinder
+	pub centre: Vector,
+	/// The radius
+	pub radius: f64,
+	/// The potential of the wall
+	pub wall: WallPotential,
 }
 
-/** Gets the force on a particle near the border.
+impl Container {
+	/// Gets the distance of a position to the wall, positive inside the container, and the normal of the wall at the nearest point, pointing inwards.
+	pub fn distance(&self, position: &Vector) -> (f64, Vector) {
+		let mut offset = *position - self.centre;
+		if let Shape::Cylinder{ axis } = self.shape {
+			let unit = axis_unit(axis);
+			offset -= unit * offset.dot(&unit);
+		}
+		let length = offset.len();
+		// The normal is undefined at the centre, where no particle will be close enough to the wall to need it
+		let normal = if length > 0.0 { offset / -length } else { Vector::zero() };
+		(self.radius - length, normal)
+	}
 
-The radius is the radius of the particle.
-The position is the position of the particle.
-*/
-pub fn get_force(radius: f64, position: &Vector) -> Vector {
-	// 4e ( -12 s^12 r^-13 + 6 s^6 r^-7)
-	// 4e ( 6 s^6 (r^-7 - 2 s^6 r^-13 )
-	// 6 * 4e s^6 r^-7 (1 - 2 s^6 r^-6)
-
-	let mut force = Vector::zero();
-//TODO: cutoff
-	// TODO double check signs
-	if position.x < BORDER_RANGE {
-		force.x += get_force_range(radius, position.x);
-	} else if position.x > BORDER_X - BORDER_RANGE {
-		force.x -= get_force_range(radius, BORDER_X - position.x);
-	}
-	if position.y < BORDER_RANGE {
-		force.y += get_force_range(radius, position.y);
-	} else if position.y > BORDER_Y - BORDER_RANGE {
-		force.y -= get_force_range(radius, BORDER_Y - position.y);
-	}
-	if position.z < BORDER_RANGE {
-		force.z += get_force_range(radius, position.z);
-	} else if position.z > BORDER_Z - BORDER_RANGE {
-		force.z -= get_force_range(radius, BORDER_Z - position.z);
-	}
-	force
-
-//let s6 = radius.powf(6.0);
-//	let distance = total_distance - radius * 2_f64.powf(1.0/6.0);
-//
-//	return 6.0 * LJ_4_EPSILON * s6 * distance.powf(-7.0) * (1.0 - (2.0 * s6 * distance.powf(-6.0)));
+	/// The volume inside the container, where the length of a cylinder is the size of the box along its axis.
+	pub fn volume(&self, size: &Vector) -> f64 {
+		match self.shape {
+			Shape::Sphere => 4.0 / 3.0 * std::f64::consts::PI * self.radius.powi(3),
+			Shape::Cylinder{ axis } => std::f64::consts::PI * self.radius * self.radius * axis_unit(axis).dot(size),
+		}
+	}
+}
+
+/// The unit vector along an axis.
+fn axis_unit(axis: usize) -> Vector {
+	[Vector::unit_x(), Vector::unit_y(), Vector::unit_z()][axis]
+}
+
+/// The walls of the system, at the origin and at the size of the box along each axis, and a container.
+pub struct Borders {
+	/// The potential of the walls along each axis, None if the axis is periodic or open
+	pub walls: [Option<WallPotential>; 3],
+	/// The container, if any
+	pub container: Option<Container>,
+}
+
+impl Borders {
+	/// Walls with the same potential along all axes, without a container.
+	pub fn new(wall: WallPotential) -> Self {
+		Borders{ walls: [Some(wall); 3], container: None }
+	}
+
+	/// Gets the energy of a particle in the potential of the walls, the force on it, and the virial.
+	///
+	/// The size is the size of the box.
+	/// The radius is the radius of the particle.
+	/// The position is the position of the particle.
+	/// Returns (energy, force, virial).
+	pub fn get(&self, size: &Vector, radius: f64, position: &Vector) -> (f64, Vector, Tensor) {
+		let mut energy = 0.0;
+		let mut force = Vector::zero();
+		let mut virial = Tensor::zero();
+		let mut add = |wall: &WallPotential, distance: f64, normal: Vector| {
+			let (e, f) = wall.get(radius, distance);
+			energy += e;
+			force += normal * f;
+			virial += Tensor::outer(&(normal * distance), &(normal * f));
+		};
+
+		for (axis, wall) in self.walls.iter().enumerate() {
+			if let Some(wall) = wall {
+				let unit = axis_unit(axis);
+				let x = unit.dot(position);
+				add(wall, x, unit);
+				add(wall, unit.dot(size) - x, -unit);
+			}
+		}
+		if let Some(container) = &self.container {
+			let (distance, normal) = container.distance(position);
+			add(&container.wall, distance, normal);
+		}
+		(energy, force, virial)
+	}
+
+	/// Reflect a particle that has passed a reflective wall back, reversing its velocity normal to the wall.
+	///
+	/// The size is the size of the box.
+	pub fn reflect(&self, size: &Vector, particle: &mut Particle) {
+		for (axis, wall) in self.walls.iter().enumerate() {
+			if let Some(WallPotential::Reflective) = wall {
+				let unit = axis_unit(axis);
+				reflect(particle, unit.dot(&particle.pos), unit);
+				reflect(particle, unit.dot(size) - unit.dot(&particle.pos), -unit);
+			}
+		}
+		if let Some(container @ Container{ wall: WallPotential::Reflective, .. }) = &self.container {
+			let (distance, normal) = container.distance(&particle.pos);
+			reflect(particle, distance, normal);
+		}
+	}
+}
+
+/// Reflect a particle at a distance behind a wall with an inward normal back in front of it, and reverse its velocity if it is moving outwards.
+fn reflect(particle: &mut Particle, distance: f64, normal: Vector) {
+	if distance >= 0.0 {
+		return;
+	}
+	particle.pos += normal * (-2.0 * distance);
+	let v = particle.v.dot(&normal);
+	if v < 0.0 {
+		particle.v += normal * (-2.0 * v);
+	}
 }
 
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SIZE: Vector = Vector{ x: 4.0, y: 5.0, z: 6.0 };
+
+	/// Each wall potential with a potential, with the radius of the particle.
+	fn walls() -> [WallPotential; 3] {
+		[
+			WallPotential::Repulsive{ epsilon_4: 4.0, range: 1.5 },
+			WallPotential::Lj93{ epsilon: 2.0, sigma: 0.8, cutoff: 2.0 },
+			WallPotential::Harmonic{ k: 10.0, range: 1.0 },
+		]
+	}
+
+	/// Walls along x and z, and a container, with a potential for all of them.
+	fn borders(wall: WallPotential, shape: Shape, scale: f64) -> Borders {
+		Borders{
+			walls: [Some(wall), None, Some(wall)],
+			container: Some(Container{ shape, centre: SIZE * (scale / 2.0), radius: 2.2 * scale, wall }),
+		}
+	}
+
+	/// Positions close to several walls at once.
+	fn positions() -> [Vector; 3] {
+		[Vector::new(0.7, 2.5, 5.2), Vector::new(3.1, 1.2, 0.9), Vector::new(0.9, 3.9, 3.1)]
+	}
+
+	fn close(a: f64, b: f64) -> bool {
+		(a - b).abs() < 1e-6 * (1.0 + b.abs())
+	}
 
+	#[test]
+	fn forces_match_energies() {
+		let h = 1e-6;
+		for wall in walls() {
+			for shape in [Shape::Sphere, Shape::Cylinder{ axis: 1 }] {
+				let borders = borders(wall, shape, 1.0);
+				for pos in positions() {
+					let (_, force, _) = borders.get(&SIZE, 0.5, &pos);
+					for axis in 0..3 {
+						let unit = axis_unit(axis);
+						let energy = |pos: Vector| borders.get(&SIZE, 0.5, &pos).0;
+						let derivative = (energy(pos + unit * h) - energy(pos - unit * h)) / (2.0 * h);
+						assert!(close(force.dot(&unit), -derivative), "The force along {} is {}, not {}", axis, force.dot(&unit), -derivative);
+					}
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn virial_matches_scaling() {
+		// Scaling the box, the container and the positions changes the energy by minus the trace of the virial
+		let h = 1e-6;
+		for wall in walls() {
+			for shape in [Shape::Sphere, Shape::Cylinder{ axis: 1 }] {
+				for pos in positions() {
+					let (_, _, virial) = borders(wall, shape, 1.0).get(&SIZE, 0.5, &pos);
+					let energy = |scale: f64| borders(wall, shape, scale).get(&(SIZE * scale), 0.5, &(pos * scale)).0;
+					let derivative = (energy(1.0 + h) - energy(1.0 - h)) / (2.0 * h);
+					assert!(close(virial.trace(), -derivative), "The virial is {}, not {}", virial.trace(), -derivative);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn wall_virial_is_per_axis() {
+		// Without a container, scaling a single axis changes the energy by minus that diagonal element of the virial
+		let h = 1e-6;
+		for wall in walls() {
+			let borders = Borders::new(wall);
+			for pos in positions() {
+				let (_, _, virial) = borders.get(&SIZE, 0.5, &pos);
+				for (axis, diagonal) in [virial.x.x, virial.y.y, virial.z.z].into_iter().enumerate() {
+					let unit = axis_unit(axis);
+					let stretch = |scale: f64, v: Vector| v + unit * (unit.dot(&v) * (scale - 1.0));
+					let energy = |scale: f64| borders.get(&stretch(scale, SIZE), 0.5, &stretch(scale, pos)).0;
+					let derivative = (energy(1.0 + h) - energy(1.0 - h)) / (2.0 * h);
+					assert!(close(diagonal, -derivative), "The virial along {} is {}, not {}", axis, diagonal, -derivative);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn reflective_walls_reflect() {
+		let mut borders = Borders::new(WallPotential::Reflective);
+		borders.container = Some(Container{ shape: Shape::Cylinder{ axis: 2 }, centre: SIZE / 2.0, radius: 2.0, wall: WallPotential::Reflective });
+		assert_eq!(borders.get(&SIZE, 0.5, &Vector::new(0.1, 2.5, 0.1)).0, 0.0);
+
+		let mut p = Particle::new(&Vector::new(2.0, 2.5, -0.2), 0.5, 1.0, 0.0);
+		p.v = Vector::new(0.0, 0.0, -1.0);
+		borders.reflect(&SIZE, &mut p);
+		assert!((p.pos - Vector::new(2.0, 2.5, 0.2)).len() < 1e-12 && p.v.z == 1.0);
+
+		// Inside the box, but 0.2 outside the cylinder
+		let mut p = Particle::new(&Vector::new(2.0, 4.7, 3.0), 0.5, 1.0, 0.0);
+		p.v = Vector::new(1.0, 2.0, 0.0);
+		borders.reflect(&SIZE, &mut p);
+		assert!((p.pos - Vector::new(2.0, 4.3, 3.0)).len() < 1e-12);
+		assert!((p.v - Vector::new(1.0, -2.0, 0.0)).len() < 1e-12);
+	}
+}
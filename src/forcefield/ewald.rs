@@ -0,0 +1,310 @@
+/*! Ewald summation of the electrostatic interactions in a periodic box.
+
+The sum of Coulomb's law over all periodic images only converges conditionally, so each charge is screened by a Gaussian charge distribution of the opposite sign.
+The screened interactions are short-ranged and summed in real space (the erfc term),
+while the screening distributions are smooth and summed in reciprocal space.
+Finally, the interaction of each screening distribution with its own charge (the self-energy) is subtracted,
+and a system with a net charge is neutralized by a uniform background charge.
+
+The split between the two sums is set by alpha, the inverse width of the Gaussians.
+*/
+
+use crate::constants::PERMITTIVITY_VACUUM;
+use crate::complex::Complex;
+use crate::particles::Particle;
+use crate::simbox::SimBox;
+use crate::vectors::{Vector, Tensor};
+
+use std::f64::consts::PI;
+
+/** The complementary error function, erfc(x) = 1 - erf(x).
+
+Below 2 this sums the series erf(x) = 2/sqrt(pi) x exp(-x^2) sum_n (2x^2)^n / (1 3 5 ... (2n+1)), whose terms are all positive,
+and above it evaluates the continued fraction erfc(x) = exp(-x^2)/sqrt(pi) / (x + (1/2) / (x + 1 / (x + (3/2) / (x + ...)))).
+The absolute error is below 1e-15 everywhere, and the relative error below 1e-12.
+*/
+pub fn erfc(x: f64) -> f64 {
+	let z = x.abs();
+	let ans = if z < 2.0 {
+		let (mut term, mut sum) = (1.0, 1.0);
+		let mut n = 0.0;
+		while term > 1e-17 * sum {
+			n += 1.0;
+			term *= 2.0 * z * z / (2.0 * n + 1.0);
+			sum += term;
+		}
+		1.0 - 2.0 / PI.sqrt() * z * (-z * z).exp() * sum
+	} else {
+		let mut fraction = z;
+		for n in (1..=40).rev() {
+			fraction = z + n as f64 / 2.0 / fraction;
+		}
+		(-z * z).exp() / PI.sqrt() / fraction
+	};
+	if x >= 0.0 { ans } else { 2.0 - ans }
+}
+
+/// The factor converting a product of charges (in units of the elementary charge) divided by a distance into an energy.
+pub(crate) fn get_normalisation(elementary_charge: f64) -> f64 {
+	elementary_charge * elementary_charge / (4.0 * PI * PERMITTIVITY_VACUUM)
+}
+
+/// Gets the real space part of the electrostatic energy between two particles, with screening parameter alpha.
+pub fn get_real_energy(alpha: f64, charges: (f64, f64), distance: f64, elementary_charge: f64) -> f64 {
+	charges.0 * charges.1 * get_normalisation(elementary_charge) * erfc(alpha * distance) / distance
+}
+
+/// Gets the magnitude of the real space part of the electrostatic force between two particles, with screening parameter alpha, positive if they repel.
+pub fn get_real_force(alpha: f64, charges: (f64, f64), distance: f64, elementary_charge: f64) -> f64 {
+	let ar = alpha * distance;
+	let gaussian = 2.0 * ar / PI.sqrt() * (-ar * ar).exp();
+	charges.0 * charges.1 * get_normalisation(elementary_charge) * (erfc(ar) + gaussian) / (distance * distance)
+}
+
+/// Gets the part of the electrostatic energy between two particles that is included in the reciprocal sum, with screening parameter alpha.
+///
+/// This is subtracted for pairs whose electrostatic interactions are excluded, as the reciprocal sum includes all pairs.
+pub fn get_reciprocal_pair_energy(alpha: f64, charges: (f64, f64), distance: f64, elementary_charge: f64) -> f64 {
+	charges.0 * charges.1 * get_normalisation(elementary_charge) * (1.0 - erfc(alpha * distance)) / distance
+}
+
+/// Gets the magnitude of the force of get_reciprocal_pair_energy(), positive if they repel.
+pub fn get_reciprocal_pair_force(alpha: f64, charges: (f64, f64), distance: f64, elementary_charge: f64) -> f64 {
+	let ar = alpha * distance;
+	let gaussian = 2.0 * ar / PI.sqrt() * (-ar * ar).exp();
+	charges.0 * charges.1 * get_normalisation(elementary_charge) * (1.0 - erfc(ar) - gaussian) / (distance * distance)
+}
+
+/// Gets the virial of a reciprocal space term with a given energy and wave vector, -dE/d(strain).
+pub fn get_reciprocal_virial(energy: f64, k: &Vector, alpha: f64) -> Tensor {
+	let k_sq = k.sqlen();
+	Tensor::diagonal(energy) + Tensor::outer(k, k) * (-2.0 * energy * (1.0 + k_sq / (4.0 * alpha * alpha)) / k_sq)
+}
+
+/// Gets the energy of the neutralizing background, with screening parameter alpha. Its virial is the energy along the diagonal, as it scales with 1/V.
+pub fn get_background_energy(alpha: f64, p: &[Particle], volume: f64, elementary_charge: f64) -> f64 {
+	let total_charge: f64 = p.iter().map(|particle| particle.q).sum();
+	-get_normalisation(elementary_charge) * PI * total_charge * total_charge / (2.0 * volume * alpha * alpha)
+}
+
+/// Gets the self-energy of each particle, and its share of the energy of the neutralizing background, with screening parameter alpha.
+pub fn get_self_energies(alpha: f64, p: &[Particle], volume: f64, elementary_charge: f64) -> Vec<f64> {
+	let normalisation = get_normalisation(elementary_charge);
+	let total_charge: f64 = p.iter().map(|particle| particle.q).sum();
+	p.iter().map(|particle| {
+		-normalisation * alpha / PI.sqrt() * particle.q * particle.q
+		- normalisation * PI * total_charge * particle.q / (2.0 * volume * alpha * alpha)
+	}).collect()
+}
+
+/// A wave vector of the reciprocal sum, with its weight 2 * 4pi/(V k^2) * exp(-k^2 / (4 alpha^2)).
+struct WaveVector {
+	n: [i64; 3],
+	k: Vector,
+	weight: f64,
+}
+
+/// The parameters of the Ewald sum, and the wave vectors of the reciprocal sum.
+pub struct Ewald {
+	/// The inverse width of the screening charge distributions
+	pub alpha: f64,
+	/// The distance beyond which the real space sum is cut off
+	pub cutoff: f64,
+	/// The largest number of wavelengths per box length along each axis in the reciprocal sum
+	pub kmax: [usize; 3],
+	wave_vectors: Vec<WaveVector>,
+	wave_vectors_size: Vector,
+}
+
+impl Ewald {
+	/// Create an Ewald sum with the given parameters. The wave vectors are calculated on the first use.
+	pub fn new(alpha: f64, cutoff: f64, kmax: [usize; 3]) -> Self {
+		Ewald{
+			alpha,
+			cutoff,
+			kmax,
+			wave_vectors: Vec::new(),
+			wave_vectors_size: Vector::zero(),
+		}
+	}
+
+	/// Create an Ewald sum for a given real space cutoff and box size, where the terms neglected in both sums are at most roughly accuracy times their largest term.
+	///
+	/// alpha is chosen so that erfc(alpha * cutoff) is about the accuracy, and kmax so that the weight of the neglected wave vectors is as well.
+	pub fn from_accuracy(accuracy: f64, cutoff: f64, size: &Vector) -> Self {
+		let s = (-accuracy.ln()).sqrt();
+		let alpha = s / cutoff;
+		let kmax = [size.x, size.y, size.z].map(|l| (alpha * l * s / PI).ceil().max(1.0) as usize);
+		Self::new(alpha, cutoff, kmax)
+	}
+
+	/// Gets the real space part of the electrostatic energy between two particles.
+	///
+	/// The charges are given in units of the elementary charge, and the energy is zero beyond the cutoff.
+	pub fn get_real_energy(&self, charges: (f64, f64), distance: f64, elementary_charge: f64) -> f64 {
+		if distance > self.cutoff {
+			return 0.0;
+		}
+		get_real_energy(self.alpha, charges, distance, elementary_charge)
+	}
+
+	/// Gets the magnitude of the real space part of the electrostatic force between two particles, positive if they repel.
+	///
+	/// The charges are given in units of the elementary charge, and the force is zero beyond the cutoff.
+	pub fn get_real_force(&self, charges: (f64, f64), distance: f64, elementary_charge: f64) -> f64 {
+		if distance > self.cutoff {
+			return 0.0;
+		}
+		get_real_force(self.alpha, charges, distance, elementary_charge)
+	}
+
+	/// Recalculate the wave vectors, if the box has changed since they were last calculated.
+	fn update_wave_vectors(&mut self, size: &Vector) {
+		if !self.wave_vectors.is_empty() && (*size - self.wave_vectors_size).sqlen() == 0.0 {
+			return;
+		}
+
+		let volume = size.x * size.y * size.z;
+		let [kx, ky, kz] = self.kmax.map(|k| k as i64);
+		// Only half of the wave vectors are needed, as k and -k contribute equally
+		self.wave_vectors.clear();
+		for nx in 0..=kx {
+			for ny in -ky..=ky {
+				for nz in -kz..=kz {
+					if nx == 0 && (ny < 0 || (ny == 0 && nz <= 0)) {
+						continue;
+					}
+					let k = Vector::new(nx as f64 / size.x, ny as f64 / size.y, nz as f64 / size.z) * (2.0 * PI);
+					let k_sq = k.sqlen();
+					let weight = 2.0 * 4.0 * PI / (volume * k_sq) * (-k_sq / (4.0 * self.alpha * self.alpha)).exp();
+					self.wave_vectors.push(WaveVector{ n: [nx, ny, nz], k, weight });
+				}
+			}
+		}
+		self.wave_vectors_size = *size;
+	}
+
+	/// Gets the reciprocal space forces on, and energies of, each particle, including the self-energy and the energy of the neutralizing background,
+	/// and the virial of the reciprocal space sum and the background.
+	///
+	/// The real space part is not included, see get_real_force() and get_real_energy().
+	/// Returns (forces, energies, virial).
+	pub fn get_reciprocal(&mut self, simbox: &SimBox, p: &[Particle], elementary_charge: f64) -> (Vec<Vector>, Vec<f64>, Tensor) {
+		self.update_wave_vectors(&simbox.size);
+		let normalisation = get_normalisation(elementary_charge);
+		let size = [simbox.size.x, simbox.size.y, simbox.size.z];
+
+		// exp(i 2pi n x / L) for each particle, axis and 0 <= n <= kmax, negative n follow from the conjugate
+		let phases: Vec<[Vec<Complex>; 3]> = p.iter().map(|particle| {
+			let pos = [particle.pos.x, particle.pos.y, particle.pos.z];
+			[0, 1, 2].map(|axis| {
+				let base = Complex::from_phase(2.0 * PI * pos[axis] / size[axis]);
+				let mut powers = vec![Complex::new(1.0, 0.0)];
+				for n in 1..=self.kmax[axis] {
+					powers.push(powers[n - 1] * base);
+				}
+				powers
+			})
+		}).collect();
+		let phase = |i: usize, n: &[i64; 3]| -> Complex {
+			let mut z = Complex::new(1.0, 0.0);
+			for axis in 0..3 {
+				let power = phases[i][axis][n[axis].unsigned_abs() as usize];
+				z = z * if n[axis] < 0 { power.conj() } else { power };
+			}
+			z
+		};
+
+		let mut forces = vec![Vector::zero(); p.len()];
+		let mut energies = vec![0.0; p.len()];
+		let mut virial = Tensor::zero();
+		for wave in self.wave_vectors.iter() {
+			// The structure factor S(k) = sum_j q_j exp(i k.r_j)
+			let mut structure = Complex::zero();
+			for (i, particle) in p.iter().enumerate() {
+				structure += phase(i, &wave.n) * particle.q;
+			}
+			let structure = structure.conj();
+			virial += get_reciprocal_virial(normalisation * wave.weight * structure.sqabs() / 2.0, &wave.k, self.alpha);
+
+			for (i, particle) in p.iter().enumerate() {
+				let z = phase(i, &wave.n) * structure;
+				let scale = normalisation * wave.weight * particle.q;
+				energies[i] += scale * z.re / 2.0;
+				forces[i] += wave.k * (scale * z.im);
+			}
+		}
+
+		for (energy, self_energy) in energies.iter_mut().zip(get_self_energies(self.alpha, p, simbox.volume(), elementary_charge)) {
+			*energy += self_energy;
+		}
+		virial += Tensor::diagonal(get_background_energy(self.alpha, p, simbox.volume(), elementary_charge));
+
+		(forces, energies, virial)
+	}
+
+	/// Gets the total electrostatic energy of the system.
+	///
+	/// The real space sum uses the minimum-image convention, so the cutoff should be at most half the box size.
+	pub fn get_energy(&mut self, simbox: &SimBox, p: &[Particle], elementary_charge: f64) -> f64 {
+		let mut energy: f64 = self.get_reciprocal(simbox, p, elementary_charge).1.iter().sum();
+		for i in 0..p.len() {
+			for j in (i+1)..p.len() {
+				let distance = simbox.separation(&p[i].pos, &p[j].pos).len();
+				energy += self.get_real_energy((p[i].q, p[j].q), distance, elementary_charge);
+			}
+		}
+		energy
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The Madelung constant of NaCl, for the nearest neighbor distance.
+	const MADELUNG_NACL: f64 = 1.747564594633;
+
+	/// Rock salt, a simple cubic lattice of alternating unit charges with a unit nearest neighbor distance, in a periodic box of a number of conventional cells.
+	///
+	/// With an elementary charge of sqrt(4 pi e0) the energy of a pair is q_i q_j / r, so the energy per ion pair is minus the Madelung constant.
+	/// Returns the particles, the box and the elementary charge.
+	pub(crate) fn rock_salt(cells: usize) -> (Vec<Particle>, SimBox, f64) {
+		let mut particles = Vec::new();
+		for x in 0..2 * cells {
+			for y in 0..2 * cells {
+				for z in 0..2 * cells {
+					let charge = if (x + y + z) % 2 == 0 { 1.0 } else { -1.0 };
+					particles.push(Particle::new(&Vector::new(x as f64, y as f64, z as f64), 0.5, 1.0, charge));
+				}
+			}
+		}
+		let size = 2.0 * cells as f64;
+		(particles, SimBox::new(Vector::new(size, size, size), true), (4.0 * PI * PERMITTIVITY_VACUUM).sqrt())
+	}
+
+	/// The Madelung constant following from the energy of rock salt.
+	pub(crate) fn madelung(energy: f64, particles: usize) -> f64 {
+		-energy / (particles as f64 / 2.0)
+	}
+
+	#[test]
+	fn erfc_matches_reference_values() {
+		let reference = [(0.0, 1.0), (0.1, 0.8875370839817152), (0.5, 0.4795001221869535), (1.0, 0.15729920705028513), (1.9, 0.007209570764742524),
+			(2.0, 0.004677734981047266), (2.1, 0.002979466656333839), (3.0, 2.209049699858544e-5), (5.0, 1.537459794428035e-12), (-1.0, 1.8427007929497148)];
+		for (x, value) in reference {
+			assert!((erfc(x) - value).abs() <= 1e-15 + 1e-12 * value, "erfc({}) = {}, not {}", x, erfc(x), value);
+		}
+	}
+
+	#[test]
+	fn madelung_constant_of_rock_salt() {
+		let (particles, simbox, elementary_charge) = rock_salt(2);
+		// The neglected terms are about the accuracy times the largest term, which is of order one, so the constant is within the accuracy
+		for accuracy in [1e-5, 1e-7, 1e-10] {
+			let mut ewald = Ewald::from_accuracy(accuracy, simbox.size.x / 2.0, &simbox.size);
+			let error = madelung(ewald.get_energy(&simbox, &particles, elementary_charge), particles.len()) - MADELUNG_NACL;
+			assert!(error.abs() < accuracy, "error {} at accuracy {}", error, accuracy);
+		}
+	}
+}
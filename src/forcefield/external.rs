@@ -0,0 +1,102 @@
+/*! External fields acting on each particle separately: a uniform electric field, which may oscillate in time, and gravity.
+
+The energy in a uniform field depends on the absolute position of a particle, so it uses the unwrapped position in a periodic box.
+The forces of external fields are not part of the virial, as they do not act between the particles.
+A uniform magnetic field gives a force that depends on the velocity, which is handled by the Boris integrator instead, see integrators::Boris.
+*/
+
+use crate::vectors::Vector;
+
+/// A uniform electric field, E(t) = constant + amplitude cos(frequency t + phase).
+#[derive(Clone, Copy)]
+pub struct ElectricField {
+	/// The constant part of the field
+	pub constant: Vector,
+	/// The amplitude of the oscillating part of the field
+	pub amplitude: Vector,
+	/// The angular frequency of the oscillating part
+	pub frequency: f64,
+	/// The phase of the oscillating part, in radians
+	pub phase: f64,
+}
+
+impl ElectricField {
+	/// A constant electric field.
+	pub fn new(field: Vector) -> Self {
+		ElectricField{ constant: field, amplitude: Vector::zero(), frequency: 0.0, phase: 0.0 }
+	}
+
+	/// The field at a time.
+	pub fn at(&self, t: f64) -> Vector {
+		self.constant + self.amplitude * (self.frequency * t + self.phase).cos()
+	}
+}
+
+/// The external fields acting on the particles.
+#[derive(Clone, Copy)]
+pub struct External {
+	/// The electric field, acting on the charge of each particle
+	pub electric: ElectricField,
+	/// The gravitational accelleration, acting on the mass of each particle
+	pub gravity: Vector,
+}
+
+impl External {
+	/// No external fields.
+	pub fn new() -> Self {
+		External{ electric: ElectricField::new(Vector::zero()), gravity: Vector::zero() }
+	}
+
+	/// Gets the energy of a particle in the external fields, and the force on it.
+	///
+	/// The time is the time at which the fields are evaluated.
+	/// The position is the unwrapped position of the particle.
+	/// The charge is given in units of the elementary charge.
+	pub fn get(&self, t: f64, position: &Vector, charge: f64, mass: f64, elementary_charge: f64) -> (f64, Vector) {
+		let electric = self.electric.at(t) * (charge * elementary_charge);
+		let gravity = self.gravity * mass;
+		let force = electric + gravity;
+		(-force.dot(position), force)
+	}
+}
+
+impl Default for External {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+/// The Lorentz force q v x B on a charged particle moving through a magnetic field, with the charge in units of the elementary charge.
+pub fn get_lorentz_force(charge: f64, velocity: &Vector, field: &Vector, elementary_charge: f64) -> Vector {
+	velocity.cross(field) * (charge * elementary_charge)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn force_is_minus_the_gradient_of_the_energy() {
+		let external = External{
+			electric: ElectricField{ constant: Vector::new(0.5, 0.0, -1.0), amplitude: Vector::new(0.0, 2.0, 0.0), frequency: 3.0, phase: 0.5 },
+			gravity: Vector::new(0.0, 0.0, -9.8),
+		};
+		let (t, position, h) = (0.7, Vector::new(1.0, 2.0, 3.0), 1e-6);
+		let (_, force) = external.get(t, &position, -2.0, 1.5, 0.3);
+		for unit in [Vector::unit_x(), Vector::unit_y(), Vector::unit_z()] {
+			let energy = |pos: Vector| external.get(t, &pos, -2.0, 1.5, 0.3).0;
+			let derivative = (energy(position + unit * h) - energy(position - unit * h)) / (2.0 * h);
+			assert!((force.dot(&unit) + derivative).abs() < 1e-8);
+		}
+		let field = Vector::new(0.5, 2.0 * (3.0 * t + 0.5).cos(), -1.0);
+		assert!((force - (field * (-2.0 * 0.3) + Vector::new(0.0, 0.0, -9.8 * 1.5))).len() < 1e-12);
+	}
+
+	#[test]
+	fn lorentz_force_is_normal_to_the_velocity() {
+		let (velocity, field) = (Vector::new(1.0, 2.0, -0.5), Vector::new(0.3, -1.0, 2.0));
+		let force = get_lorentz_force(1.5, &velocity, &field, 2.0);
+		assert!(force.dot(&velocity).abs() < 1e-12 && force.dot(&field).abs() < 1e-12);
+		assert!((get_lorentz_force(1.0, &Vector::unit_x(), &Vector::unit_y(), 1.0) - Vector::unit_z()).len() < 1e-12);
+	}
+}
@@ -1,10 +1,425 @@
-
 //! All functions related to the forcefield of the system, including the energies, forces, and temperature normalization
 
 pub mod temperature;
+pub mod pressure;
 pub mod vanderwaals;
+pub mod potentials;
+pub mod tabulated;
 pub mod electrostatic;
+pub mod ewald;
+pub mod pme;
 pub mod borders;
+pub mod external;
+pub mod bonded;
+
+use crate::particles::Particle;
+use crate::vectors::{Vector, Tensor};
+use crate::log_data::DataLog;
+use crate::units::Dimension;
+use crate::constants::{LJ_4_EPSILON, ELEMENTARY_CHARGE, BORDER_X, BORDER_Y, BORDER_Z, BORDER_4_EPSILON, BORDER_RANGE};
+use crate::simbox::SimBox;
+use crate::neighbors::NeighborList;
+use crate::species::{SpeciesTable, Species, MixingRule};
+use borders::{Borders, WallPotential};
+use external::External;
+use bonded::{Topology, BondError};
+use vanderwaals::Cutoff;
+use ewald::Ewald;
+use pme::Pme;
+
+/// The forces and potential energies of each particle, split up by term, as calculated by the last evaluation of the forcefield.
+///
+/// The energy of each pair interaction is split equally between its two particles, so the energies of all particles add up to the total potential energy.
+#[derive(Default)]
+pub struct Contributions {
+	/// The electrostatic force on each particle
+	pub force_electric: Vec<Vector>,
+	/// The van der Waals force on each particle
+	pub force_vdw: Vec<Vector>,
+	/// The force of the bonded terms on each particle
+	pub force_bonded: Vec<Vector>,
+	/// The force of the external fields on each particle
+	pub force_external: Vec<Vector>,
+	/// The total force on each particle
+	pub force_total: Vec<Vector>,
+	/// The electrostatic energy of each particle
+	pub energy_electric: Vec<f64>,
+	/// The van der Waals energy of each particle
+	pub energy_vdw: Vec<f64>,
+	/// The energy of each particle in the potential of the borders
+	pub energy_border: Vec<f64>,
+	/// The energy of each particle in the external fields
+	pub energy_external: Vec<f64>,
+	/// The energy of the bonds of each particle
+	pub energy_bond: Vec<f64>,
+	/// The energy of the angles of each particle
+	pub energy_angle: Vec<f64>,
+	/// The energy of the dihedrals of each particle
+	pub energy_dihedral: Vec<f64>,
+	/// The virial tensor of the interactions between the particles, the sum of r_ij f_ij over all pairs and the bonded terms
+	pub virial: Tensor,
+	/// The virial tensor of the walls, the sum of the distance to each wall times its force
+	pub virial_wall: Tensor,
+}
+
+impl Contributions {
+	/// Create empty contributions for a number of particles.
+	fn new(particles: usize) -> Self {
+		Contributions{
+			force_electric: vec![Vector::zero(); particles],
+			force_vdw: vec![Vector::zero(); particles],
+			force_bonded: vec![Vector::zero(); particles],
+			force_external: vec![Vector::zero(); particles],
+			force_total: vec![Vector::zero(); particles],
+			energy_electric: vec![0.0; particles],
+			energy_vdw: vec![0.0; particles],
+			energy_border: vec![0.0; particles],
+			energy_external: vec![0.0; particles],
+			energy_bond: vec![0.0; particles],
+			energy_angle: vec![0.0; particles],
+			energy_dihedral: vec![0.0; particles],
+			virial: Tensor::zero(),
+			virial_wall: Tensor::zero(),
+		}
+	}
+
+	/// The potential energy of a particle, the sum of its energies of all terms.
+	pub fn get_potential_energy(&self, i: usize) -> f64 {
+		self.energy_electric[i] + self.energy_vdw[i] + self.energy_border[i] + self.energy_external[i] + self.energy_bond[i] + self.energy_angle[i] + self.energy_dihedral[i]
+	}
+}
+
+/// The method used to calculate the electrostatic interactions.
+pub enum Electrostatics {
+	/// No electrostatic interactions
+	Off,
+	/// Coulomb's law between each pair, only valid without periodic boundaries and without a neighbor list
+	Coulomb,
+	/// Ewald summation, for a periodic box
+	Ewald(Ewald),
+	/// Particle-mesh Ewald summation, for large systems in a periodic box
+	Pme(Pme),
+}
+
+/// The set of force terms acting on the particles.
+pub struct ForceField {
+	/// Whether to include the van der Waals interactions, the pair potentials of the species
+	pub vanderwaals: bool,
+	/// How to calculate the electrostatic interactions
+	pub electrostatic: Electrostatics,
+	/// The box containing the system
+	pub simbox: SimBox,
+	/// The walls and container of the system, if any
+	pub borders: Option<Borders>,
+	/// The external fields, if any
+	pub external: Option<External>,
+	/// The neighbor list, if any. Without a list all pairs interact, with a list pairs beyond its cutoff are ignored.
+	pub neighbors: Option<NeighborList>,
+	/// The cutoff of the pair potentials, if any
+	pub cutoff: Option<Cutoff>,
+	/// The species of the particles, with the pair potential of each pair of species
+	pub species: SpeciesTable,
+	/// The bonded terms, and the pairs of bonded particles whose non-bonded interactions are excluded or scaled
+	pub topology: Topology,
+	/// The elementary charge, in which the charges of the particles are given
+	pub elementary_charge: f64,
+	/// The time at which the time-dependent external fields are evaluated by compute()
+	pub t: f64,
+	/// The contributions of each term, as calculated by the last call to compute()
+	pub contributions: Contributions,
+}
+
+impl ForceField {
+	/// Create a new forcefield, with all force terms enabled and the default parameters from constants.
+	pub fn new() -> Self {
+		ForceField{
+			vanderwaals: true,
+			electrostatic: Electrostatics::Coulomb,
+			simbox: SimBox::new(Vector::new(BORDER_X, BORDER_Y, BORDER_Z), false),
+			borders: Some(Borders::new(WallPotential::Repulsive{
+				epsilon_4: BORDER_4_EPSILON,
+				range: BORDER_RANGE,
+			})),
+			external: None,
+			neighbors: None,
+			cutoff: None,
+			species: default_species(),
+			topology: Topology::new(),
+			elementary_charge: ELEMENTARY_CHARGE,
+			t: 0.0,
+			contributions: Contributions::default(),
+		}
+	}
+
+	/// Add the series logged by log() to a DataLog.
+	pub fn add_series(&self, data: &mut DataLog) {
+		data.add_particle_vector_series("force_electric", Dimension::Force);
+		data.add_particle_vector_series("force_vdw", Dimension::Force);
+		data.add_particle_vector_series("force_bonded", Dimension::Force);
+		data.add_particle_vector_series("force_external", Dimension::Force);
+		data.add_particle_vector_series("force_total", Dimension::Force);
+		data.add_particle_series("energy_electric", Dimension::Energy);
+		data.add_particle_series("energy_vdw", Dimension::Energy);
+		data.add_particle_series("energy_border", Dimension::Energy);
+		data.add_particle_series("energy_external", Dimension::Energy);
+		data.add_particle_series("energy_bond", Dimension::Energy);
+		data.add_particle_series("energy_angle", Dimension::Energy);
+		data.add_particle_series("energy_dihedral", Dimension::Energy);
+		data.add_global_series("energy_tail", Dimension::Energy);
+	}
+
+	/// Log the contributions of the last call to compute(), for the last time step in data.
+	///
+	/// The tail correction is shared equally by all particles in energy_total, so the global energy_total is the total potential energy.
+	pub fn log(&self, p: &[Particle], data: &mut DataLog) {
+		let tail = self.get_tail_corrections(p).0;
+		data.global.insert_into("energy_tail", tail);
+
+		let c = &self.contributions;
+		for i in 0..c.force_total.len() {
+			data.insert_particle_vector_len("force_electric", i, c.force_electric[i]);
+			data.insert_particle_vector_len("force_vdw", i, c.force_vdw[i]);
+			data.insert_particle_vector_len("force_bonded", i, c.force_bonded[i]);
+			data.insert_particle_vector_len("force_external", i, c.force_external[i]);
+			data.insert_particle_vector_len("force_total", i, c.force_total[i]);
+			data.add_to_particle_add("energy_electric", i, c.energy_electric[i]);
+			data.add_to_particle_add("energy_vdw", i, c.energy_vdw[i]);
+			data.add_to_particle_add("energy_border", i, c.energy_border[i]);
+			data.add_to_particle_add("energy_external", i, c.energy_external[i]);
+			data.add_to_particle_add("energy_bond", i, c.energy_bond[i]);
+			data.add_to_particle_add("energy_angle", i, c.energy_angle[i]);
+			data.add_to_particle_add("energy_dihedral", i, c.energy_dihedral[i]);
+			data.add_to_particle_add("energy_total", i, c.get_potential_energy(i) + tail / p.len() as f64);
+		}
+	}
+
+	/// Calculate the forces on all particles at their current positions, and store the resulting accellerations.
+	///
+	/// The contributions of each term are stored in contributions.
+	/// Fails if a bond is stretched beyond the maximum length of its potential, leaving the accellerations and contributions unchanged.
+	pub fn compute(&mut self, p: &mut [Particle]) -> Result<(), BondError> {
+		let mut c = Contributions::new(p.len());
+
+		if let Some(list) = &mut self.neighbors {
+			list.update(&self.simbox, p);
+		}
+
+		match &self.neighbors {
+			Some(list) => {
+				let cutoff_sq = list.cutoff * list.cutoff;
+				for &(i, j) in list.pairs() {
+					self.pair(p, i, j, Some(cutoff_sq), &mut c);
+				}
+			}
+			None => {
+				// Iterate over each pair of particles
+				for i in 0..p.len() {
+					for j in (i+1)..(p.len()) {
+						self.pair(p, i, j, None, &mut c);
+					}
+				}
+			}
+		}
+
+		let reciprocal = match &mut self.electrostatic {
+			Electrostatics::Ewald(ewald) => Some(ewald.get_reciprocal(&self.simbox, p, self.elementary_charge)),
+			Electrostatics::Pme(pme) => Some(pme.get_reciprocal(&self.simbox, p, self.elementary_charge)),
+			_ => None,
+		};
+		if let Some((forces, energies, virial)) = reciprocal {
+			for (i, (force, energy)) in forces.into_iter().zip(energies).enumerate() {
+				c.force_electric[i] += force;
+				c.force_total[i] += force;
+				c.energy_electric[i] += energy;
+			}
+			c.virial += virial;
+		}
+
+		// The reciprocal sum includes all pairs, so remove it for the pairs whose electrostatic interactions are excluded or scaled
+		let alpha = match &self.electrostatic {
+			Electrostatics::Ewald(ewald) => Some(ewald.alpha),
+			Electrostatics::Pme(pme) => Some(pme.alpha),
+			_ => None,
+		};
+		if let Some(alpha) = alpha {
+			for (i, j, (_, scale)) in self.topology.scaled_pairs() {
+				let separation = self.simbox.separation(&p[i].pos, &p[j].pos);
+				let distance = separation.len();
+				let dir = separation / distance;
+				let charges = (p[i].q, p[j].q);
+				let f = -(1.0 - scale) * ewald::get_reciprocal_pair_force(alpha, charges, distance, self.elementary_charge);
+				let v = -(1.0 - scale) * ewald::get_reciprocal_pair_energy(alpha, charges, distance, self.elementary_charge);
+				c.force_electric[i] += dir * (-f);
+				c.force_electric[j] += dir * f;
+				c.force_total[i] += dir * (-f);
+				c.force_total[j] += dir * f;
+				c.virial += Tensor::outer(&(-separation), &(dir * (-f)));
+				for k in [i, j] {
+					c.energy_electric[k] += v / 2.0;
+				}
+			}
+		}
+
+		self.topology.add_contributions(&self.simbox, p, &mut c)?;
+
+		for i in 0..p.len() {
+			if let Some(borders) = &self.borders {
+				let (energy, force, virial) = borders.get(&self.simbox.size, p[i].r, &p[i].pos);
+				c.force_total[i] += force;
+				c.energy_border[i] = energy;
+				c.virial_wall += virial;
+			}
+			if let Some(external) = &self.external {
+				let (energy, force) = external.get(self.t, &self.simbox.unwrapped(&p[i]), p[i].q, p[i].m, self.elementary_charge);
+				c.force_external[i] = force;
+				c.force_total[i] += force;
+				c.energy_external[i] = energy;
+			}
+
+			p[i].a = c.force_total[i] / p[i].m;
+		}
+
+		self.contributions = c;
+		Ok(())
+	}
+
+	/// The largest distance over which pairs of particles interact, through the neighbor list, the cutoff or the real space part of the Ewald sum.
+	///
+	/// Along a periodic axis the box must be at least twice this size, so each pair only interacts through its nearest image.
+	/// Zero if none of these apply.
+	pub fn get_range(&self) -> f64 {
+		let neighbors = self.neighbors.as_ref().map_or(0.0, |n| n.cutoff + n.skin);
+		let cutoff = self.cutoff.as_ref().map_or(0.0, |c| c.radius);
+		let ewald = match &self.electrostatic {
+			Electrostatics::Ewald(ewald) => ewald.cutoff,
+			Electrostatics::Pme(pme) => pme.cutoff,
+			_ => 0.0,
+		};
+		neighbors.max(cutoff).max(ewald)
+	}
+
+	/// Gets the total potential energy of the system from the last call to compute(), including the tail correction.
+	pub fn get_potential_energy(&self, p: &[Particle]) -> f64 {
+		let c = &self.contributions;
+		let energy: f64 = (0..c.energy_vdw.len()).map(|i| c.get_potential_energy(i)).sum();
+		energy + self.get_tail_corrections(p).0
+	}
+
+	/// The volume available to the particles, that of the container if there is one, otherwise that of the box.
+	pub fn volume(&self) -> f64 {
+		match self.borders.as_ref().and_then(|b| b.container.as_ref()) {
+			Some(container) => container.volume(&self.simbox.size),
+			None => self.simbox.volume(),
+		}
+	}
+
+	/// Gets the pressure tensor of the system, from the kinetic energy, the virials of the last call to compute(), and the tail correction.
+	///
+	/// The virial of the walls is included, so in a closed system this is the force per area on the walls.
+	pub fn get_pressure_tensor(&self, p: &[Particle]) -> Tensor {
+		let mut kinetic = Tensor::zero();
+		for particle in p {
+			kinetic += Tensor::outer(&particle.v, &particle.v) * particle.m;
+		}
+		(kinetic + self.contributions.virial + self.contributions.virial_wall) / self.volume() + Tensor::diagonal(self.get_tail_corrections(p).1)
+	}
+
+	/// Gets the pressure of the system, see get_pressure_tensor().
+	pub fn get_pressure(&self, p: &[Particle]) -> f64 {
+		self.get_pressure_tensor(p).trace() / 3.0
+	}
+
+	/// Gets the long-range corrections to the energy and pressure of the system, for the part of the pair potentials beyond the cutoff.
+	///
+	/// Assumes the particles are distributed homogeneously beyond the cutoff, so this is only meaningful in a periodic box.
+	/// Returns (energy, pressure), which are zero if the cutoff has no tail corrections.
+	pub fn get_tail_corrections(&self, p: &[Particle]) -> (f64, f64) {
+		let cutoff = match &self.cutoff {
+			Some(cutoff) if cutoff.tail_correction && self.vanderwaals => cutoff.radius,
+			_ => return (0.0, 0.0),
+		};
+
+		// Count the particles of each species, as the pair potentials only depend on the species
+		let mut counts = vec![0.0; self.species.species().len()];
+		for particle in p {
+			counts[particle.species] += 1.0;
+		}
+
+		let mut energy = 0.0;
+		let mut virial = 0.0;
+		for a in 0..counts.len() {
+			for b in a..counts.len() {
+				let pairs = if a == b { counts[a] * (counts[a] - 1.0) / 2.0 } else { counts[a] * counts[b] };
+				if pairs == 0.0 {
+					continue;
+				}
+				let potential = self.species.get_potential(a, b);
+				energy += pairs * potential.get_tail_energy(cutoff);
+				virial += pairs * potential.get_tail_virial(cutoff);
+			}
+		}
+
+		let volume = self.simbox.volume();
+		let four_pi = 4.0 * std::f64::consts::PI;
+		(four_pi / volume * energy, -four_pi / (3.0 * volume * volume) * virial)
+	}
+
+	/// Add the interaction between particles i and j to the contributions, unless the square of their distance is larger than cutoff_sq.
+	fn pair(&self, p: &[Particle], i: usize, j: usize, cutoff_sq: Option<f64>, c: &mut Contributions) {
+		let separation = self.simbox.separation(&p[i].pos, &p[j].pos);
+		if cutoff_sq.is_some_and(|cutoff_sq| separation.sqlen() > cutoff_sq) {
+			return;
+		}
+		let sep_dist = separation.len();
+		let dir = separation/sep_dist;
+
+		let (vdw_scale, elec_scale) = self.topology.scaling(i, j);
+		if vdw_scale == 0.0 && elec_scale == 0.0 {
+			return;
+		}
+		let potential = self.species.get_potential(p[i].species, p[j].species);
+
+		let (vdw_force, vdw_pot) = match (self.vanderwaals && vdw_scale != 0.0, &self.cutoff) {
+			(false, _) => (0.0, 0.0),
+			(true, None) => (potential.get_force(sep_dist), potential.get_energy(sep_dist)),
+			(true, Some(cutoff)) => (potential.get_force_cutoff(sep_dist, cutoff), potential.get_energy_cutoff(sep_dist, cutoff)),
+		};
+
+		let charges = (p[i].q, p[j].q);
+		let (elec_f, elec_v) = match &self.electrostatic {
+			_ if elec_scale == 0.0 => (0.0, 0.0),
+			Electrostatics::Off => (0.0, 0.0),
+			Electrostatics::Coulomb => (electrostatic::get_force(charges, sep_dist, self.elementary_charge), electrostatic::get_energy(charges, sep_dist, self.elementary_charge)),
+			Electrostatics::Ewald(ewald) => (ewald.get_real_force(charges, sep_dist, self.elementary_charge), ewald.get_real_energy(charges, sep_dist, self.elementary_charge)),
+			Electrostatics::Pme(pme) => (pme.get_real_force(charges, sep_dist, self.elementary_charge), pme.get_real_energy(charges, sep_dist, self.elementary_charge)),
+		};
+
+		let (vdw_force, vdw_pot) = (vdw_force * vdw_scale, vdw_pot * vdw_scale);
+		let (elec_f, elec_v) = (elec_f * elec_scale, elec_v * elec_scale);
+
+		c.force_electric[i] += dir * (-elec_f);
+		c.force_electric[j] += dir * ( elec_f);
+		c.force_vdw[i] 		+= dir * ( vdw_force);
+		c.force_vdw[j] 		+= dir * (-vdw_force);
+		c.force_total[i] 	+= dir * ( vdw_force - elec_f);
+		c.force_total[j] 	+= dir * (-vdw_force + elec_f);
+		c.virial += Tensor::outer(&(-separation), &(dir * (vdw_force - elec_f)));
+		for k in [i,j] {
+			c.energy_electric[k] += elec_v / 2.0;
+			c.energy_vdw[k] += vdw_pot / 2.0;
+		}
+	}
+}
+
+/// A table with a single species, of unit size and mass, with the default well depth.
+fn default_species() -> SpeciesTable {
+	let mut species = SpeciesTable::new(MixingRule::LorentzBerthelot);
+	species.add(Species{ name: String::from("default"), sigma: 1.0, epsilon: LJ_4_EPSILON / 4.0, mass: 1.0, charge: 0.0 });
+	species
+}
 
-//TODO: boundaries/borders
-//TODO: cutoffs for interaction
+impl Default for ForceField {
+	fn default() -> Self {
+		Self::new()
+	}
+}
@@ -0,0 +1,315 @@
+/*! Smooth particle-mesh Ewald (PME) summation of the electrostatic interactions in a periodic box.
+
+The real space sum, self-energy and neutralizing background are the same as in the Ewald sum (see ewald),
+but the reciprocal sum is calculated on a grid, which scales as N log N instead of N^2:
+the charges are spread onto the grid using B-splines, the grid is Fourier transformed and multiplied by the influence function,
+and the forces are interpolated from the transformed back grid using the same B-splines.
+
+See Essmann et al., "A smooth particle mesh Ewald method", J. Chem. Phys. 103, 8577 (1995).
+*/
+
+use super::ewald;
+use crate::complex::Complex;
+use crate::fft;
+use crate::particles::Particle;
+use crate::simbox::SimBox;
+use crate::vectors::{Vector, Tensor};
+
+use std::f64::consts::PI;
+
+/** Gets the values M_n(w + j) of the cardinal B-spline of order n, and its derivatives, for j = 0..n.
+
+w must be in [0, 1). Returns (values, derivatives).
+*/
+fn get_bspline(w: f64, order: usize) -> (Vec<f64>, Vec<f64>) {
+	// M_2(w) = w, M_2(w + 1) = 1 - w
+	let mut values = vec![0.0; order];
+	values[0] = w;
+	values[1] = 1.0 - w;
+	let mut derivatives = vec![1.0, -1.0];
+
+	// M_n(x) = (x M_n-1(x) + (n - x) M_n-1(x - 1)) / (n - 1)
+	for n in 3..=order {
+		if n == order {
+			// M_n'(x) = M_n-1(x) - M_n-1(x - 1)
+			derivatives = (0..order).map(|j| values[j] - if j > 0 { values[j - 1] } else { 0.0 }).collect();
+		}
+		for j in (0..n).rev() {
+			let x = w + j as f64;
+			let previous = if j > 0 { values[j - 1] } else { 0.0 };
+			values[j] = (x * values[j] + (n as f64 - x) * previous) / (n - 1) as f64;
+		}
+	}
+
+	(values, derivatives)
+}
+
+/// Gets |b(m)|^2 for each m on a grid axis of a given size, the factor correcting the B-spline interpolation of exp(2pi i m u / size).
+fn get_bspline_moduli(size: usize, order: usize) -> Vec<f64> {
+	// M_n(k + 1) for k = 0..n-1
+	let (values, _) = get_bspline(0.0, order);
+	let mut moduli: Vec<f64> = (0..size).map(|m| {
+		let mut sum = Complex::zero();
+		for k in 0..(order - 1) {
+			sum += Complex::from_phase(2.0 * PI * (m * k) as f64 / size as f64) * values[k + 1];
+		}
+		sum.sqabs()
+	}).collect();
+
+	// Odd orders have a zero at m = size / 2, use the average of its neighbors instead
+	for m in 0..size {
+		if moduli[m] < 1e-7 {
+			moduli[m] = (moduli[(m + size - 1) % size] + moduli[(m + 1) % size]) / 2.0;
+		}
+	}
+	moduli.iter().map(|m| 1.0 / m).collect()
+}
+
+/// The B-spline weights of a particle along one axis. Weight j belongs to grid point first - j.
+struct Spline {
+	first: i64,
+	values: Vec<f64>,
+	derivatives: Vec<f64>,
+}
+
+/// The parameters of the PME sum, and the influence function of its grid.
+pub struct Pme {
+	/// The inverse width of the screening charge distributions
+	pub alpha: f64,
+	/// The distance beyond which the real space sum is cut off
+	pub cutoff: f64,
+	/// The order of the B-splines, 4 is cubic
+	pub order: usize,
+	/// The number of grid points along each axis, which must be powers of two
+	pub grid: [usize; 3],
+	influence: Vec<f64>,
+	influence_size: Vector,
+}
+
+impl Pme {
+	/// Create a PME sum with the given parameters. The influence function is calculated on the first use.
+	pub fn new(alpha: f64, cutoff: f64, order: usize, grid: [usize; 3]) -> Self {
+		assert!(order >= 3, "The order of the PME B-splines must be at least 3, got {}", order);
+		assert!(grid.iter().all(|&k| k.is_power_of_two() && k >= order), "The PME grid sizes must be powers of two of at least the order, got {:?}", grid);
+		Pme{
+			alpha,
+			cutoff,
+			order,
+			grid,
+			influence: Vec::new(),
+			influence_size: Vector::zero(),
+		}
+	}
+
+	/// Create a PME sum for a given real space cutoff, B-spline order, and box size, with roughly the given relative accuracy.
+	///
+	/// alpha is the same as for the Ewald sum, and the grid spacing is chosen so that the spline interpolation is about as accurate.
+	pub fn from_accuracy(accuracy: f64, cutoff: f64, order: usize, size: &Vector) -> Self {
+		let s = (-accuracy.ln()).sqrt();
+		let alpha = s / cutoff;
+		// The relative error of the reciprocal forces is roughly 0.1 * (alpha * spacing)^order
+		let spacing = (10.0 * accuracy).powf(1.0 / order as f64) / alpha;
+		let grid = [size.x, size.y, size.z].map(|l| ((l / spacing).ceil() as usize).max(order).next_power_of_two());
+		Self::new(alpha, cutoff, order, grid)
+	}
+
+	/// Gets the real space part of the electrostatic energy between two particles, zero beyond the cutoff.
+	pub fn get_real_energy(&self, charges: (f64, f64), distance: f64, elementary_charge: f64) -> f64 {
+		if distance > self.cutoff {
+			return 0.0;
+		}
+		ewald::get_real_energy(self.alpha, charges, distance, elementary_charge)
+	}
+
+	/// Gets the magnitude of the real space part of the electrostatic force between two particles, positive if they repel, zero beyond the cutoff.
+	pub fn get_real_force(&self, charges: (f64, f64), distance: f64, elementary_charge: f64) -> f64 {
+		if distance > self.cutoff {
+			return 0.0;
+		}
+		ewald::get_real_force(self.alpha, charges, distance, elementary_charge)
+	}
+
+	/// The wave vector of a grid point, using negative frequencies for the upper half of each axis.
+	fn get_wave_vector(&self, size: &Vector, m: [usize; 3]) -> Vector {
+		let lengths = [size.x, size.y, size.z];
+		let [x, y, z] = [0, 1, 2].map(|axis| {
+			let k = self.grid[axis];
+			let m = if m[axis] <= k / 2 { m[axis] as f64 } else { m[axis] as f64 - k as f64 };
+			2.0 * PI * m / lengths[axis]
+		});
+		Vector::new(x, y, z)
+	}
+
+	/// Recalculate the influence function, if the box has changed since it was last calculated.
+	fn update_influence(&mut self, size: &Vector) {
+		if !self.influence.is_empty() && (*size - self.influence_size).sqlen() == 0.0 {
+			return;
+		}
+
+		let moduli = self.grid.map(|k| get_bspline_moduli(k, self.order));
+		let volume = size.x * size.y * size.z;
+
+		self.influence = vec![0.0; self.grid[0] * self.grid[1] * self.grid[2]];
+		for mx in 0..self.grid[0] {
+			for my in 0..self.grid[1] {
+				for mz in 0..self.grid[2] {
+					if mx == 0 && my == 0 && mz == 0 {
+						continue;
+					}
+					let k_sq = self.get_wave_vector(size, [mx, my, mz]).sqlen();
+					let index = (mx * self.grid[1] + my) * self.grid[2] + mz;
+					self.influence[index] = 4.0 * PI / (volume * k_sq) * (-k_sq / (4.0 * self.alpha * self.alpha)).exp()
+						* moduli[0][mx] * moduli[1][my] * moduli[2][mz];
+				}
+			}
+		}
+		self.influence_size = *size;
+	}
+
+	/// Gets the reciprocal space forces on, and energies of, each particle, including the self-energy and the energy of the neutralizing background,
+	/// and the virial of the reciprocal space sum and the background.
+	///
+	/// The real space part is not included, see get_real_force() and get_real_energy().
+	/// Returns (forces, energies, virial).
+	pub fn get_reciprocal(&mut self, simbox: &SimBox, p: &[Particle], elementary_charge: f64) -> (Vec<Vector>, Vec<f64>, Tensor) {
+		self.update_influence(&simbox.size);
+		let normalisation = ewald::get_normalisation(elementary_charge);
+		let lengths = [simbox.size.x, simbox.size.y, simbox.size.z];
+		let grid = self.grid;
+		let order = self.order;
+
+		// The B-spline weights along each axis of each particle
+		let splines: Vec<[Spline; 3]> = p.iter().map(|particle| {
+			let pos = [particle.pos.x, particle.pos.y, particle.pos.z];
+			[0, 1, 2].map(|axis| {
+				let u = pos[axis] / lengths[axis] * grid[axis] as f64;
+				let first = u.floor();
+				let (values, derivatives) = get_bspline(u - first, order);
+				Spline{ first: first as i64, values, derivatives }
+			})
+		}).collect();
+		let index = |axis: usize, first: i64, j: usize| -> usize {
+			(first - j as i64).rem_euclid(grid[axis] as i64) as usize
+		};
+
+		// Spread the charges onto the grid
+		let mut q = vec![Complex::zero(); grid[0] * grid[1] * grid[2]];
+		for (particle, [x, y, z]) in p.iter().zip(splines.iter()) {
+			for jx in 0..order {
+				for jy in 0..order {
+					let offset = (index(0, x.first, jx) * grid[1] + index(1, y.first, jy)) * grid[2];
+					let weight = particle.q * x.values[jx] * y.values[jy];
+					for jz in 0..order {
+						q[offset + index(2, z.first, jz)].re += weight * z.values[jz];
+					}
+				}
+			}
+		}
+
+		// Convolve with the influence function, the potential (up to normalisation) at each grid point
+		fft::fft_3d(&mut q, grid, false);
+		let mut virial = Tensor::zero();
+		for mx in 0..grid[0] {
+			for my in 0..grid[1] {
+				for mz in 0..grid[2] {
+					let index = (mx * grid[1] + my) * grid[2] + mz;
+					let influence = self.influence[index];
+					if influence != 0.0 {
+						let energy = normalisation * influence * q[index].sqabs() / 2.0;
+						virial += ewald::get_reciprocal_virial(energy, &self.get_wave_vector(&simbox.size, [mx, my, mz]), self.alpha);
+					}
+					q[index] = q[index] * influence;
+				}
+			}
+		}
+		fft::fft_3d(&mut q, grid, true);
+
+		// Interpolate the energies and forces from the potential
+		let mut forces = vec![Vector::zero(); p.len()];
+		let mut energies = vec![0.0; p.len()];
+		for (i, (particle, [x, y, z])) in p.iter().zip(splines.iter()).enumerate() {
+			let mut potential = 0.0;
+			let mut gradient = [0.0; 3];
+			for jx in 0..order {
+				for jy in 0..order {
+					let offset = (index(0, x.first, jx) * grid[1] + index(1, y.first, jy)) * grid[2];
+					for jz in 0..order {
+						let phi = q[offset + index(2, z.first, jz)].re;
+						potential += phi * x.values[jx] * y.values[jy] * z.values[jz];
+						gradient[0] += phi * x.derivatives[jx] * y.values[jy] * z.values[jz];
+						gradient[1] += phi * x.values[jx] * y.derivatives[jy] * z.values[jz];
+						gradient[2] += phi * x.values[jx] * y.values[jy] * z.derivatives[jz];
+					}
+				}
+			}
+			energies[i] = normalisation * particle.q * potential / 2.0;
+			// d/dr = grid / length * d/du
+			forces[i] = Vector::new(gradient[0] * grid[0] as f64 / lengths[0],
+			                        gradient[1] * grid[1] as f64 / lengths[1],
+			                        gradient[2] * grid[2] as f64 / lengths[2]) * (-normalisation * particle.q);
+		}
+
+		for (energy, self_energy) in energies.iter_mut().zip(ewald::get_self_energies(self.alpha, p, simbox.volume(), elementary_charge)) {
+			*energy += self_energy;
+		}
+		virial += Tensor::diagonal(ewald::get_background_energy(self.alpha, p, simbox.volume(), elementary_charge));
+
+		(forces, energies, virial)
+	}
+
+	/// Gets the total electrostatic energy of the system.
+	///
+	/// The real space sum uses the minimum-image convention, so the cutoff should be at most half the box size.
+	pub fn get_energy(&mut self, simbox: &SimBox, p: &[Particle], elementary_charge: f64) -> f64 {
+		let mut energy: f64 = self.get_reciprocal(simbox, p, elementary_charge).1.iter().sum();
+		for i in 0..p.len() {
+			for j in (i+1)..p.len() {
+				let distance = simbox.separation(&p[i].pos, &p[j].pos).len();
+				energy += self.get_real_energy((p[i].q, p[j].q), distance, elementary_charge);
+			}
+		}
+		energy
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::random::Random;
+	use crate::constants::PME_ORDER;
+	use crate::forcefield::ewald::Ewald;
+
+	/// A random salt solution of a number of unit charges of alternating sign, in a periodic box.
+	fn salt(count: usize, size: f64) -> (Vec<Particle>, SimBox) {
+		let mut random = Random::new(1);
+		let particles = (0..count).map(|i| {
+			let charge = if i % 2 == 0 { 1.0 } else { -1.0 };
+			Particle::new(&(Vector::new(random.uniform(), random.uniform(), random.uniform()) * size), 0.5, 1.0, charge)
+		}).collect();
+		(particles, SimBox::new(Vector::new(size, size, size), true))
+	}
+
+	/// The relative root mean square error of the reciprocal forces of PME, and the relative error of its reciprocal energy,
+	/// compared to a converged Ewald sum with the same alpha.
+	fn errors(pme: &mut Pme, particles: &[Particle], simbox: &SimBox) -> (f64, f64) {
+		let mut ewald = Ewald::from_accuracy(1e-12, pme.cutoff, &simbox.size);
+		ewald.alpha = pme.alpha;
+		let (reference, reference_energies, _) = ewald.get_reciprocal(simbox, particles, 1.0);
+		let (forces, energies, _) = pme.get_reciprocal(simbox, particles, 1.0);
+		let norm = reference.iter().map(|f| f.sqlen()).sum::<f64>().sqrt();
+		let force = forces.iter().zip(reference.iter()).map(|(a, b)| (*a - *b).sqlen()).sum::<f64>().sqrt() / norm;
+		let (energy, reference_energy) = (energies.iter().sum::<f64>(), reference_energies.iter().sum::<f64>());
+		(force, ((energy - reference_energy) / reference_energy).abs())
+	}
+
+	#[test]
+	fn reciprocal_matches_ewald() {
+		let (particles, simbox) = salt(200, 10.0);
+		for (accuracy, order) in [(1e-3, 4), (1e-3, PME_ORDER), (1e-4, PME_ORDER), (1e-5, PME_ORDER), (1e-4, 8)] {
+			let mut pme = Pme::from_accuracy(accuracy, 4.0, order, &simbox.size);
+			let (force, energy) = errors(&mut pme, &particles, &simbox);
+			assert!(force < accuracy, "force error {:e} at accuracy {:e} and order {}", force, accuracy, order);
+			assert!(energy < accuracy, "energy error {:e} at accuracy {:e} and order {}", energy, accuracy, order);
+		}
+	}
+}
@@ -0,0 +1,388 @@
+/*! The pair potentials of the non-bonded (van der Waals) interactions, which can be assigned to each pair of species.
+
+Each potential is a function of the distance between the centres of two particles, with its parameters set per pair.
+The cutoff schemes and the tail corrections work for any potential.
+*/
+
+use super::vanderwaals::{self, Cutoff, CutoffScheme, tail_integral};
+
+/// A potential between two particles, as a function of the distance between their centres.
+pub trait PairPotential {
+	/// Gets the potential energy at a distance.
+	fn get_energy(&self, distance: f64) -> f64;
+
+	/// Gets the derivative of the energy to the distance, positive if the particles attract.
+	fn get_force(&self, distance: f64) -> f64;
+
+	/// Gets the integral of r^2 * U(r) from the cutoff to infinity.
+	///
+	/// The energy tail correction of the system is the sum of 4 pi / V times this integral over all pairs.
+	fn get_tail_energy(&self, cutoff: f64) -> f64;
+
+	/// Gets the integral of r^3 * dU/dr from the cutoff to infinity.
+	///
+	/// The pressure tail correction of the system is the sum of -4 pi / (3 V^2) times this integral over all pairs.
+	fn get_tail_virial(&self, cutoff: f64) -> f64;
+
+	/// The distance below which the potential is not valid, so particles closer than this overlap.
+	fn min_distance(&self) -> f64 {
+		0.0
+	}
+
+	/// Gets the potential energy at a distance, using a cutoff.
+	fn get_energy_cutoff(&self, distance: f64, cutoff: &Cutoff) -> f64 {
+		if distance >= cutoff.radius {
+			return 0.0;
+		}
+
+		let energy = self.get_energy(distance);
+		match cutoff.scheme {
+			CutoffScheme::Truncated => energy,
+			CutoffScheme::EnergyShifted => energy - self.get_energy(cutoff.radius),
+			CutoffScheme::ForceShifted => energy - self.get_energy(cutoff.radius)
+				- (distance - cutoff.radius) * self.get_force(cutoff.radius),
+			CutoffScheme::Switched{ start } => energy * get_switch(start, cutoff.radius, distance).0,
+		}
+	}
+
+	/// Gets the derivative of the energy to the distance, using a cutoff.
+	fn get_force_cutoff(&self, distance: f64, cutoff: &Cutoff) -> f64 {
+		if distance >= cutoff.radius {
+			return 0.0;
+		}
+
+		let force = self.get_force(distance);
+		match cutoff.scheme {
+			CutoffScheme::Truncated | CutoffScheme::EnergyShifted => force,
+			CutoffScheme::ForceShifted => force - self.get_force(cutoff.radius),
+			CutoffScheme::Switched{ start } => {
+				let (s, ds) = get_switch(start, cutoff.radius, distance);
+				force * s + self.get_energy(distance) * ds
+			}
+		}
+	}
+}
+
+/** Gets the switching function and its derivative at a given distance.
+
+The switching function goes from 1 at start to 0 at end, with continuous first and second derivatives.
+*/
+fn get_switch(start: f64, end: f64, distance: f64) -> (f64, f64) {
+	if distance <= start {
+		return (1.0, 0.0);
+	}
+	let x = (distance - start) / (end - start);
+	let s = 1.0 - x * x * x * (10.0 - 15.0 * x + 6.0 * x * x);
+	let ds = -30.0 * x * x * (1.0 - x) * (1.0 - x) / (end - start);
+	(s, ds)
+}
+
+/// The integral of r^power * exp(-rate (r - offset)) from start to infinity, for power >= 0 and a positive rate.
+fn exp_tail_integral(start: f64, power: i32, rate: f64, offset: f64) -> f64 {
+	// Repeated integration by parts gives exp(-rate (start - offset)) * sum_k power! / k! * start^k / rate^(power - k + 1)
+	let mut sum = 0.0;
+	let mut term = 1.0 / rate;
+	for k in (0..=power).rev() {
+		sum += term * start.powi(k);
+		term *= k as f64 / rate;
+	}
+	(-rate * (start - offset)).exp() * sum
+}
+
+/// The Lennard-Jones 12-6 potential, see vanderwaals.
+#[derive(Clone, Copy)]
+pub struct LennardJones {
+	/// 4 * the well depth
+	pub epsilon_4: f64,
+	/// The size of the pair
+	pub sigma: f64,
+}
+
+impl PairPotential for LennardJones {
+	fn get_energy(&self, distance: f64) -> f64 {
+		vanderwaals::get_potential(self.epsilon_4, self.sigma, distance)
+	}
+
+	fn get_force(&self, distance: f64) -> f64 {
+		vanderwaals::get_force(self.epsilon_4, self.sigma, distance)
+	}
+
+	fn get_tail_energy(&self, cutoff: f64) -> f64 {
+		vanderwaals::get_tail_energy(self.epsilon_4, self.sigma, cutoff)
+	}
+
+	fn get_tail_virial(&self, cutoff: f64) -> f64 {
+		vanderwaals::get_tail_virial(self.epsilon_4, self.sigma, cutoff)
+	}
+
+	fn min_distance(&self) -> f64 {
+		self.sigma
+	}
+}
+
+/** The Weeks-Chandler-Andersen potential, the repulsive part of the Lennard-Jones potential.
+
+The Lennard-Jones potential is cut off at its minimum and shifted up by the well depth, so it is purely repulsive and zero beyond the minimum.
+*/
+#[derive(Clone, Copy)]
+pub struct Wca {
+	/// 4 * the well depth of the Lennard-Jones potential
+	pub epsilon_4: f64,
+	/// The size of the pair
+	pub sigma: f64,
+}
+
+impl Wca {
+	/// The distance of the minimum of the Lennard-Jones potential, beyond which the potential is zero.
+	pub fn range(&self) -> f64 {
+		self.sigma * (1.0 + 2_f64.powf(1.0 / 6.0))
+	}
+
+	fn lennard_jones(&self) -> LennardJones {
+		LennardJones{ epsilon_4: self.epsilon_4, sigma: self.sigma }
+	}
+}
+
+impl PairPotential for Wca {
+	fn get_energy(&self, distance: f64) -> f64 {
+		if distance >= self.range() {
+			return 0.0;
+		}
+		self.lennard_jones().get_energy(distance) + self.epsilon_4 / 4.0
+	}
+
+	fn get_force(&self, distance: f64) -> f64 {
+		if distance >= self.range() {
+			return 0.0;
+		}
+		self.lennard_jones().get_force(distance)
+	}
+
+	fn get_tail_energy(&self, cutoff: f64) -> f64 {
+		let range = self.range();
+		if cutoff >= range {
+			return 0.0;
+		}
+		let lj = self.lennard_jones();
+		lj.get_tail_energy(cutoff) - lj.get_tail_energy(range) + self.epsilon_4 / 4.0 * (range.powi(3) - cutoff.powi(3)) / 3.0
+	}
+
+	fn get_tail_virial(&self, cutoff: f64) -> f64 {
+		let range = self.range();
+		if cutoff >= range {
+			return 0.0;
+		}
+		let lj = self.lennard_jones();
+		lj.get_tail_virial(cutoff) - lj.get_tail_virial(range)
+	}
+
+	fn min_distance(&self) -> f64 {
+		self.sigma
+	}
+}
+
+/** The Buckingham potential, A exp(-r / rho) - C / r^6.
+
+The attraction wins at very short distances, so particles that get too close collapse onto each other.
+*/
+#[derive(Clone, Copy)]
+pub struct Buckingham {
+	/// The strength of the repulsion
+	pub a: f64,
+	/// The range of the repulsion
+	pub rho: f64,
+	/// The strength of the attraction
+	pub c: f64,
+}
+
+impl PairPotential for Buckingham {
+	fn get_energy(&self, distance: f64) -> f64 {
+		self.a * (-distance / self.rho).exp() - self.c * distance.powi(-6)
+	}
+
+	fn get_force(&self, distance: f64) -> f64 {
+		-self.a / self.rho * (-distance / self.rho).exp() + 6.0 * self.c * distance.powi(-7)
+	}
+
+	fn get_tail_energy(&self, cutoff: f64) -> f64 {
+		self.a * exp_tail_integral(cutoff, 2, 1.0 / self.rho, 0.0) - self.c * tail_integral(cutoff, -4)
+	}
+
+	fn get_tail_virial(&self, cutoff: f64) -> f64 {
+		-self.a / self.rho * exp_tail_integral(cutoff, 3, 1.0 / self.rho, 0.0) + 6.0 * self.c * tail_integral(cutoff, -4)
+	}
+}
+
+/// The Morse potential, D ((1 - exp(-a (r - r0)))^2 - 1), which is zero at infinity and has a minimum of -D at r0.
+#[derive(Clone, Copy)]
+pub struct Morse {
+	/// The well depth D
+	pub depth: f64,
+	/// The inverse width a of the well
+	pub width: f64,
+	/// The distance r0 of the minimum
+	pub length: f64,
+}
+
+impl PairPotential for Morse {
+	fn get_energy(&self, distance: f64) -> f64 {
+		let e = (-self.width * (distance - self.length)).exp();
+		self.depth * (e * e - 2.0 * e)
+	}
+
+	fn get_force(&self, distance: f64) -> f64 {
+		let e = (-self.width * (distance - self.length)).exp();
+		2.0 * self.width * self.depth * (e - e * e)
+	}
+
+	fn get_tail_energy(&self, cutoff: f64) -> f64 {
+		self.depth * (exp_tail_integral(cutoff, 2, 2.0 * self.width, self.length) - 2.0 * exp_tail_integral(cutoff, 2, self.width, self.length))
+	}
+
+	fn get_tail_virial(&self, cutoff: f64) -> f64 {
+		2.0 * self.width * self.depth * (exp_tail_integral(cutoff, 3, self.width, self.length) - exp_tail_integral(cutoff, 3, 2.0 * self.width, self.length))
+	}
+}
+
+/** The screened Coulomb (Yukawa or Debye-Hückel) potential, A exp(-kappa r) / r.
+
+For charged particles in an electrolyte A is q_i q_j / (4 pi epsilon), and 1 / kappa is the Debye length.
+*/
+#[derive(Clone, Copy)]
+pub struct Yukawa {
+	/// The strength A, positive if the particles repel
+	pub strength: f64,
+	/// The inverse screening length
+	pub kappa: f64,
+}
+
+impl PairPotential for Yukawa {
+	fn get_energy(&self, distance: f64) -> f64 {
+		self.strength * (-self.kappa * distance).exp() / distance
+	}
+
+	fn get_force(&self, distance: f64) -> f64 {
+		-self.get_energy(distance) * (self.kappa + 1.0 / distance)
+	}
+
+	fn get_tail_energy(&self, cutoff: f64) -> f64 {
+		self.strength * exp_tail_integral(cutoff, 1, self.kappa, 0.0)
+	}
+
+	fn get_tail_virial(&self, cutoff: f64) -> f64 {
+		-self.strength * (self.kappa * exp_tail_integral(cutoff, 2, self.kappa, 0.0) + exp_tail_integral(cutoff, 1, self.kappa, 0.0))
+	}
+}
+
+/// The purely repulsive soft-sphere potential, epsilon (sigma / r)^n.
+#[derive(Clone, Copy)]
+pub struct SoftSphere {
+	/// The energy at a distance of sigma
+	pub epsilon: f64,
+	/// The size of the pair
+	pub sigma: f64,
+	/// The exponent n, the tail corrections are infinite unless it is larger than 3
+	pub exponent: f64,
+}
+
+impl PairPotential for SoftSphere {
+	fn get_energy(&self, distance: f64) -> f64 {
+		self.epsilon * (self.sigma / distance).powf(self.exponent)
+	}
+
+	fn get_force(&self, distance: f64) -> f64 {
+		-self.exponent * self.get_energy(distance) / distance
+	}
+
+	fn get_tail_energy(&self, cutoff: f64) -> f64 {
+		if self.exponent <= 3.0 {
+			return f64::INFINITY;
+		}
+		self.get_energy(cutoff) * cutoff.powi(3) / (self.exponent - 3.0)
+	}
+
+	fn get_tail_virial(&self, cutoff: f64) -> f64 {
+		-self.exponent * self.get_tail_energy(cutoff)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Each potential, with distances where it is valid and a cutoff.
+	fn potentials() -> Vec<(Box<dyn PairPotential>, [f64; 4], f64)> {
+		vec![
+			(Box::new(LennardJones{ epsilon_4: 4.0, sigma: 1.0 }), [1.8, 2.1, 2.5, 3.5], 3.0),
+			(Box::new(Wca{ epsilon_4: 4.0, sigma: 1.0 }), [1.5, 1.9, 2.1, 2.5], 1.8),
+			(Box::new(Buckingham{ a: 1000.0, rho: 0.3, c: 10.0 }), [1.0, 1.5, 2.0, 3.0], 2.5),
+			(Box::new(Morse{ depth: 1.0, width: 2.0, length: 1.2 }), [0.9, 1.2, 1.6, 2.4], 2.5),
+			(Box::new(Yukawa{ strength: 2.0, kappa: 1.5 }), [0.5, 1.0, 2.0, 3.0], 2.5),
+			(Box::new(SoftSphere{ epsilon: 1.0, sigma: 1.0, exponent: 12.0 }), [0.9, 1.0, 1.3, 2.0], 2.5),
+		]
+	}
+
+	fn close(a: f64, b: f64, tolerance: f64) -> bool {
+		(a - b).abs() <= tolerance * (1.0 + b.abs())
+	}
+
+	/// The derivative of a function by central differences.
+	fn derivative(f: impl Fn(f64) -> f64, x: f64) -> f64 {
+		let h = 1e-6;
+		(f(x + h) - f(x - h)) / (2.0 * h)
+	}
+
+	/// The integral of a function from a start to infinity, with Simpson's rule after substituting r = start / t.
+	fn integral_to_infinity(f: impl Fn(f64) -> f64, start: f64) -> f64 {
+		let n = 20000;
+		let h = 1.0 / n as f64;
+		let g = |t: f64| if t == 0.0 { 0.0 } else { f(start / t) * start / (t * t) };
+		let sum: f64 = (1..n).map(|i| g(i as f64 * h) * if i % 2 == 1 { 4.0 } else { 2.0 }).sum();
+		(sum + g(0.0) + g(1.0)) * h / 3.0
+	}
+
+	#[test]
+	fn forces_match_energies() {
+		for (potential, distances, _) in potentials() {
+			for r in distances {
+				let force = derivative(|r| potential.get_energy(r), r);
+				assert!(close(potential.get_force(r), force, 1e-6), "The force at {} is {}, not {}", r, potential.get_force(r), force);
+			}
+		}
+	}
+
+	#[test]
+	fn tail_corrections_match_integrals() {
+		for (potential, _, cutoff) in potentials() {
+			let energy = integral_to_infinity(|r| r * r * potential.get_energy(r), cutoff);
+			let virial = integral_to_infinity(|r| r * r * r * potential.get_force(r), cutoff);
+			assert!(close(potential.get_tail_energy(cutoff), energy, 1e-6), "The tail energy is {}, not {}", potential.get_tail_energy(cutoff), energy);
+			assert!(close(potential.get_tail_virial(cutoff), virial, 1e-6), "The tail virial is {}, not {}", potential.get_tail_virial(cutoff), virial);
+		}
+	}
+
+	#[test]
+	fn cutoff_schemes_are_consistent() {
+		let schemes = [CutoffScheme::Truncated, CutoffScheme::EnergyShifted, CutoffScheme::ForceShifted, CutoffScheme::Switched{ start: 2.0 }];
+		for (potential, distances, _) in potentials() {
+			for scheme in schemes {
+				let cutoff = Cutoff{ radius: 2.8, scheme, tail_correction: false };
+				for r in distances.iter().filter(|&&r| r < 2.8) {
+					let force = derivative(|r| potential.get_energy_cutoff(r, &cutoff), *r);
+					assert!(close(potential.get_force_cutoff(*r, &cutoff), force, 1e-6));
+				}
+
+				// Only the truncated potential jumps at the cutoff, and only the force-shifted and switched forces go to zero
+				let below = 2.8 - 1e-9;
+				let energy = potential.get_energy_cutoff(below, &cutoff);
+				let force = potential.get_force_cutoff(below, &cutoff);
+				assert_eq!(potential.get_energy_cutoff(2.8, &cutoff), 0.0);
+				match scheme {
+					CutoffScheme::Truncated => assert!(close(energy, potential.get_energy(2.8), 1e-6)),
+					CutoffScheme::EnergyShifted => assert!(energy.abs() < 1e-8 && close(force, potential.get_force(2.8), 1e-6)),
+					_ => assert!(energy.abs() < 1e-8 && force.abs() < 1e-8),
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,253 @@
+//! Barostats, to keep the pressure of a system at a target by changing the size of the box.
+
+use crate::particles::Particle;
+use crate::simbox::SimBox;
+use crate::constants::BOLTZMANN_CONST;
+use super::temperature;
+
+/// Scale the box and the positions of all particles in it by a factor.
+pub fn scale_system(particles: &mut [Particle], simbox: &mut SimBox, factor: f64) {
+	simbox.size = simbox.size * factor;
+	for p in particles.iter_mut() {
+		p.pos = p.pos * factor;
+	}
+}
+
+/// Twice the kinetic energy of the particles.
+fn get_double_kinetic_energy(particles: &[Particle]) -> f64 {
+	particles.iter().map(|p| p.m * p.v.sqlen()).sum()
+}
+
+/** A barostat, coupling the system to a pressure bath.
+
+Each step is split around the integrator: `pre_step` is called before the integrator moves the particles,
+`rescale` after the particles have moved but before the forces are recalculated, and `post_step` after the new velocities are known.
+The box may be scaled both in `pre_step` and in `rescale`.
+The pressure passed to `pre_step` and `post_step` is the current pressure, see ForceField::get_pressure(),
+and the velocities of the particles are those at time t, also for integrators that store them half a step away.
+*/
+pub trait Barostat {
+	/// The part of the step before the integrator, at time t.
+	fn pre_step(&mut self, _particles: &mut [Particle], _simbox: &mut SimBox, _pressure: f64, _t: f64, _dt: f64) {}
+
+	/// Change the size of the box, after the particles have moved.
+	fn rescale(&mut self, particles: &mut [Particle], simbox: &mut SimBox, t: f64, dt: f64);
+
+	/// The part of the step after the integrator, at time t.
+	fn post_step(&mut self, _particles: &mut [Particle], _simbox: &SimBox, _pressure: f64, _t: f64, _dt: f64) {}
+
+	/// The energy of the barostat, so that the total energy of the system plus the barostat is conserved.
+	fn energy(&self, simbox: &SimBox) -> f64;
+}
+
+/** The Berendsen weak-coupling barostat, which scales the box towards a target pressure once the simulation passes a start time.
+
+The box is scaled by (1 - compressibility * dt / coupling * (target - pressure))^(1/3) each step.
+This does not sample the isothermal-isobaric ensemble, so its energy, the target pressure times the volume, is not conserved.
+*/
+pub struct Berendsen {
+	/// The target pressure
+	pub target: f64,
+	/// The coupling constant, a large coupling constant results in a slow change in pressure
+	pub coupling: f64,
+	/// The compressibility of the system
+	pub compressibility: f64,
+	/// The time at which the barostat is switched on
+	pub start: f64,
+	/// The pressure at the start of the current step
+	pressure: f64,
+}
+
+impl Berendsen {
+	/// Create a new barostat.
+	pub fn new(target: f64, coupling: f64, compressibility: f64, start: f64) -> Self {
+		Berendsen{ target, coupling, compressibility, start, pressure: target }
+	}
+}
+
+impl Barostat for Berendsen {
+	fn pre_step(&mut self, _particles: &mut [Particle], _simbox: &mut SimBox, pressure: f64, _t: f64, _dt: f64) {
+		self.pressure = pressure;
+	}
+
+	fn rescale(&mut self, particles: &mut [Particle], simbox: &mut SimBox, t: f64, dt: f64) {
+		if t < self.start {
+			return;
+		}
+		let factor = (1.0 - self.compressibility * dt / self.coupling * (self.target - self.pressure)).cbrt();
+		scale_system(particles, simbox, factor);
+	}
+
+	fn energy(&self, simbox: &SimBox) -> f64 {
+		self.target * simbox.volume()
+	}
+}
+
+/** An isotropic Martyna-Tobias-Klein (MTK) barostat, where the logarithm of the volume is a dynamical variable with a fictitious mass.
+
+See Martyna et al., J. Chem. Phys. 101, 4177 (1994). The volume is integrated for half a time step before and after the integrator,
+and the box is scaled by half a step before and after the integrator moves the particles, so their displacement is scaled by half a step.
+On its own this conserves the energy of the system plus that of the barostat, the isoenthalpic-isobaric ensemble.
+The thermostat only acts on the particles and not on the velocity of the box, so together they only approximate the isothermal-isobaric ensemble.
+*/
+pub struct Mtk {
+	/// The target pressure
+	pub target: f64,
+	/// The period of the oscillations of the volume
+	pub coupling: f64,
+	/// The time at which the barostat is switched on
+	pub start: f64,
+	/// The velocity of the logarithm of the box length
+	pub velocity: f64,
+	/// The mass of the barostat
+	mass: f64,
+	/// The number of degrees of freedom of the system
+	degrees_of_freedom: f64,
+}
+
+impl Mtk {
+	/// Create a new barostat, for a number of particles with a number of degrees of freedom removed by constraints, at a temperature.
+	pub fn new(target: f64, coupling: f64, start: f64, temperature: f64, particles: usize, constraints: usize) -> Self {
+		let degrees_of_freedom = temperature::get_degrees_of_freedom(particles, constraints);
+		Mtk{
+			target,
+			coupling,
+			start,
+			velocity: 0.0,
+			mass: (degrees_of_freedom + 3.0) * BOLTZMANN_CONST * temperature * coupling * coupling,
+			degrees_of_freedom,
+		}
+	}
+
+	/// Integrate the velocity of the box for half a time step, given the current pressure.
+	fn kick(&mut self, particles: &[Particle], pressure: f64, volume: f64, dt: f64) {
+		let double_kinetic = get_double_kinetic_energy(particles);
+		let force = 3.0 * volume * (pressure - self.target) + 3.0 / self.degrees_of_freedom * double_kinetic;
+		self.velocity += force / self.mass * dt / 2.0;
+	}
+
+	/// Scale the velocities of the particles, for the coupling to the velocity of the box during half a time step.
+	fn scale_velocities(&self, particles: &mut [Particle], dt: f64) {
+		let scale = (-(1.0 + 3.0 / self.degrees_of_freedom) * self.velocity * dt / 2.0).exp();
+		for p in particles.iter_mut() {
+			p.v = p.v * scale;
+		}
+	}
+}
+
+impl Barostat for Mtk {
+	fn pre_step(&mut self, particles: &mut [Particle], simbox: &mut SimBox, pressure: f64, t: f64, dt: f64) {
+		if t < self.start {
+			return;
+		}
+		self.kick(particles, pressure, simbox.volume(), dt);
+		self.scale_velocities(particles, dt);
+		scale_system(particles, simbox, (self.velocity * dt / 2.0).exp());
+	}
+
+	fn rescale(&mut self, particles: &mut [Particle], simbox: &mut SimBox, t: f64, dt: f64) {
+		if t < self.start {
+			return;
+		}
+		scale_system(particles, simbox, (self.velocity * dt / 2.0).exp());
+	}
+
+	fn post_step(&mut self, particles: &mut [Particle], simbox: &SimBox, pressure: f64, t: f64, dt: f64) {
+		if t <= self.start {
+			return;
+		}
+		let before = get_double_kinetic_energy(particles);
+		self.scale_velocities(particles, dt);
+		// The pressure was calculated before the velocities were scaled, so its kinetic part is updated
+		let volume = simbox.volume();
+		let pressure = pressure + (get_double_kinetic_energy(particles) - before) / (3.0 * volume);
+		self.kick(particles, pressure, volume, dt);
+	}
+
+	fn energy(&self, simbox: &SimBox) -> f64 {
+		self.mass * self.velocity * self.velocity / 2.0 + self.target * simbox.volume()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::vectors::Vector;
+	use crate::random::Random;
+	use crate::forcefield::{ForceField, Electrostatics};
+	use crate::forcefield::vanderwaals::{Cutoff, CutoffScheme};
+	use crate::integrators::VelocityVerlet;
+	use crate::simulation::Simulation;
+
+	/// A Lennard-Jones forcefield with a cutoff in a periodic box, without walls.
+	fn lennard_jones(size: f64, cutoff: f64) -> ForceField {
+		let mut forcefield = ForceField::new();
+		forcefield.electrostatic = Electrostatics::Off;
+		forcefield.borders = None;
+		forcefield.simbox = SimBox::new(Vector::new(size, size, size), true);
+		forcefield.cutoff = Some(Cutoff{ radius: cutoff, scheme: CutoffScheme::ForceShifted, tail_correction: false });
+		forcefield
+	}
+
+	/// Particles on a simple cubic lattice, slightly displaced and with random velocities at a temperature.
+	fn lattice(cells: usize, spacing: f64, temperature: f64, seed: u64) -> Vec<Particle> {
+		let mut random = Random::new(seed);
+		let mut particles: Vec<Particle> = (0..cells * cells * cells).map(|i| {
+			let cell = Vector::new((i % cells) as f64, (i / cells % cells) as f64, (i / cells / cells) as f64) + Vector::new(0.5, 0.5, 0.5);
+			let displacement = Vector::new(random.uniform(), random.uniform(), random.uniform()) * 0.1;
+			Particle::new(&(cell * spacing + displacement), 0.5, 1.0, 0.0)
+		}).collect();
+		temperature::maxwell_boltzmann(&mut particles, temperature, &mut random);
+		particles
+	}
+
+	#[test]
+	fn ideal_gas_pressure() {
+		let mut forcefield = lennard_jones(6.0, 2.5);
+		forcefield.vanderwaals = false;
+		let mut particles = lattice(4, 1.5, 1.7, 1);
+		forcefield.compute(&mut particles).unwrap();
+		// P = N k T / V, with the temperature of all 3N degrees of freedom
+		let temperature = temperature::get_temperature(&particles, 0);
+		let expected = particles.len() as f64 * BOLTZMANN_CONST * temperature / forcefield.volume();
+		assert!((forcefield.get_pressure(&particles) - expected).abs() < 1e-12 * expected);
+	}
+
+	#[test]
+	fn virial_pressure_is_the_volume_derivative_of_the_energy() {
+		let mut forcefield = lennard_jones(9.2, 4.0);
+		let mut particles = lattice(4, 2.3, 0.0, 2);
+		forcefield.compute(&mut particles).unwrap();
+		let pressure = forcefield.get_pressure(&particles);
+
+		// -dU/dV from an isotropic strain of the box and the positions
+		let energy = |strain: f64| {
+			let mut forcefield = lennard_jones(9.2, 4.0);
+			let mut particles = particles.clone();
+			scale_system(&mut particles, &mut forcefield.simbox, (1.0 + strain).cbrt());
+			forcefield.compute(&mut particles).unwrap();
+			(forcefield.get_potential_energy(&particles), forcefield.volume())
+		};
+		let (h, l) = (energy(1e-6), energy(-1e-6));
+		let derivative = -(h.0 - l.0) / (h.1 - l.1);
+		assert!((pressure - derivative).abs() < 1e-6 * pressure.abs().max(1.0), "{} != {}", pressure, derivative);
+	}
+
+	#[test]
+	fn mtk_conserves_its_energy() {
+		let particles = lattice(4, 2.3, 1.0, 3);
+		let mtk = Mtk::new(1.0, 2.0, 0.0, 1.0, particles.len(), 0);
+		let mut sim = Simulation::new(particles, lennard_jones(9.2, 4.0), Box::new(VelocityVerlet), None, Some(Box::new(mtk)), None, 0, 0.001).unwrap();
+		sim.run(2000).unwrap();
+
+		let conserved = sim.data.global.get("energy_conserved");
+		let start = conserved[0];
+		let drift = conserved.iter().map(|e| (e - start).abs()).fold(0.0, f64::max);
+		assert!(drift < 1e-3 * sim.particles.len() as f64, "drift {}", drift);
+		// The volume did change, with the energy of the barostat including the target pressure times the volume
+		let volumes = sim.data.global.get("volume");
+		assert!(volumes.iter().any(|v| (v - volumes[0]).abs() > 0.1 * volumes[0]));
+		let barostat = sim.data.global.get("energy_barostat");
+		assert!(barostat.iter().zip(volumes).all(|(e, v)| *e >= v - 1e-9));
+	}
+}
@@ -0,0 +1,281 @@
+/*! Tabulated pair potentials, such as the coarse-grained potentials from iterative Boltzmann inversion.
+
+A table is a text file with three columns per line: the distance r, the energy U, and the force F = -dU/dr.
+Empty lines and lines starting with # are ignored, and the distances must be increasing, but need not be evenly spaced.
+
+Between the rows the energy is interpolated with a cubic Hermite spline through the energies, with the tabulated forces as its slopes.
+The force is the derivative of this spline, so it is exactly consistent with the energy, and both are continuous.
+The potential is zero beyond the last row, so the energy and force of the last row must be close to zero, see Tabulated::check().
+Before the first row the energy continues linearly, with the force of the first row.
+*/
+
+use std::fmt;
+use std::error::Error;
+use std::path::Path;
+
+use super::potentials::PairPotential;
+
+/// An error while reading or checking a table.
+#[derive(Debug)]
+pub enum TableError {
+	/// The file could not be read
+	Io(std::io::Error),
+	/// A line is not three finite numbers, or the distances are not increasing, with the number of the line (or row)
+	Invalid(usize, String),
+	/// The table has fewer than two rows
+	TooShort,
+	/// The forces do not match the derivative of the energies, with the distance and the error relative to the range of the energies
+	Inconsistent(f64, f64),
+	/// The energy or the force of the last row is not close to zero, with the energy and the force
+	NotZeroAtEnd(f64, f64),
+}
+
+impl fmt::Display for TableError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			TableError::Io(e) => write!(f, "Could not read the table: {}", e),
+			TableError::Invalid(line, e) => write!(f, "Invalid table on line {}: {}", line, e),
+			TableError::TooShort => write!(f, "The table must have at least two rows"),
+			TableError::Inconsistent(r, error) => write!(f, "The forces of the table do not match its energies, the integrated force differs by {} times the range of the energies at r = {}", error, r),
+			TableError::NotZeroAtEnd(energy, force) => write!(f, "The table must go to zero at its last row, where the energy is {} and the force {}", energy, force),
+		}
+	}
+}
+
+impl Error for TableError {}
+
+impl From<std::io::Error> for TableError {
+	fn from(e: std::io::Error) -> Self {
+		TableError::Io(e)
+	}
+}
+
+/// A pair potential interpolated from a table of energies and forces.
+#[derive(Clone)]
+pub struct Tabulated {
+	r: Vec<f64>,
+	energy: Vec<f64>,
+	force: Vec<f64>,
+}
+
+impl Tabulated {
+	/// Create a table from the distances, energies, and forces (-dU/dr) of each row.
+	pub fn new(r: Vec<f64>, energy: Vec<f64>, force: Vec<f64>) -> Result<Self, TableError> {
+		assert!(r.len() == energy.len() && r.len() == force.len(), "The columns of a table must have the same length");
+		if r.len() < 2 {
+			return Err(TableError::TooShort);
+		}
+		for i in 0..r.len() {
+			if !(r[i].is_finite() && energy[i].is_finite() && force[i].is_finite()) {
+				return Err(TableError::Invalid(i + 1, String::from("all values must be finite")));
+			}
+			if r[i] < 0.0 || (i > 0 && r[i] <= r[i - 1]) {
+				return Err(TableError::Invalid(i + 1, String::from("the distances must be increasing and not negative")));
+			}
+		}
+		Ok(Tabulated{ r, energy, force })
+	}
+
+	/// Parse a table from the contents of a file, see the module documentation for the format.
+	pub fn parse(contents: &str) -> Result<Self, TableError> {
+		let (mut r, mut energy, mut force, mut lines) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+		for (i, line) in contents.lines().enumerate() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+			let values: Vec<f64> = line.split_whitespace().map(|v| v.parse::<f64>())
+				.collect::<Result<_, _>>().map_err(|e| TableError::Invalid(i + 1, e.to_string()))?;
+			if values.len() != 3 {
+				return Err(TableError::Invalid(i + 1, format!("expected 3 columns, got {}", values.len())));
+			}
+			r.push(values[0]);
+			energy.push(values[1]);
+			force.push(values[2]);
+			lines.push(i + 1);
+		}
+		// new() reports the number of the row, rather than of the line
+		Self::new(r, energy, force).map_err(|e| match e {
+			TableError::Invalid(row, message) => TableError::Invalid(lines[row - 1], message),
+			e => e,
+		})
+	}
+
+	/// Read a table from a file, and check it within a tolerance, see check().
+	pub fn from_file(filename: &Path, tolerance: f64) -> Result<Self, TableError> {
+		let table = Self::parse(&std::fs::read_to_string(filename)?)?;
+		table.check(tolerance)?;
+		Ok(table)
+	}
+
+	/// Check that the forces match the derivative of the energies, and that the table goes to zero at its end.
+	///
+	/// The forces are integrated with the trapezoidal rule from the end of the table,
+	/// which may differ from the energies by at most tolerance times the range of the energies.
+	/// The energy of the last row may be at most tolerance times the range of the energies,
+	/// and its force at most that divided by the length of the table, so the jumps where the potential is cut off are small.
+	pub fn check(&self, tolerance: f64) -> Result<(), TableError> {
+		let (min, max) = self.energy.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &u| (min.min(u), max.max(u)));
+		let range = max - min;
+
+		let last = self.r.len() - 1;
+		let length = self.r[last] - self.r[0];
+		if self.energy[last].abs() > tolerance * range || self.force[last].abs() * length > tolerance * range {
+			return Err(TableError::NotZeroAtEnd(self.energy[last], self.force[last]));
+		}
+
+		let mut integrated = self.energy[last];
+		let mut worst = (self.r[last], 0.0);
+		for i in (0..last).rev() {
+			integrated += (self.r[i + 1] - self.r[i]) * (self.force[i] + self.force[i + 1]) / 2.0;
+			let error = (integrated - self.energy[i]).abs();
+			if error > worst.1 {
+				worst = (self.r[i], error);
+			}
+		}
+
+		if worst.1 > tolerance * range {
+			return Err(TableError::Inconsistent(worst.0, worst.1 / range));
+		}
+		Ok(())
+	}
+
+	/// The distance of the last row, beyond which the potential is zero.
+	pub fn range(&self) -> f64 {
+		self.r[self.r.len() - 1]
+	}
+
+	/// The index of the row at the start of the interval containing a distance from the first row on, and the fraction of the interval up to it.
+	fn interval(&self, distance: f64) -> (usize, f64) {
+		let i = (self.r.partition_point(|&r| r <= distance) - 1).min(self.r.len() - 2);
+		(i, (distance - self.r[i]) / (self.r[i + 1] - self.r[i]))
+	}
+
+	/// Integrate a function over the table from a distance, which is exact for polynomials of up to the fifth degree on each interval.
+	fn integrate(&self, from: f64, f: impl Fn(f64) -> f64) -> f64 {
+		// The three point Gauss-Legendre rule on [0, 1]
+		let offset = 0.6_f64.sqrt() / 2.0;
+		let rule = [(0.5 - offset, 5.0 / 18.0), (0.5, 8.0 / 18.0), (0.5 + offset, 5.0 / 18.0)];
+
+		let mut integral = 0.0;
+		for i in 0..(self.r.len() - 1) {
+			let (start, end) = (self.r[i].max(from), self.r[i + 1]);
+			if start >= end {
+				continue;
+			}
+			for (x, weight) in rule {
+				integral += weight * (end - start) * f(start + x * (end - start));
+			}
+		}
+		integral
+	}
+}
+
+impl PairPotential for Tabulated {
+	fn get_energy(&self, distance: f64) -> f64 {
+		if distance >= self.range() {
+			return 0.0;
+		}
+		if distance < self.r[0] {
+			return self.energy[0] + (self.r[0] - distance) * self.force[0];
+		}
+		let (i, t) = self.interval(distance);
+		let h = self.r[i + 1] - self.r[i];
+		// The cubic Hermite basis functions, with the slopes dU/dr = -F
+		let (h00, h10, h01, h11) = ((1.0 + 2.0 * t) * (1.0 - t) * (1.0 - t), t * (1.0 - t) * (1.0 - t), t * t * (3.0 - 2.0 * t), t * t * (t - 1.0));
+		h00 * self.energy[i] - h10 * h * self.force[i] + h01 * self.energy[i + 1] - h11 * h * self.force[i + 1]
+	}
+
+	fn get_force(&self, distance: f64) -> f64 {
+		if distance >= self.range() {
+			return 0.0;
+		}
+		if distance < self.r[0] {
+			return -self.force[0];
+		}
+		let (i, t) = self.interval(distance);
+		let h = self.r[i + 1] - self.r[i];
+		let (d00, d10, d01, d11) = (6.0 * t * (t - 1.0), (1.0 - t) * (1.0 - 3.0 * t), 6.0 * t * (1.0 - t), t * (3.0 * t - 2.0));
+		(d00 * self.energy[i] + d01 * self.energy[i + 1]) / h - d10 * self.force[i] - d11 * self.force[i + 1]
+	}
+
+	fn get_tail_energy(&self, cutoff: f64) -> f64 {
+		self.integrate(cutoff, |r| r * r * self.get_energy(r))
+	}
+
+	fn get_tail_virial(&self, cutoff: f64) -> f64 {
+		self.integrate(cutoff, |r| r * r * r * self.get_force(r))
+	}
+
+	fn min_distance(&self) -> f64 {
+		self.r[0]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The rows of the cubic U = (2 - r)^3 from 0.5 to 2, which goes to zero at the end of the table.
+	fn cubic(rows: usize) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+		let r: Vec<f64> = (0..rows).map(|i| 0.5 + 1.5 * i as f64 / (rows - 1) as f64).collect();
+		let energy = r.iter().map(|r| (2.0 - r).powi(3)).collect();
+		let force = r.iter().map(|r| 3.0 * (2.0 - r).powi(2)).collect();
+		(r, energy, force)
+	}
+
+	#[test]
+	fn interpolates_cubics_exactly() {
+		let (r, energy, force) = cubic(16);
+		let table = Tabulated::new(r, energy, force).unwrap();
+		table.check(1e-2).unwrap();
+		for distance in [0.5, 0.61, 1.0, 1.37, 1.99] {
+			assert!((table.get_energy(distance) - (2.0 - distance).powi(3)).abs() < 1e-12);
+			assert!((table.get_force(distance) + 3.0 * (2.0 - distance).powi(2)).abs() < 1e-12);
+		}
+		assert_eq!(table.get_energy(2.5), 0.0);
+		assert_eq!(table.get_force(2.5), 0.0);
+	}
+
+	#[test]
+	fn extrapolates_before_the_first_row() {
+		let (r, energy, force) = cubic(7);
+		let table = Tabulated::new(r, energy, force).unwrap();
+		// Linearly, with the force of the first row, so the energy and force are continuous there
+		let (energy, slope) = (1.5_f64.powi(3), -3.0 * 1.5_f64.powi(2));
+		for distance in [0.0, 0.3, 0.5 - 1e-12] {
+			assert!((table.get_energy(distance) - (energy + slope * (distance - 0.5))).abs() < 1e-9);
+			assert_eq!(table.get_force(distance), slope);
+		}
+		assert!((table.get_force(0.5) - slope).abs() < 1e-12);
+	}
+
+	#[test]
+	fn check_requires_zero_at_the_end() {
+		let (r, mut energy, force) = cubic(31);
+		energy.iter_mut().for_each(|u| *u += 0.1);
+		let shifted = Tabulated::new(r.clone(), energy, force.clone()).unwrap();
+		assert!(matches!(shifted.check(1e-2), Err(TableError::NotZeroAtEnd(..))));
+
+		// The energy goes to zero, but with a slope
+		let energy = r.iter().map(|r| 2.0 - r).collect();
+		let sloped = Tabulated::new(r.clone(), energy, vec![1.0; r.len()]).unwrap();
+		assert!(matches!(sloped.check(1e-2), Err(TableError::NotZeroAtEnd(..))));
+	}
+
+	#[test]
+	fn check_requires_consistent_forces() {
+		let (r, energy, force) = cubic(31);
+		let doubled = Tabulated::new(r, energy, force.iter().map(|f| 2.0 * f).collect()).unwrap();
+		assert!(matches!(doubled.check(1e-2), Err(TableError::Inconsistent(..))));
+	}
+
+	#[test]
+	fn parse_reports_lines() {
+		let table = Tabulated::parse("# r U F\n\n1.0 1.0 2.0\n1.5 0.0 0.0\n").unwrap();
+		assert_eq!(table.range(), 1.5);
+		assert!(matches!(Tabulated::parse("1.0 1.0 2.0\n# comment\n0.5 0.0 0.0\n"), Err(TableError::Invalid(3, _))));
+		assert!(matches!(Tabulated::parse("1.0 1.0\n"), Err(TableError::Invalid(1, _))));
+		assert!(matches!(Tabulated::parse("1.0 1.0 2.0\n"), Err(TableError::TooShort)));
+	}
+}
@@ -2,30 +2,595 @@
 //! Functions to set the temperature of a system.
 
 use crate::particles::Particle;
-use crate::constants::{FALLBACK_TEMPERATURE, BOLTZMANN_CONST, TIME_STEP};
+use crate::vectors::{Vector, Tensor};
+use crate::random::Random;
+use crate::constants::{FALLBACK_TEMPERATURE, BOLTZMANN_CONST};
 
 /** Gets the scaling factor for the velocities to achieve a set temperature
  
 A large coupling constant results in a slow change in temperature, 
-whereas a coupling constant equal to TIME_STEP results in instant adjustments.
+whereas a coupling constant equal to the time step dt results in instant adjustments.
 All velocities should be multiplied by the resulting scaling factor.
 */
-pub fn get_scale(particles: &Vec<Particle>, target: f64, coupling: f64) -> f64 {
-	let mut temperature = get_temperature(particles);
+pub fn get_scale(particles: &[Particle], constraints: usize, target: f64, coupling: f64, dt: f64) -> f64 {
+	let mut temperature = get_temperature(particles, constraints);
 
 	if temperature == 0.0 {
 		temperature = FALLBACK_TEMPERATURE;
 	}
 
-	return (1.0 + ((target / temperature) - 1.0) * TIME_STEP / coupling).sqrt();
+	return (1.0 + ((target / temperature) - 1.0) * dt / coupling).sqrt();
 }
 
-/// Gets the current temperature of a system, based on the kinetic energy
-pub fn get_temperature(particles: &Vec<Particle>) -> f64 {
+/// Gets the number of degrees of freedom of a number of particles, three each minus the number removed by constraints.
+pub fn get_degrees_of_freedom(particles: usize, constraints: usize) -> f64 {
+	(3 * particles).saturating_sub(constraints) as f64
+}
+
+/// Gets the current temperature of a system, based on the kinetic energy and the number of degrees of freedom removed by constraints, zero without degrees of freedom
+pub fn get_temperature(particles: &[Particle], constraints: usize) -> f64 {
 	let mut double_kinetic_energy = 0.0;
 	for i in 0..particles.len() {
 		double_kinetic_energy += particles[i].m * particles[i].v.sqlen();
 	}
 
-	return double_kinetic_energy / (3.0 * BOLTZMANN_CONST * (particles.len() as f64));
+	let degrees_of_freedom = get_degrees_of_freedom(particles.len(), constraints);
+	if degrees_of_freedom == 0.0 {
+		return 0.0;
+	}
+	return double_kinetic_energy / (BOLTZMANN_CONST * degrees_of_freedom);
+}
+
+/// Gets twice the kinetic energy of a system.
+fn get_double_kinetic_energy(particles: &[Particle]) -> f64 {
+	particles.iter().map(|p| p.m * p.v.sqlen()).sum()
+}
+
+/// Draws the velocity of each particle from the Maxwell-Boltzmann distribution at a temperature.
+pub fn maxwell_boltzmann(particles: &mut [Particle], temperature: f64, random: &mut Random) {
+	for p in particles.iter_mut() {
+		let sigma = (BOLTZMANN_CONST * temperature / p.m).sqrt();
+		p.v = Vector::new(random.normal(), random.normal(), random.normal()) * sigma;
+	}
+}
+
+/// Gets the position and velocity of the centre of mass.
+fn get_centre_of_mass(particles: &[Particle]) -> (Vector, Vector) {
+	let mass: f64 = particles.iter().map(|p| p.m).sum();
+	let (mut position, mut velocity) = (Vector::zero(), Vector::zero());
+	for p in particles.iter() {
+		position += p.pos * p.m;
+		velocity += p.v * p.m;
+	}
+	(position / mass, velocity / mass)
+}
+
+/// Removes the momentum of the centre of mass, so the system as a whole does not drift.
+pub fn remove_momentum(particles: &mut [Particle]) {
+	if particles.is_empty() {
+		return;
+	}
+	let velocity = get_centre_of_mass(particles).1;
+	for p in particles.iter_mut() {
+		p.v -= velocity;
+	}
+}
+
+/** Removes the angular momentum around the centre of mass, by subtracting the rotation with that angular momentum.
+
+This is only meaningful without periodic axes, as the positions are taken as they are.
+Nothing is removed if the particles lie on a line, as the tensor of inertia cannot be inverted.
+*/
+pub fn remove_angular_momentum(particles: &mut [Particle]) {
+	if particles.is_empty() {
+		return;
+	}
+	let centre = get_centre_of_mass(particles).0;
+	let mut momentum = Vector::zero();
+	let mut inertia = Tensor::zero();
+	for p in particles.iter() {
+		let r = p.pos - centre;
+		momentum += r.cross(&p.v) * p.m;
+		inertia += (Tensor::diagonal(r.sqlen()) + Tensor::outer(&r, &r) * -1.0) * p.m;
+	}
+
+	// Solve inertia * omega = momentum with Cramer's rule, the tensor being symmetric so its rows are also its columns
+	let (a, b, c) = (inertia.x, inertia.y, inertia.z);
+	let determinant = a.dot(&b.cross(&c));
+	if determinant.abs() <= 1e-12 * inertia.trace().powi(3) {
+		return;
+	}
+	let omega = Vector::new(momentum.dot(&b.cross(&c)), a.dot(&momentum.cross(&c)), a.dot(&b.cross(&momentum))) / determinant;
+	for p in particles.iter_mut() {
+		p.v -= omega.cross(&(p.pos - centre));
+	}
+}
+
+/// Scales the velocities so the temperature is exactly the target, leaving them at rest if the system has no kinetic energy.
+pub fn rescale(particles: &mut [Particle], constraints: usize, target: f64) {
+	let temperature = get_temperature(particles, constraints);
+	if temperature == 0.0 {
+		return;
+	}
+	let scale = (target / temperature).sqrt();
+	for p in particles.iter_mut() {
+		p.v = p.v * scale;
+	}
+}
+
+/** A thermostat, coupling the system to a heat bath.
+
+Each step is split around the integrator: `pre_step` is called before the integrator moves the particles, and `post_step` after the new velocities are known.
+Both return the factor by which all velocities were scaled.
+*/
+pub trait Thermostat {
+	/// The part of the step before the integrator, at time t.
+	fn pre_step(&mut self, _particles: &mut [Particle], _t: f64, _dt: f64) -> f64 {
+		1.0
+	}
+
+	/// The part of the step after the integrator, at time t.
+	fn post_step(&mut self, particles: &mut [Particle], t: f64, dt: f64) -> f64;
+
+	/// The energy of the thermostat, so that the total energy of the system plus the thermostat is conserved.
+	fn energy(&self) -> f64;
+}
+
+/** The Berendsen weak-coupling thermostat, which scales the velocities towards a target temperature once the simulation passes a start time.
+
+This does not sample the canonical ensemble. The energy of the thermostat is the kinetic energy it has removed from the system.
+*/
+pub struct Berendsen {
+	/// The target temperature
+	pub target: f64,
+	/// The coupling constant, see get_scale()
+	pub coupling: f64,
+	/// The time at which the thermostat is switched on
+	pub start: f64,
+	/// The number of degrees of freedom removed by constraints
+	pub constraints: usize,
+	/// The kinetic energy removed from the system so far
+	pub removed: f64,
+}
+
+impl Berendsen {
+	/// Create a new thermostat, for a system with a number of degrees of freedom removed by constraints.
+	pub fn new(target: f64, coupling: f64, start: f64, constraints: usize) -> Self {
+		Berendsen{ target, coupling, start, constraints, removed: 0.0 }
+	}
+}
+
+impl Thermostat for Berendsen {
+	fn post_step(&mut self, particles: &mut [Particle], t: f64, dt: f64) -> f64 {
+		if t <= self.start {
+			return 1.0;
+		}
+
+		let scale = get_scale(particles, self.constraints, self.target, self.coupling, dt);
+		self.removed -= (scale * scale - 1.0) * get_double_kinetic_energy(particles) / 2.0;
+		for p in particles.iter_mut() {
+			p.v = p.v * scale;
+		}
+		scale
+	}
+
+	fn energy(&self) -> f64 {
+		self.removed
+	}
+}
+
+/// The weights of the fourth order Suzuki-Yoshida decomposition, used to integrate the Nose-Hoover chain.
+fn get_suzuki_yoshida_weights() -> [f64; 3] {
+	let w = 1.0 / (2.0 - 2.0_f64.cbrt());
+	[w, 1.0 - 2.0 * w, w]
+}
+
+/** A Nose-Hoover chain thermostat, which samples the canonical ensemble at a target temperature once the simulation passes a start time.
+
+The system is coupled to a chain of thermostat variables, where each one thermostats the previous one.
+The chain is integrated for half a time step before and after the integrator, following Martyna et al., Mol. Phys. 87, 1117 (1996).
+*/
+pub struct NoseHooverChain {
+	/// The target temperature
+	pub target: f64,
+	/// The period of the oscillations of the temperature
+	pub coupling: f64,
+	/// The time at which the thermostat is switched on
+	pub start: f64,
+	/// The positions of the thermostat variables
+	pub positions: Vec<f64>,
+	/// The velocities of the thermostat variables
+	pub velocities: Vec<f64>,
+	/// The masses of the thermostat variables
+	masses: Vec<f64>,
+	/// The number of degrees of freedom of the system
+	degrees_of_freedom: f64,
+}
+
+impl NoseHooverChain {
+	/// Create a new thermostat of a given chain length, for a number of particles with a number of degrees of freedom removed by constraints.
+	pub fn new(target: f64, coupling: f64, start: f64, length: usize, particles: usize, constraints: usize) -> Self {
+		let degrees_of_freedom = get_degrees_of_freedom(particles, constraints);
+		let kt = BOLTZMANN_CONST * target.max(FALLBACK_TEMPERATURE);
+		let mut masses = vec![kt * coupling * coupling; length];
+		masses[0] *= degrees_of_freedom;
+		NoseHooverChain{
+			target,
+			coupling,
+			start,
+			positions: vec![0.0; length],
+			velocities: vec![0.0; length],
+			masses,
+			degrees_of_freedom,
+		}
+	}
+
+	/// The force on thermostat variable j, given twice the kinetic energy of the system.
+	fn get_force(&self, j: usize, double_kinetic: f64, kt: f64) -> f64 {
+		if j == 0 {
+			(double_kinetic - self.degrees_of_freedom * kt) / self.masses[0]
+		} else {
+			(self.masses[j - 1] * self.velocities[j - 1] * self.velocities[j - 1] - kt) / self.masses[j]
+		}
+	}
+
+	/// Integrate the chain for half a time step, and scale the velocities of the particles accordingly.
+	fn half_step(&mut self, particles: &mut [Particle], dt: f64) -> f64 {
+		let kt = BOLTZMANN_CONST * self.target;
+		let m = self.velocities.len();
+		let mut double_kinetic = get_double_kinetic_energy(particles);
+		let mut scale = 1.0;
+
+		for w in get_suzuki_yoshida_weights() {
+			let delta = w * dt;
+
+			// Update the velocities from the end of the chain to the start
+			for j in (0..m).rev() {
+				let friction = if j + 1 < m { (-self.velocities[j + 1] * delta / 8.0).exp() } else { 1.0 };
+				self.velocities[j] *= friction;
+				self.velocities[j] += self.get_force(j, double_kinetic, kt) * delta / 4.0;
+				self.velocities[j] *= friction;
+			}
+
+			let s = (-self.velocities[0] * delta / 2.0).exp();
+			scale *= s;
+			double_kinetic *= s * s;
+			for j in 0..m {
+				self.positions[j] += self.velocities[j] * delta / 2.0;
+			}
+
+			// And back from the start of the chain to the end
+			for j in 0..m {
+				let friction = if j + 1 < m { (-self.velocities[j + 1] * delta / 8.0).exp() } else { 1.0 };
+				self.velocities[j] *= friction;
+				self.velocities[j] += self.get_force(j, double_kinetic, kt) * delta / 4.0;
+				self.velocities[j] *= friction;
+			}
+		}
+
+		for p in particles.iter_mut() {
+			p.v = p.v * scale;
+		}
+		scale
+	}
+}
+
+impl Thermostat for NoseHooverChain {
+	fn pre_step(&mut self, particles: &mut [Particle], t: f64, dt: f64) -> f64 {
+		if t < self.start { 1.0 } else { self.half_step(particles, dt) }
+	}
+
+	fn post_step(&mut self, particles: &mut [Particle], t: f64, dt: f64) -> f64 {
+		if t <= self.start { 1.0 } else { self.half_step(particles, dt) }
+	}
+
+	fn energy(&self) -> f64 {
+		let kt = BOLTZMANN_CONST * self.target;
+		let mut energy = 0.0;
+		for j in 0..self.velocities.len() {
+			energy += self.masses[j] * self.velocities[j] * self.velocities[j] / 2.0;
+			energy += if j == 0 { self.degrees_of_freedom } else { 1.0 } * kt * self.positions[j];
+		}
+		energy
+	}
+}
+
+/** The canonical sampling through velocity rescaling (CSVR) thermostat of Bussi, Donadio and Parrinello, J. Chem. Phys. 126, 014101 (2007).
+
+Like the Berendsen thermostat it scales all velocities towards a target temperature, but with a stochastic term so the kinetic energy follows its canonical distribution.
+The energy of the thermostat is the kinetic energy it has removed from the system.
+*/
+pub struct VelocityRescale {
+	/// The target temperature
+	pub target: f64,
+	/// The relaxation time of the kinetic energy
+	pub coupling: f64,
+	/// The time at which the thermostat is switched on
+	pub start: f64,
+	/// The number of degrees of freedom removed by constraints
+	pub constraints: usize,
+	/// The kinetic energy removed from the system so far
+	pub removed: f64,
+	/// The generator of the random numbers
+	pub random: Random,
+}
+
+impl VelocityRescale {
+	/// Create a new thermostat, for a system with a number of degrees of freedom removed by constraints, with a seed for its random numbers.
+	pub fn new(target: f64, coupling: f64, start: f64, constraints: usize, seed: u64) -> Self {
+		VelocityRescale{ target, coupling, start, constraints, removed: 0.0, random: Random::new(seed) }
+	}
+}
+
+impl Thermostat for VelocityRescale {
+	fn post_step(&mut self, particles: &mut [Particle], t: f64, dt: f64) -> f64 {
+		let kinetic = get_double_kinetic_energy(particles) / 2.0;
+		if t <= self.start || kinetic == 0.0 {
+			return 1.0;
+		}
+
+		let degrees_of_freedom = (3 * particles.len()).saturating_sub(self.constraints);
+		let n = degrees_of_freedom as f64;
+		let target = n * BOLTZMANN_CONST * self.target / 2.0;
+		let c = (-dt / self.coupling).exp();
+
+		// The new kinetic energy, sampled from the exact solution of the stochastic equation of the kinetic energy
+		let r1 = self.random.normal();
+		let r_sq = self.random.chi_squared(degrees_of_freedom - 1);
+		let new_kinetic = c * kinetic + (1.0 - c) * target * (r1 * r1 + r_sq) / n
+			+ 2.0 * r1 * (c * (1.0 - c) * kinetic * target / n).sqrt();
+		let mut scale = (new_kinetic / kinetic).sqrt();
+		if r1 + (c * n * kinetic / ((1.0 - c) * target)).sqrt() < 0.0 {
+			scale = -scale;
+		}
+
+		self.removed -= new_kinetic - kinetic;
+		for p in particles.iter_mut() {
+			p.v = p.v * scale;
+		}
+		scale
+	}
+
+	fn energy(&self) -> f64 {
+		self.removed
+	}
+}
+
+/** The Andersen thermostat, where particles randomly collide with a heat bath.
+
+Each step, each particle collides with a probability of dt / coupling, after which its velocity is drawn from the Maxwell-Boltzmann distribution at the target temperature.
+This samples the canonical ensemble, but disturbs the dynamics of the particles.
+The energy of the thermostat is the kinetic energy it has removed from the system.
+*/
+pub struct Andersen {
+	/// The target temperature
+	pub target: f64,
+	/// The average time between collisions of a particle
+	pub coupling: f64,
+	/// The time at which the thermostat is switched on
+	pub start: f64,
+	/// The kinetic energy removed from the system so far
+	pub removed: f64,
+	/// The generator of the random numbers
+	pub random: Random,
+}
+
+impl Andersen {
+	/// Create a new thermostat, with a seed for its random numbers.
+	pub fn new(target: f64, coupling: f64, start: f64, seed: u64) -> Self {
+		Andersen{ target, coupling, start, removed: 0.0, random: Random::new(seed) }
+	}
+}
+
+impl Thermostat for Andersen {
+	fn post_step(&mut self, particles: &mut [Particle], t: f64, dt: f64) -> f64 {
+		if t <= self.start {
+			return 1.0;
+		}
+
+		let probability = dt / self.coupling;
+		for p in particles.iter_mut() {
+			if self.random.uniform() < probability {
+				let sigma = (BOLTZMANN_CONST * self.target / p.m).sqrt();
+				let v = Vector::new(self.random.normal(), self.random.normal(), self.random.normal()) * sigma;
+				self.removed -= p.m * (v.sqlen() - p.v.sqlen()) / 2.0;
+				p.v = v;
+			}
+		}
+		// The velocities are not scaled uniformly
+		1.0
+	}
+
+	fn energy(&self) -> f64 {
+		self.removed
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::forcefield::{ForceField, Electrostatics};
+	use crate::forcefield::vanderwaals::{Cutoff, CutoffScheme};
+	use crate::simbox::SimBox;
+	use crate::integrators::VelocityVerlet;
+	use crate::simulation::Simulation;
+
+	/// Particles of different masses at random positions in a cube, with velocities drawn at a temperature.
+	fn particles(count: usize, temperature: f64, seed: u64) -> Vec<Particle> {
+		let mut random = Random::new(seed);
+		let mut particles: Vec<Particle> = (0..count).map(|i| {
+			let pos = Vector::new(random.uniform(), random.uniform(), random.uniform()) * 10.0;
+			Particle::new(&pos, 0.5, 1.0 + (i % 3) as f64, 0.0)
+		}).collect();
+		maxwell_boltzmann(&mut particles, temperature, &mut random);
+		particles
+	}
+
+	fn momentum(particles: &[Particle]) -> Vector {
+		particles.iter().fold(Vector::zero(), |sum, p| sum + p.v * p.m)
+	}
+
+	fn angular_momentum(particles: &[Particle]) -> Vector {
+		let centre = get_centre_of_mass(particles).0;
+		particles.iter().fold(Vector::zero(), |sum, p| sum + (p.pos - centre).cross(&p.v) * p.m)
+	}
+
+	#[test]
+	fn maxwell_boltzmann_has_the_temperature() {
+		let temperature = 2.5;
+		let particles = particles(20000, temperature, 1);
+		// Each component is normal with variance kT / m, so the mean of m v_x^2 is kT
+		for component in [|v: Vector| v.x, |v: Vector| v.y, |v: Vector| v.z] {
+			let mean = particles.iter().map(|p| p.m * component(p.v).powi(2)).sum::<f64>() / particles.len() as f64;
+			assert!((mean / temperature - 1.0).abs() < 0.03, "{} != {}", mean, temperature);
+			let mean = particles.iter().map(|p| p.m.sqrt() * component(p.v)).sum::<f64>() / particles.len() as f64;
+			assert!(mean.abs() < 0.05);
+		}
+		assert!((get_temperature(&particles, 0) / temperature - 1.0).abs() < 0.02);
+	}
+
+	#[test]
+	fn momentum_is_removed() {
+		let mut particles = particles(50, 1.0, 2);
+		remove_momentum(&mut particles);
+		assert!(momentum(&particles).len() < 1e-12);
+	}
+
+	#[test]
+	fn angular_momentum_is_removed() {
+		let mut particles = particles(50, 1.0, 3);
+		assert!(angular_momentum(&particles).len() > 1.0);
+		remove_momentum(&mut particles);
+		remove_angular_momentum(&mut particles);
+		assert!(angular_momentum(&particles).len() < 1e-10);
+		// A rotation around the centre of mass carries no momentum
+		assert!(momentum(&particles).len() < 1e-10);
+	}
+
+	#[test]
+	fn rescale_sets_the_temperature_exactly() {
+		let mut particles = particles(50, 1.0, 4);
+		remove_momentum(&mut particles);
+		rescale(&mut particles, 3, 1.5);
+		let double_kinetic = get_double_kinetic_energy(&particles);
+		assert!((double_kinetic / (3.0 * 50.0 - 3.0) - 1.5).abs() < 1e-12);
+		assert!((get_temperature(&particles, 3) - 1.5).abs() < 1e-12);
+	}
+
+	#[test]
+	fn no_degrees_of_freedom_have_no_temperature() {
+		let mut particles = particles(1, 1.0, 5);
+		remove_momentum(&mut particles);
+		assert_eq!(get_temperature(&particles, 3), 0.0);
+		rescale(&mut particles, 3, 1.0);
+		assert!(particles[0].v.len() < 1e-12);
+	}
+
+	/// A Lennard-Jones fluid on a slightly displaced cubic lattice in a periodic box, with velocities drawn at a temperature.
+	fn lennard_jones(cells: usize, temperature: f64, seed: u64) -> (Vec<Particle>, ForceField) {
+		let spacing = 2.3;
+		let mut random = Random::new(seed);
+		let mut particles: Vec<Particle> = (0..cells * cells * cells).map(|i| {
+			let cell = Vector::new((i % cells) as f64, (i / cells % cells) as f64, (i / cells / cells) as f64) + Vector::new(0.5, 0.5, 0.5);
+			let displacement = Vector::new(random.uniform(), random.uniform(), random.uniform()) * 0.1;
+			Particle::new(&(cell * spacing + displacement), 0.5, 1.0, 0.0)
+		}).collect();
+		maxwell_boltzmann(&mut particles, temperature, &mut random);
+
+		let mut forcefield = ForceField::new();
+		forcefield.electrostatic = Electrostatics::Off;
+		forcefield.borders = None;
+		let size = cells as f64 * spacing;
+		forcefield.simbox = SimBox::new(Vector::new(size, size, size), true);
+		forcefield.cutoff = Some(Cutoff{ radius: 4.0, scheme: CutoffScheme::ForceShifted, tail_correction: false });
+		(particles, forcefield)
+	}
+
+	#[test]
+	fn nose_hoover_chain_samples_the_temperature() {
+		let (particles, forcefield) = lennard_jones(4, 1.0, 6);
+		let count = particles.len();
+		let thermostat = NoseHooverChain::new(1.5, 0.1, 0.0, 3, count, 0);
+		let mut sim = Simulation::new(particles, forcefield, Box::new(VelocityVerlet), Some(Box::new(thermostat)), None, None, 0, 0.002).unwrap();
+		sim.run(10000).unwrap();
+
+		let conserved = sim.data.global.get("energy_conserved");
+		let start = conserved[0];
+		let drift = conserved.iter().map(|e| (e - start).abs()).fold(0.0, f64::max);
+		assert!(drift < 1e-3 * count as f64, "drift {}", drift);
+		// The thermostat did heat the system, as the temperature started below the target
+		assert!(sim.data.global.get("energy_thermostat").iter().any(|e| e.abs() > 0.1 * count as f64));
+
+		let temperatures = sim.data.global.get("temperature");
+		let equilibrated = &temperatures[temperatures.len() / 4..];
+		let mean = equilibrated.iter().sum::<f64>() / equilibrated.len() as f64;
+		assert!((mean / 1.5 - 1.0).abs() < 0.05, "{} != 1.5", mean);
+	}
+
+	/// The error in the conserved energy of an ideal gas coupled to a Nose-Hoover chain, after integrating the chain alone for some time.
+	fn nose_hoover_chain_error(dt: f64) -> f64 {
+		let mut particles = particles(20, 1.0, 7);
+		let mut thermostat = NoseHooverChain::new(2.0, 0.5, 0.0, 3, particles.len(), 0);
+		let energy = |particles: &[Particle], thermostat: &NoseHooverChain| get_double_kinetic_energy(particles) / 2.0 + thermostat.energy();
+		let start = energy(&particles, &thermostat);
+		// Each half step advances the chain by dt / 2
+		for _ in 0..(4.0 / dt).round() as usize {
+			thermostat.half_step(&mut particles, dt);
+		}
+		assert!(thermostat.energy().abs() > 1.0);
+		(energy(&particles, &thermostat) - start).abs()
+	}
+
+	#[test]
+	fn nose_hoover_chain_is_fourth_order() {
+		// Halving the time step of a fourth order method reduces its error by 16, against 4 for a second order one
+		let coarse = nose_hoover_chain_error(0.1);
+		let fine = nose_hoover_chain_error(0.05);
+		assert!(coarse / fine > 10.0, "{} / {} = {}", coarse, fine, coarse / fine);
+		assert!(coarse < 0.05);
+	}
+
+	/// The mean and the variance of some samples.
+	fn mean_and_variance(samples: &[f64]) -> (f64, f64) {
+		let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+		let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+		(mean, variance)
+	}
+
+	/// The kinetic energy of an ideal gas after each of a number of steps of a thermostat, checking that it plus the energy of the thermostat is constant.
+	fn thermostat_kinetic_energies(thermostat: &mut dyn Thermostat, particles: &mut [Particle], steps: usize, dt: f64) -> Vec<f64> {
+		let start = get_double_kinetic_energy(particles) / 2.0 + thermostat.energy();
+		let kinetic = (1..=steps).map(|step| {
+			thermostat.post_step(particles, step as f64 * dt, dt);
+			get_double_kinetic_energy(particles) / 2.0
+		}).collect::<Vec<f64>>();
+		assert!((kinetic[steps - 1] + thermostat.energy() - start).abs() < 1e-9 * steps as f64);
+		kinetic
+	}
+
+	#[test]
+	fn velocity_rescale_samples_the_canonical_kinetic_energy() {
+		let temperature = 2.0;
+		let mut particles = particles(10, 0.5, 8);
+		let mut thermostat = VelocityRescale::new(temperature, 0.1, 0.0, 0, 9);
+		let kinetic = thermostat_kinetic_energies(&mut thermostat, &mut particles, 100000, 0.01);
+		// The kinetic energy of n degrees of freedom is gamma distributed, with a mean of n kT / 2 and a variance of n (kT)^2 / 2
+		let n = 30.0;
+		let kt = BOLTZMANN_CONST * temperature;
+		let (mean, variance) = mean_and_variance(&kinetic[1000..]);
+		assert!((mean / (n * kt / 2.0) - 1.0).abs() < 0.02, "{} != {}", mean, n * kt / 2.0);
+		assert!((variance / (n * kt * kt / 2.0) - 1.0).abs() < 0.07, "{} != {}", variance, n * kt * kt / 2.0);
+	}
+
+	#[test]
+	fn andersen_reaches_the_temperature() {
+		let temperature = 2.0;
+		let dt = 0.01;
+		let mut particles = particles(100, 0.5, 10);
+		let mut thermostat = Andersen::new(temperature, 0.1, 0.0, 11);
+		let kinetic = thermostat_kinetic_energies(&mut thermostat, &mut particles, 20000, dt);
+		let (mean, _) = mean_and_variance(&kinetic[1000..]);
+		let expected = 3.0 * particles.len() as f64 * BOLTZMANN_CONST * temperature / 2.0;
+		assert!((mean / expected - 1.0).abs() < 0.02, "{} != {}", mean, expected);
+	}
 }
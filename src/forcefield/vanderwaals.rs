@@ -1,39 +1,98 @@
-
 //! Gets the van der Waals potential and force, according to the Lennard-Jones 12-6 expression
 
-use crate::constants::LJ_4_EPSILON;
-
 /** Gets the potential of a given configuration of two particles.
 
+The epsilon_4 is 4 * the well depth of the potential.
 The radius is the average radius of the two particles.
 The total distance is the distance between the centres of the particles
 */
-pub fn get_potential(radius: f64, total_distance: f64) -> f64 {
+pub fn get_potential(epsilon_4: f64, radius: f64, total_distance: f64) -> f64 {
 	//4e ( (s/r)^12 - (s/r)^6 )
 	// r is dist, s is radius, e is well depth
-  
-//TODO: cutoff
-//TODO: is this right like this?	
-	let distance = total_distance - radius;// * 2_f64.powf(1.0/6.0);
+	// r is the distance between the centres minus s, as documented in config.toml,
+	// so the potential diverges where the centres are s apart and its minimum is 2^(1/6) s further
+	let distance = total_distance - radius;
 
 	let attraction = (radius / distance).powf(6.0);
 	let repulsion = attraction * attraction;
 
-	return LJ_4_EPSILON * (repulsion - attraction); 
+	return epsilon_4 * (repulsion - attraction); 
 }
 
 /** Gets the magnitude of the force between two particles.
 
+The epsilon_4 is 4 * the well depth of the potential.
 The radius is the average radius of the two particles.
 The total distance is the distance between the centres of the particles
 */
-pub fn get_force(radius: f64, total_distance: f64) -> f64 {
+pub fn get_force(epsilon_4: f64, radius: f64, total_distance: f64) -> f64 {
 	// 4e ( -12 s^12 r^-13 + 6 s^6 r^-7)
 	// 4e ( 6 s^6 (r^-7 - 2 s^6 r^-13 )
 	// 6 * 4e s^6 r^-7 (1 - 2 s^6 r^-6)
 	let s6 = radius.powf(6.0);
-	let distance = total_distance - radius; //* 2_f64.powf(1.0/6.0);
+	let distance = total_distance - radius;
+
+	return 6.0 * epsilon_4 * s6 * distance.powf(-7.0) * (1.0 - (2.0 * s6 * distance.powf(-6.0)));
+}
+
+/// How the potential is modified to go to zero at the cutoff.
+#[derive(Clone, Copy)]
+pub enum CutoffScheme {
+	/// The potential and force are simply set to zero beyond the cutoff, so the energy jumps at the cutoff.
+	Truncated,
+	/// The potential is shifted so it is zero at the cutoff, the force is unchanged.
+	EnergyShifted,
+	/// The force is shifted so it is zero at the cutoff, and the potential is changed to match.
+	ForceShifted,
+	/// The potential is smoothly switched off between start and the cutoff, using a fifth order polynomial.
+	Switched {
+		/// The distance at which the switching starts
+		start: f64,
+	},
+}
+
+/// A cutoff of the pair potentials.
+#[derive(Clone, Copy)]
+pub struct Cutoff {
+	/// The distance (between the centres) beyond which the potential is zero
+	pub radius: f64,
+	/// How the potential goes to zero at the cutoff
+	pub scheme: CutoffScheme,
+	/// Whether to calculate the long-range tail corrections for the part of the potential beyond the cutoff
+	pub tail_correction: bool,
+}
 
-	return 6.0 * LJ_4_EPSILON * s6 * distance.powf(-7.0) * (1.0 - (2.0 * s6 * distance.powf(-6.0)));
+/// The integral of x^power from start to infinity, for power < -1.
+pub(crate) fn tail_integral(start: f64, power: i32) -> f64 {
+	-start.powi(power + 1) / (power + 1) as f64
 }
 
+/** Gets the integral of r^2 * U(r) from the cutoff to infinity, for a single pair.
+
+The energy tail correction of the system is the sum of 4 pi / V times this integral over all pairs.
+*/
+pub fn get_tail_energy(epsilon_4: f64, radius: f64, cutoff: f64) -> f64 {
+	// The potential is a function of x = r - s, so expand r^2 = x^2 + 2 s x + s^2
+	let s = radius;
+	let x = cutoff - radius;
+	let mut integral = 0.0;
+	for (coefficient, power) in [(1.0, 2), (2.0 * s, 1), (s * s, 0)] {
+		integral += coefficient * (s.powi(12) * tail_integral(x, power - 12) - s.powi(6) * tail_integral(x, power - 6));
+	}
+	epsilon_4 * integral
+}
+
+/** Gets the integral of r^3 * dU/dr from the cutoff to infinity, for a single pair.
+
+The pressure tail correction of the system is the sum of -4 pi / (3 V^2) times this integral over all pairs.
+*/
+pub fn get_tail_virial(epsilon_4: f64, radius: f64, cutoff: f64) -> f64 {
+	// The potential is a function of x = r - s, so expand r^3 = x^3 + 3 s x^2 + 3 s^2 x + s^3
+	let s = radius;
+	let x = cutoff - radius;
+	let mut integral = 0.0;
+	for (coefficient, power) in [(1.0, 3), (3.0 * s, 2), (3.0 * s * s, 1), (s * s * s, 0)] {
+		integral += coefficient * (-12.0 * s.powi(12) * tail_integral(x, power - 13) + 6.0 * s.powi(6) * tail_integral(x, power - 7));
+	}
+	epsilon_4 * integral
+}
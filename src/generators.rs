@@ -0,0 +1,389 @@
+/*! Generators of initial configurations: lattices filling the box at a density, and random insertion of particles without overlaps.
+
+The species of a mixture follow from their mole fractions with mixture(), in a random order, so the species are mixed over the box.
+Along axes that are not periodic the particles are kept a radius away from the walls, and a container keeps them a radius inside its wall.
+*/
+
+use std::fmt;
+use std::error::Error;
+
+use crate::vectors::Vector;
+use crate::particles::Particle;
+use crate::species::SpeciesTable;
+use crate::simbox::SimBox;
+use crate::forcefield::borders::Container;
+use crate::random::Random;
+
+/// The lattices that can fill the box.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Lattice {
+	/// Simple cubic, one site per cubic cell
+	SimpleCubic,
+	/// Body-centred cubic, two sites per cubic cell
+	BodyCentredCubic,
+	/// Face-centred cubic, four sites per cubic cell
+	FaceCentredCubic,
+	/// Hexagonal close-packed, four sites per orthorhombic cell of a by sqrt(3) a by sqrt(8/3) a, stacked along z
+	HexagonalClosePacked,
+}
+
+impl Lattice {
+	/// The sites in a unit cell, in fractions of the cell, shifted so no site is on the side of the cell.
+	pub fn basis(&self) -> &'static [[f64; 3]] {
+		match self {
+			Lattice::SimpleCubic => &[[0.5, 0.5, 0.5]],
+			Lattice::BodyCentredCubic => &[[0.25, 0.25, 0.25], [0.75, 0.75, 0.75]],
+			Lattice::FaceCentredCubic => &[[0.25, 0.25, 0.25], [0.75, 0.75, 0.25], [0.75, 0.25, 0.75], [0.25, 0.75, 0.75]],
+			Lattice::HexagonalClosePacked => &[[0.25, 1.0 / 12.0, 0.25], [0.75, 7.0 / 12.0, 0.25], [0.75, 11.0 / 12.0, 0.75], [0.25, 5.0 / 12.0, 0.75]],
+		}
+	}
+
+	/// The sides of the unit cell, in units of the lattice constant.
+	pub fn cell(&self) -> Vector {
+		match self {
+			Lattice::HexagonalClosePacked => Vector::new(1.0, 3.0_f64.sqrt(), (8.0_f64 / 3.0).sqrt()),
+			_ => Vector::new(1.0, 1.0, 1.0),
+		}
+	}
+
+	/// The start, number and sides of the cells along each axis filling the box at a number density, see positions().
+	fn cells(&self, simbox: &SimBox, density: f64, margin: f64) -> ([f64; 3], [f64; 3], [f64; 3]) {
+		let shape = self.cell();
+		// The lattice constant giving the density
+		let constant = (self.basis().len() as f64 / (density * shape.x * shape.y * shape.z)).cbrt();
+		let size = [simbox.size.x, simbox.size.y, simbox.size.z];
+		let shape = [shape.x, shape.y, shape.z];
+
+		let mut start = [0.0; 3];
+		let mut cells = [0.0; 3];
+		let mut side = [0.0; 3];
+		for k in 0..3 {
+			let margin = if simbox.periodic[k] { 0.0 } else { margin };
+			let length = (size[k] - 2.0 * margin).max(0.0);
+			start[k] = margin;
+			cells[k] = (length / (constant * shape[k])).round().max(1.0);
+			side[k] = length / cells[k];
+		}
+		(start, cells, side)
+	}
+
+	/// The number of sites of the lattice filling the box at a number density, as a float so it does not overflow, see positions().
+	pub fn sites(&self, simbox: &SimBox, density: f64, margin: f64) -> f64 {
+		let (_, cells, _) = self.cells(simbox, density, margin);
+		cells.iter().product::<f64>() * self.basis().len() as f64
+	}
+
+	/// The positions of the sites of the lattice filling the box at a number density.
+	///
+	/// The number of cells along each axis is rounded so the cells tile the box exactly, which slightly strains the lattice and changes the density.
+	/// Along axes that are not periodic the lattice fills the box minus a margin at each wall.
+	pub fn positions(&self, simbox: &SimBox, density: f64, margin: f64) -> Vec<Vector> {
+		let basis = self.basis();
+		let (start, cells, side) = self.cells(simbox, density, margin);
+		let cells = cells.map(|n| n as usize);
+
+		let mut positions = Vec::with_capacity(cells[0] * cells[1] * cells[2] * basis.len());
+		for i in 0..cells[0] {
+			for j in 0..cells[1] {
+				for l in 0..cells[2] {
+					for site in basis {
+						let cell = [i, j, l];
+						let [x, y, z] = [0, 1, 2].map(|k| start[k] + (cell[k] as f64 + site[k]) * side[k]);
+						positions.push(Vector::new(x, y, z));
+					}
+				}
+			}
+		}
+		positions
+	}
+}
+
+/// An error when not all particles can be inserted without overlaps.
+#[derive(Debug)]
+pub struct InsertionError {
+	/// The number of particles that were inserted
+	pub inserted: usize,
+	/// The number of particles that should have been inserted
+	pub count: usize,
+}
+
+impl fmt::Display for InsertionError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Could only insert {} of {} particles without overlaps", self.inserted, self.count)
+	}
+}
+
+impl Error for InsertionError {}
+
+/** The species of a number of particles of a mixture, in a random order.
+
+The fractions are the mole fractions of the species, by index in the species table, which are normalised to add up to one.
+The number of particles of each species is rounded so the total is the given number, giving the leftover particles to the largest remainders.
+*/
+pub fn mixture(fractions: &[(usize, f64)], count: usize, random: &mut Random) -> Vec<usize> {
+	let total: f64 = fractions.iter().map(|&(_, x)| x).sum();
+	let exact: Vec<f64> = fractions.iter().map(|&(_, x)| x / total * count as f64).collect();
+	let mut counts: Vec<usize> = exact.iter().map(|x| x.floor() as usize).collect();
+	let mut order: Vec<usize> = (0..fractions.len()).collect();
+	order.sort_by(|&a, &b| (exact[b] - exact[b].floor()).total_cmp(&(exact[a] - exact[a].floor())));
+	let left = count - counts.iter().sum::<usize>();
+	for &i in order.iter().take(left) {
+		counts[i] += 1;
+	}
+
+	let mut species: Vec<usize> = fractions.iter().zip(counts).flat_map(|(&(s, _), n)| std::iter::repeat_n(s, n)).collect();
+	// Fisher-Yates shuffle
+	for i in (1..species.len()).rev() {
+		let j = ((random.uniform() * (i + 1) as f64) as usize).min(i);
+		species.swap(i, j);
+	}
+	species
+}
+
+/// The largest radius of the species of a mixture.
+pub fn largest_radius(fractions: &[(usize, f64)], table: &SpeciesTable) -> f64 {
+	fractions.iter().map(|&(s, _)| table.species()[s].sigma).fold(0.0, f64::max)
+}
+
+/// Fill the box with a lattice of a mixture of species at a number density, leaving out the sites outside of the container, if any.
+pub fn fill_lattice(lattice: Lattice, simbox: &SimBox, container: Option<&Container>, density: f64, fractions: &[(usize, f64)], table: &SpeciesTable, random: &mut Random) -> Vec<Particle> {
+	let margin = largest_radius(fractions, table);
+	let positions: Vec<Vector> = lattice.positions(simbox, density, margin).into_iter()
+		.filter(|pos| container.is_none_or(|c| c.distance(pos).0 >= margin))
+		.collect();
+	let species = mixture(fractions, positions.len(), random);
+	positions.iter().zip(species).map(|(pos, s)| table.particle(s, pos)).collect()
+}
+
+/// A grid of cells of at least a range, holding the indices of the positions in each, so only neighbouring cells have to be searched for the positions within the range.
+struct Grid {
+	size: [f64; 3],
+	periodic: [bool; 3],
+	cells: [usize; 3],
+	members: Vec<Vec<usize>>,
+}
+
+impl Grid {
+	/// Create an empty grid over the box, with at most about one cell per expected position.
+	fn new(simbox: &SimBox, range: f64, count: usize) -> Self {
+		let size = [simbox.size.x, simbox.size.y, simbox.size.z];
+		let mut cells = size.map(|l| (l / range).floor().max(1.0));
+		let total: f64 = cells.iter().product();
+		if total > count.max(1) as f64 {
+			let factor = (total / count.max(1) as f64).cbrt();
+			cells = cells.map(|n| (n / factor).floor().max(1.0));
+		}
+		let cells = cells.map(|n| n as usize);
+		Grid{ size, periodic: simbox.periodic, cells, members: vec![Vec::new(); cells[0] * cells[1] * cells[2]] }
+	}
+
+	/// The cell of a position along each axis, positions outside of a walled box are put in the outermost cell.
+	fn cell(&self, pos: &Vector) -> [usize; 3] {
+		let pos = [pos.x, pos.y, pos.z];
+		[0, 1, 2].map(|k| ((pos[k] / self.size[k] * self.cells[k] as f64).floor().max(0.0) as usize).min(self.cells[k] - 1))
+	}
+
+	fn index(&self, cell: [usize; 3]) -> usize {
+		(cell[0] * self.cells[1] + cell[1]) * self.cells[2] + cell[2]
+	}
+
+	/// Add the index of a position.
+	fn insert(&mut self, pos: &Vector, i: usize) {
+		let index = self.index(self.cell(pos));
+		self.members[index].push(i);
+	}
+
+	/// The indices of the positions in the cell of a position and its neighbours, which include all positions within the range.
+	fn near(&self, pos: &Vector) -> Vec<usize> {
+		let cell = self.cell(pos);
+		let mut cells = Vec::with_capacity(27);
+		for dx in -1..=1_i64 {
+			for dy in -1..=1_i64 {
+				for dz in -1..=1_i64 {
+					let mut neighbor = [0; 3];
+					let mut valid = true;
+					for (k, d) in [dx, dy, dz].into_iter().enumerate() {
+						let n = cell[k] as i64 + d;
+						if n >= 0 && n < self.cells[k] as i64 {
+							neighbor[k] = n as usize;
+						} else if self.periodic[k] {
+							neighbor[k] = n.rem_euclid(self.cells[k] as i64) as usize;
+						} else {
+							valid = false;
+						}
+					}
+					// A small periodic box can wrap around to the same cell
+					let index = self.index(neighbor);
+					if valid && !cells.contains(&index) {
+						cells.push(index);
+					}
+				}
+			}
+		}
+		cells.into_iter().flat_map(|c| self.members[c].iter().copied()).collect()
+	}
+}
+
+/** Insert particles of the given species, such as those of a mixture(), at random positions in the box, and inside the container, if any.
+
+Each particle is tried at up to a number of attempts of random positions,
+and is only inserted at least the minimum distance away from the existing and the inserted particles, and away further than the min_distance() of their pair potential.
+Returns only the inserted particles, and fails if a particle cannot be inserted.
+The particles are kept in a grid, so each attempt is only compared to the particles close to it.
+*/
+#[allow(clippy::too_many_arguments)]
+pub fn insert_random(species: &[usize], existing: &[Particle], simbox: &SimBox, container: Option<&Container>, min_distance: f64, attempts: usize, table: &SpeciesTable, random: &mut Random) -> Result<Vec<Particle>, InsertionError> {
+	let count = species.len();
+	let mut particles: Vec<Particle> = Vec::with_capacity(count);
+	// All pairs further apart than the range are free
+	let mut kinds: Vec<usize> = existing.iter().map(|p| p.species).chain(species.iter().copied()).collect();
+	kinds.sort_unstable();
+	kinds.dedup();
+	let range = kinds.iter().flat_map(|&a| kinds.iter().map(move |&b| (a, b)))
+		.map(|(a, b)| table.get_potential(a, b).min_distance())
+		.fold(min_distance, f64::max);
+	let mut grid = Grid::new(simbox, range, existing.len() + count);
+	for (i, p) in existing.iter().enumerate() {
+		grid.insert(&p.pos, i);
+	}
+	for (inserted, &s) in species.iter().enumerate() {
+		let radius = table.species()[s].sigma;
+		let mut position = None;
+		for _ in 0..attempts {
+			let mut coordinate = |size: f64, periodic: bool| if periodic { random.uniform() * size } else { radius + random.uniform() * (size - 2.0 * radius) };
+			let pos = Vector::new(coordinate(simbox.size.x, simbox.periodic[0]),
+			                      coordinate(simbox.size.y, simbox.periodic[1]),
+			                      coordinate(simbox.size.z, simbox.periodic[2]));
+			if container.is_some_and(|c| c.distance(&pos).0 < radius) {
+				continue;
+			}
+			let free = grid.near(&pos).into_iter().all(|i| {
+				let p = if i < existing.len() { &existing[i] } else { &particles[i - existing.len()] };
+				let distance = simbox.separation(&p.pos, &pos).len();
+				distance >= min_distance && distance > table.get_potential(p.species, s).min_distance()
+			});
+			if free {
+				position = Some(pos);
+				break;
+			}
+		}
+		match position {
+			Some(pos) => {
+				grid.insert(&pos, existing.len() + particles.len());
+				particles.push(table.particle(s, &pos));
+			}
+			None => return Err(InsertionError{ inserted, count }),
+		}
+	}
+	Ok(particles)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::species::{Species, MixingRule};
+
+	const LATTICES: [(Lattice, f64, usize); 4] = [
+		// The nearest-neighbour distance in lattice constants, and the number of nearest neighbours
+		(Lattice::SimpleCubic, 1.0, 6),
+		(Lattice::BodyCentredCubic, 0.8660254037844386, 8),
+		(Lattice::FaceCentredCubic, std::f64::consts::FRAC_1_SQRT_2, 12),
+		(Lattice::HexagonalClosePacked, 1.0, 12),
+	];
+
+	fn table() -> SpeciesTable {
+		let mut table = SpeciesTable::new(MixingRule::LorentzBerthelot);
+		table.add(Species{ name: String::from("a"), sigma: 1.0, epsilon: 1.0, mass: 1.0, charge: 0.0 });
+		table.add(Species{ name: String::from("b"), sigma: 0.5, epsilon: 1.0, mass: 1.0, charge: 0.0 });
+		table
+	}
+
+	#[test]
+	fn lattices_have_their_nearest_neighbours() {
+		let density = 0.8;
+		for (lattice, nearest, neighbours) in LATTICES {
+			let shape = lattice.cell();
+			let constant = (lattice.basis().len() as f64 / (density * shape.x * shape.y * shape.z)).cbrt();
+			let simbox = SimBox::new(shape * (4.0 * constant), true);
+			let positions = lattice.positions(&simbox, density, 0.0);
+			assert_eq!(positions.len(), 64 * lattice.basis().len());
+			assert_eq!(lattice.sites(&simbox, density, 0.0), positions.len() as f64);
+			assert!((positions.len() as f64 / simbox.volume() - density).abs() < 1e-12);
+			for a in positions.iter() {
+				let distances: Vec<f64> = positions.iter().map(|b| simbox.separation(a, b).len()).filter(|&r| r > 1e-9).collect();
+				let closest = distances.iter().copied().fold(f64::INFINITY, f64::min);
+				assert!((closest - nearest * constant).abs() < 1e-9, "{} != {}", closest, nearest * constant);
+				assert_eq!(distances.iter().filter(|&&r| r < closest + 1e-9).count(), neighbours);
+			}
+		}
+	}
+
+	#[test]
+	fn lattices_keep_a_margin_from_walls() {
+		let simbox = SimBox::new(Vector::new(10.0, 10.0, 10.0), false);
+		for (lattice, _, _) in LATTICES {
+			let positions = lattice.positions(&simbox, 0.5, 1.0);
+			assert_eq!(lattice.sites(&simbox, 0.5, 1.0), positions.len() as f64);
+			assert!(positions.iter().all(|p| [p.x, p.y, p.z].iter().all(|&x| (1.0..=9.0).contains(&x))));
+		}
+	}
+
+	#[test]
+	fn mixture_follows_the_fractions() {
+		let mut random = Random::new(1);
+		let species = mixture(&[(0, 0.75), (1, 0.25), (2, 0.0)], 10, &mut random);
+		assert_eq!(species.len(), 10);
+		// 7.5 and 2.5, with the leftover particle going to either
+		let a = species.iter().filter(|&&s| s == 0).count();
+		assert!(a == 7 || a == 8);
+		assert_eq!(species.iter().filter(|&&s| s == 2).count(), 0);
+	}
+
+	#[test]
+	fn insertion_avoids_the_existing_particles() {
+		let table = table();
+		let simbox = SimBox::new(Vector::new(6.0, 6.0, 6.0), true);
+		let existing: Vec<Particle> = [(1.0, 1.0, 1.0), (3.0, 3.0, 3.0), (5.0, 5.0, 1.0)].iter()
+			.map(|&(x, y, z)| table.particle(0, &Vector::new(x, y, z)))
+			.collect();
+		let mut random = Random::new(2);
+		let species = mixture(&[(0, 1.0), (1, 1.0)], 30, &mut random);
+		let inserted = insert_random(&species, &existing, &simbox, None, 1.1, 1000, &table, &mut random).unwrap();
+		assert_eq!(inserted.len(), 30);
+		for (i, p) in inserted.iter().enumerate() {
+			for q in existing.iter().chain(inserted[..i].iter()) {
+				assert!(simbox.separation(&p.pos, &q.pos).len() >= 1.1);
+			}
+		}
+	}
+
+	#[test]
+	fn insertion_scales_to_many_particles() {
+		let table = table();
+		let simbox = SimBox::new(Vector::new(40.0, 40.0, 40.0), true);
+		let mut random = Random::new(4);
+		let species = mixture(&[(0, 1.0), (1, 1.0)], 20000, &mut random);
+		let inserted = insert_random(&species, &[], &simbox, None, 0.0, 1000, &table, &mut random).unwrap();
+		assert_eq!(inserted.len(), 20000);
+		// Compare with the pairs of a neighbor list
+		let mut neighbors = crate::neighbors::NeighborList::new(1.0, 0.0);
+		neighbors.update(&simbox, &inserted);
+		for &(i, j) in neighbors.pairs() {
+			let distance = simbox.separation(&inserted[i].pos, &inserted[j].pos).len();
+			assert!(distance > table.get_potential(inserted[i].species, inserted[j].species).min_distance());
+		}
+	}
+
+	#[test]
+	fn insertion_fails_when_full() {
+		let table = table();
+		let simbox = SimBox::new(Vector::new(3.0, 3.0, 3.0), true);
+		let existing = vec![table.particle(0, &Vector::new(1.5, 1.5, 1.5))];
+		let mut random = Random::new(3);
+		// Any position is within 1.5 sqrt(3) of the existing particle
+		match insert_random(&[0], &existing, &simbox, None, 2.7, 100, &table, &mut random) {
+			Err(e) => assert_eq!((e.inserted, e.count), (0, 1)),
+			Ok(_) => panic!("expected the insertion to fail"),
+		}
+	}
+}
@@ -5,7 +5,7 @@ Algorithm taken from <http://blog.andreaskahler.com/2009/06/creating-icosphere-m
 */
 
 use std::collections::HashMap;
-use crate::vectors::Vector;
+use molecular_dynamics::vectors::Vector;
 
 /// Adds a vertex to the mesh, fixing it to be on the unit sphere.
 fn add_vertex(vertices: &mut Vec::<[f64; 3]>, x: f64, y: f64, z: f64) {
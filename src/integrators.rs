@@ -0,0 +1,438 @@
+//! Integrators, to advance the particles through time.
+
+use crate::particles::Particle;
+use crate::vectors::Vector;
+use crate::log_data::DataLog;
+use crate::units::Dimension;
+use crate::random::Random;
+use crate::constants::BOLTZMANN_CONST;
+use crate::forcefield::external;
+use crate::simulation::SimulationError;
+
+/** A scheme to integrate the equations of motion.
+
+Each step is split around the single force evaluation of that step:
+`pre_force` is called with the accellerations of the previous step, after which the forces (and accellerations) are recalculated for the new positions,
+and finally `post_force` is called with the new accellerations.
+*/
+pub trait Integrator {
+	/// Prepare the particles before the first step, once the initial accellerations are known.
+	fn init(&mut self, _particles: &mut [Particle], _dt: f64) -> Result<(), SimulationError> {
+		Ok(())
+	}
+
+	/// The part of the step before the forces are recalculated.
+	fn pre_force(&mut self, particles: &mut [Particle], dt: f64);
+
+	/// The part of the step after the forces are recalculated.
+	fn post_force(&mut self, _particles: &mut [Particle], _dt: f64) {}
+
+	/// The velocity of a particle at the current time, for integrators that store the velocity at a different time.
+	fn velocity(&self, particle: &Particle, _dt: f64) -> Vector {
+		particle.v
+	}
+
+	/// Set the velocity of a particle at the current time, the inverse of velocity().
+	fn set_velocity(&self, particle: &mut Particle, velocity: Vector, _dt: f64) {
+		particle.v = velocity;
+	}
+
+	/// Add the series logged by log() to a DataLog.
+	fn add_series(&self, _data: &mut DataLog) {}
+
+	/// Log the contributions of the integrator during the last step, for the last time step in data.
+	fn log(&self, _data: &mut DataLog) {}
+}
+
+/// The semi-implicit Euler integrator, updating the velocity and then the position.
+pub struct Euler;
+
+impl Integrator for Euler {
+	fn pre_force(&mut self, particles: &mut [Particle], dt: f64) {
+		for p in particles.iter_mut() {
+			p.update(dt);
+		}
+	}
+}
+
+/** The velocity Verlet integrator.
+
+The velocity gets half a kick from the old accelleration, the position drifts a full step,
+and after the forces are recalculated the velocity gets the other half kick from the new accelleration.
+*/
+pub struct VelocityVerlet;
+
+impl Integrator for VelocityVerlet {
+	fn pre_force(&mut self, particles: &mut [Particle], dt: f64) {
+		for p in particles.iter_mut() {
+			p.update_v(dt / 2.0);
+			p.update_pos(dt);
+		}
+	}
+
+	fn post_force(&mut self, particles: &mut [Particle], dt: f64) {
+		for p in particles.iter_mut() {
+			p.update_v(dt / 2.0);
+		}
+	}
+}
+
+/** The leapfrog integrator.
+
+The velocities are stored half a step behind the positions, so the velocity of a particle is v(t - dt/2).
+The velocity at the current time is interpolated using the current accelleration.
+*/
+pub struct Leapfrog;
+
+impl Integrator for Leapfrog {
+	fn init(&mut self, particles: &mut [Particle], dt: f64) -> Result<(), SimulationError> {
+		// Move the velocities back half a step
+		for p in particles.iter_mut() {
+			p.update_v(-dt / 2.0);
+		}
+		Ok(())
+	}
+
+	fn pre_force(&mut self, particles: &mut [Particle], dt: f64) {
+		for p in particles.iter_mut() {
+			p.update_v(dt);
+			p.update_pos(dt);
+		}
+	}
+
+	fn velocity(&self, particle: &Particle, dt: f64) -> Vector {
+		particle.v + particle.a * (dt / 2.0)
+	}
+
+	fn set_velocity(&self, particle: &mut Particle, velocity: Vector, dt: f64) {
+		particle.v = velocity - particle.a * (dt / 2.0);
+	}
+}
+
+/** Langevin dynamics, using the BAOAB splitting of Leimkuhler and Matthews.
+
+Each particle feels a friction force and a random force, which together keep the system at a target temperature, like an implicit solvent.
+The step is split into a half kick (B), half a drift (A), the exact solution of the friction and noise (O), half a drift (A), and after the forces are recalculated the other half kick (B).
+The random numbers come from a seeded generator, so runs with the same seed are identical.
+*/
+pub struct Langevin {
+	/// The target temperature
+	pub temperature: f64,
+	/// The friction coefficient (per unit time) of each particle
+	pub friction: Vec<f64>,
+	/// The generator of the random forces
+	pub random: Random,
+	/// The friction force on each particle during the last step
+	pub force_friction: Vec<Vector>,
+	/// The random force on each particle during the last step
+	pub force_noise: Vec<Vector>,
+}
+
+impl Langevin {
+	/// Create a new Langevin integrator, with a friction coefficient for each particle.
+	pub fn new(temperature: f64, friction: Vec<f64>, seed: u64) -> Self {
+		Langevin{
+			temperature,
+			friction,
+			random: Random::new(seed),
+			force_friction: Vec::new(),
+			force_noise: Vec::new(),
+		}
+	}
+}
+
+impl Integrator for Langevin {
+	fn init(&mut self, particles: &mut [Particle], _dt: f64) -> Result<(), SimulationError> {
+		if self.friction.len() != particles.len() {
+			return Err(SimulationError::Friction(self.friction.len(), particles.len()));
+		}
+		self.force_friction = vec![Vector::zero(); particles.len()];
+		self.force_noise = vec![Vector::zero(); particles.len()];
+		Ok(())
+	}
+
+	fn pre_force(&mut self, particles: &mut [Particle], dt: f64) {
+		for (i, p) in particles.iter_mut().enumerate() {
+			p.update_v(dt / 2.0);
+			p.update_pos(dt / 2.0);
+
+			// v(t + dt) = c1 v(t) + c2 sqrt(kT/m) R, with R normally distributed
+			let c1 = (-self.friction[i] * dt).exp();
+			let c2 = (1.0 - c1 * c1).sqrt();
+			let sigma = (BOLTZMANN_CONST * self.temperature / p.m).sqrt();
+			let noise = Vector::new(self.random.normal(), self.random.normal(), self.random.normal()) * (c2 * sigma);
+			let friction = p.v * (c1 - 1.0);
+			p.v = p.v + friction + noise;
+			// The average forces during the step that cause the same change in velocity
+			self.force_friction[i] = friction * (p.m / dt);
+			self.force_noise[i] = noise * (p.m / dt);
+
+			p.update_pos(dt / 2.0);
+		}
+	}
+
+	fn post_force(&mut self, particles: &mut [Particle], dt: f64) {
+		for p in particles.iter_mut() {
+			p.update_v(dt / 2.0);
+		}
+	}
+
+	fn add_series(&self, data: &mut DataLog) {
+		data.add_particle_vector_series("force_friction", Dimension::Force);
+		data.add_particle_vector_series("force_noise", Dimension::Force);
+	}
+
+	fn log(&self, data: &mut DataLog) {
+		for i in 0..self.force_friction.len() {
+			data.insert_particle_vector_len("force_friction", i, self.force_friction[i]);
+			data.insert_particle_vector_len("force_noise", i, self.force_noise[i]);
+		}
+	}
+}
+
+/** The Boris pusher, for charged particles in a uniform magnetic field.
+
+The Lorentz force q v x B depends on the velocity, so it cannot be calculated with the other forces.
+Like the leapfrog integrator the velocities are stored half a step behind the positions:
+each step the velocity gets half a kick from the accelleration, is rotated around the magnetic field, and gets the other half kick, before the position drifts a full step.
+The rotation keeps the speed exactly, so the magnetic field does no work.
+*/
+pub struct Boris {
+	/// The magnetic field
+	pub field: Vector,
+	/// The elementary charge, in which the charges of the particles are given
+	pub elementary_charge: f64,
+	/// The Lorentz force on each particle during the last step
+	pub force_magnetic: Vec<Vector>,
+}
+
+impl Boris {
+	/// Create a new Boris integrator for a magnetic field.
+	pub fn new(field: Vector, elementary_charge: f64) -> Self {
+		Boris{ field, elementary_charge, force_magnetic: Vec::new() }
+	}
+
+	/// Rotate a velocity of a particle around the magnetic field, over a time dt.
+	fn rotate(&self, particle: &Particle, v: Vector, dt: f64) -> Vector {
+		let t = self.field * (particle.q * self.elementary_charge * dt / (2.0 * particle.m));
+		let s = t * (2.0 / (1.0 + t.sqlen()));
+		let v_prime = v + v.cross(&t);
+		v + v_prime.cross(&s)
+	}
+}
+
+impl Integrator for Boris {
+	fn init(&mut self, particles: &mut [Particle], dt: f64) -> Result<(), SimulationError> {
+		self.force_magnetic = vec![Vector::zero(); particles.len()];
+		// Move the velocities back half a step, the reverse of velocity()
+		for p in particles.iter_mut() {
+			p.v = self.rotate(p, p.v, -dt / 2.0);
+			p.update_v(-dt / 2.0);
+		}
+		Ok(())
+	}
+
+	fn pre_force(&mut self, particles: &mut [Particle], dt: f64) {
+		for (i, p) in particles.iter_mut().enumerate() {
+			p.update_v(dt / 2.0);
+			let v_minus = p.v;
+			p.v = self.rotate(p, v_minus, dt);
+			// The rotation is the Lorentz force of the average velocity during the step
+			self.force_magnetic[i] = external::get_lorentz_force(p.q, &((v_minus + p.v) / 2.0), &self.field, self.elementary_charge);
+			p.update_v(dt / 2.0);
+			p.update_pos(dt);
+		}
+	}
+
+	fn velocity(&self, particle: &Particle, dt: f64) -> Vector {
+		self.rotate(particle, particle.v + particle.a * (dt / 2.0), dt / 2.0)
+	}
+
+	fn set_velocity(&self, particle: &mut Particle, velocity: Vector, dt: f64) {
+		particle.v = self.rotate(particle, velocity, -dt / 2.0) - particle.a * (dt / 2.0);
+	}
+
+	fn add_series(&self, data: &mut DataLog) {
+		data.add_particle_vector_series("force_magnetic", Dimension::Force);
+	}
+
+	fn log(&self, data: &mut DataLog) {
+		for i in 0..self.force_magnetic.len() {
+			data.insert_particle_vector_len("force_magnetic", i, self.force_magnetic[i]);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::forcefield::temperature;
+
+	/// A particle with a velocity and an accelleration in all directions.
+	fn particle() -> Particle {
+		let mut p = Particle::new(&Vector::zero(), 1.0, 2.0, 1.5);
+		p.v = Vector::new(0.3, -1.2, 0.7);
+		p.a = Vector::new(-2.0, 0.5, 1.0);
+		p
+	}
+
+	#[test]
+	fn set_velocity_inverts_velocity() {
+		let dt = 0.01;
+		let integrators: [Box<dyn Integrator>; 3] = [Box::new(VelocityVerlet), Box::new(Leapfrog), Box::new(Boris::new(Vector::new(0.5, 2.0, -1.0), 3.0))];
+		for integrator in integrators {
+			let mut p = particle();
+			let v = integrator.velocity(&p, dt);
+			integrator.set_velocity(&mut p, v, dt);
+			assert!((p.v - particle().v).len() < 1e-12);
+		}
+	}
+
+	/// Run a particle through a number of steps of an integrator with a constant accelleration, returning it and its positions after each step.
+	fn run(integrator: &mut dyn Integrator, particle: Particle, steps: usize, dt: f64) -> (Particle, Vec<Vector>) {
+		let mut particles = vec![particle];
+		integrator.init(&mut particles, dt).unwrap();
+		let mut positions = Vec::new();
+		for _ in 0..steps {
+			integrator.pre_force(&mut particles, dt);
+			integrator.post_force(&mut particles, dt);
+			positions.push(particles[0].pos);
+		}
+		(particles.remove(0), positions)
+	}
+
+	/// The largest error in the energy of a harmonic pair with a spring constant and a rest length of 1, in the first and the second half of a run.
+	fn harmonic_pair_energy_errors(integrator: &mut dyn Integrator, steps: usize, dt: f64) -> (f64, f64) {
+		let mut particles = vec![Particle::new(&Vector::zero(), 0.5, 1.0, 0.0), Particle::new(&Vector::new(1.5, 0.0, 0.0), 0.5, 2.0, 0.0)];
+		let accellerate = |particles: &mut [Particle]| {
+			let separation = particles[1].pos - particles[0].pos;
+			let force = separation * (1.0 - separation.len()) / separation.len();
+			particles[0].a = -force / particles[0].m;
+			particles[1].a = force / particles[1].m;
+		};
+		let energy = |integrator: &dyn Integrator, particles: &[Particle]| {
+			let kinetic: f64 = particles.iter().map(|p| p.m * integrator.velocity(p, dt).sqlen() / 2.0).sum();
+			kinetic + ((particles[1].pos - particles[0].pos).len() - 1.0).powi(2) / 2.0
+		};
+
+		accellerate(&mut particles);
+		integrator.init(&mut particles, dt).unwrap();
+		let start = energy(integrator, &particles);
+		let errors: Vec<f64> = (0..steps).map(|_| {
+			integrator.pre_force(&mut particles, dt);
+			accellerate(&mut particles);
+			integrator.post_force(&mut particles, dt);
+			(energy(integrator, &particles) - start).abs()
+		}).collect();
+		let largest = |errors: &[f64]| errors.iter().cloned().fold(0.0, f64::max);
+		(largest(&errors[..steps / 2]), largest(&errors[steps / 2..]))
+	}
+
+	#[test]
+	fn verlet_conserves_the_energy() {
+		// About 200 oscillations of the pair, with an energy of 0.125
+		let (first, second) = harmonic_pair_energy_errors(&mut VelocityVerlet, 20000, 0.05);
+		assert!(second < 5e-4 && second < 1.1 * first, "{} {}", first, second);
+		// The error of a second order method is a quarter for half the time step
+		let (_, fine) = harmonic_pair_energy_errors(&mut VelocityVerlet, 40000, 0.025);
+		assert!((second / fine - 4.0).abs() < 0.2, "{} / {}", second, fine);
+
+		// The semi-implicit Euler integrator does not drift either, but its error is only first order
+		let (_, euler) = harmonic_pair_energy_errors(&mut Euler, 20000, 0.05);
+		assert!(euler > 10.0 * second, "{} {}", euler, second);
+	}
+
+	/// A particle with a charge of 1.5 and a mass of 2, in a field of 2 along z, so the cyclotron frequency is 1.5.
+	fn cyclotron(velocity: Vector) -> (Boris, Particle, f64) {
+		let mut p = Particle::new(&Vector::zero(), 1.0, 2.0, 1.5);
+		p.v = velocity;
+		(Boris::new(Vector::new(0.0, 0.0, 2.0), 1.0), p, 1.5)
+	}
+
+	#[test]
+	fn boris_keeps_the_speed() {
+		let (mut boris, p, _) = cyclotron(Vector::new(1.0, 0.0, 0.5));
+		let speed = p.v.len();
+		let dt = 0.05;
+		let (p, _) = run(&mut boris, p, 1000, dt);
+		assert!((boris.velocity(&p, dt).len() - speed).abs() < 1e-12);
+	}
+
+	#[test]
+	fn boris_gyrates_at_the_cyclotron_frequency() {
+		let (mut boris, p, frequency) = cyclotron(Vector::new(1.0, 0.0, 0.0));
+		let steps = 1000;
+		let dt = 2.0 * std::f64::consts::PI / frequency / steps as f64;
+		let (_, positions) = run(&mut boris, p, steps, dt);
+
+		// The positive charge circles clockwise around the field, with the Larmor radius v / frequency
+		let radius = 1.0 / frequency;
+		let centre = Vector::new(0.0, -radius, 0.0);
+		for pos in positions.iter() {
+			assert!(((*pos - centre).len() - radius).abs() < 1e-4 * radius);
+		}
+		assert!(positions[steps / 4].x > 0.0 && positions[steps / 4].y < -radius);
+		assert!(positions[steps - 1].len() < 1e-4 * radius);
+	}
+
+	#[test]
+	fn boris_drifts_along_e_cross_b() {
+		// An electric field of 1 along x gives a drift of E x B / B^2 = -0.5 along y, averaged over whole gyrations
+		let (mut boris, mut p, frequency) = cyclotron(Vector::zero());
+		p.a = Vector::new(1.5 / 2.0, 0.0, 0.0);
+		let steps = 10000;
+		let time = 10.0 * 2.0 * std::f64::consts::PI / frequency;
+		let (_, positions) = run(&mut boris, p, steps, time / steps as f64);
+		let drift = positions[steps - 1] / time;
+		assert!((drift - Vector::new(0.0, -0.5, 0.0)).len() < 1e-3);
+	}
+
+	/// Free particles of different masses at rest, with a Langevin integrator giving each of them the same friction.
+	fn langevin(count: usize, temperature: f64, seed: u64) -> (Langevin, Vec<Particle>) {
+		let particles = (0..count).map(|i| Particle::new(&Vector::new(i as f64, 0.0, 0.0), 0.5, 1.0 + (i % 3) as f64, 0.0)).collect();
+		(Langevin::new(temperature, vec![2.0; count], seed), particles)
+	}
+
+	#[test]
+	fn langevin_needs_a_friction_for_each_particle() {
+		let (mut langevin, mut particles) = langevin(3, 1.0, 1);
+		langevin.friction.pop();
+		assert!(matches!(langevin.init(&mut particles, 0.01), Err(SimulationError::Friction(2, 3))));
+	}
+
+	#[test]
+	fn langevin_runs_with_the_same_seed_are_identical() {
+		// The bits of the positions and velocities after a number of steps
+		let run = |seed| {
+			let (mut langevin, mut particles) = langevin(5, 1.0, seed);
+			langevin.init(&mut particles, 0.01).unwrap();
+			for _ in 0..100 {
+				langevin.pre_force(&mut particles, 0.01);
+				langevin.post_force(&mut particles, 0.01);
+			}
+			particles.iter().flat_map(|p| [p.pos.x, p.pos.y, p.pos.z, p.v.x, p.v.y, p.v.z]).map(f64::to_bits).collect::<Vec<u64>>()
+		};
+		assert_eq!(run(3), run(3));
+		assert!(run(3).iter().zip(run(4)).all(|(a, b)| *a != b));
+	}
+
+	#[test]
+	fn langevin_reaches_the_temperature() {
+		let temperature = 1.5;
+		let dt = 0.01;
+		let (mut langevin, mut particles) = langevin(100, temperature, 5);
+		langevin.init(&mut particles, dt).unwrap();
+		// The velocities relax within a few times 1 / friction, after which each sample is correlated over about 1 / (2 friction)
+		let mut sum = 0.0;
+		let steps = 5000;
+		for step in 0..steps + 500 {
+			langevin.pre_force(&mut particles, dt);
+			langevin.post_force(&mut particles, dt);
+			if step >= 500 {
+				sum += temperature::get_temperature(&particles, 0);
+			}
+		}
+		let mean = sum / steps as f64;
+		assert!((mean / temperature - 1.0).abs() < 0.03, "{} != {}", mean, temperature);
+	}
+}
@@ -0,0 +1,24 @@
+//! A molecular dynamics simulation library.
+//!
+//! The [`simulation::Simulation`] type owns the particles, forcefield, integrator and data log, and advances the system through time.
+
+#![warn(missing_docs)]
+
+pub mod constants;
+pub mod units;
+pub mod vectors;
+pub mod complex;
+pub mod fft;
+pub mod random;
+pub mod particles;
+pub mod species;
+pub mod simbox;
+pub mod neighbors;
+pub mod forcefield;
+pub mod log_data;
+pub mod integrators;
+pub mod constraints;
+pub mod minimizers;
+pub mod generators;
+pub mod simulation;
+pub mod config;
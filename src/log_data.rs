@@ -1,27 +1,32 @@
 
 //! Structs and methods to save and log data about the system
 
-use crate::Vector;
+use crate::vectors::Vector;
+use crate::units::{Units, Dimension};
 
 use std::collections::HashMap;
 use std::iter::{zip, Zip};
 use std::vec::IntoIter;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::BufWriter;
 
 /// A map, containing named elements that have f64 values for each time step.
 pub struct LinearData<'a> {
+	/// The series, by name
 	pub map: HashMap::<&'a str, Vec::<f64>>
 }
 
 /// A map, containing named elements that have f64 values for each particle for each timestep.
 pub struct ParticleData<'a> {
+	/// The series, by name, with a series for each particle
 	pub map: HashMap::<&'a str, Vec::<Vec::<f64>>>,
 	particles: usize
 }
 
 /// A map, containing named elements that have Vector values for each particle for each timestep.
 pub struct ParticleVectorData<'a> {
+	/// The series, by name, with a series for each particle
 	pub map: HashMap::<&'a str, Vec::<Vec::<Vector>>>,
 	particles: usize
 }
@@ -31,10 +36,18 @@ pub struct ParticleVectorData<'a> {
 /// Used to store all data about a system over time.
 pub struct DataLog<'a> {
 	//TODO: insert system properties
+	/// The time of each logged step
 	pub time: Vec::<f64>,
+	/// Values for the whole system
 	pub global: LinearData<'a>,
+	/// Values for each particle
 	pub particle: ParticleData<'a>,
+	/// Vector values for each particle
 	pub particle_vector: ParticleVectorData<'a>,
+	/// The dimension of each series, by name
+	pub dimensions: HashMap::<&'a str, Dimension>,
+	/// The units in which to_file() writes the data, which is stored in the internal units
+	pub units: Units,
 }
 
 impl<'a> DataLog<'a> {
@@ -45,6 +58,8 @@ impl<'a> DataLog<'a> {
 			global: LinearData::new(),
 			particle: ParticleData::new(particles),
 			particle_vector: ParticleVectorData::new(particles),
+			dimensions: HashMap::new(),
+			units: Units::default(),
 		}
 	}
 
@@ -81,10 +96,17 @@ impl<'a> DataLog<'a> {
 		zip(self.time.clone(), self.particle_vector.get(name)[index].clone())
 	}
 
-	/// Create a global series and a particle series with a given name. 
-	pub fn add_particle_series(&mut self, name: &'a str) {
+	/// Create a global series with a given name and dimension.
+	pub fn add_global_series(&mut self, name: &'a str, dimension: Dimension) {
+		self.global.add_series(name);
+		self.dimensions.insert(name, dimension);
+	}
+
+	/// Create a global series and a particle series with a given name and dimension.
+	pub fn add_particle_series(&mut self, name: &'a str, dimension: Dimension) {
 		self.particle.add_series(name);
 		self.global.add_series(name);
+		self.dimensions.insert(name, dimension);
 	}
 	
 	/// Simultaneously insert a value into a particle series, and add the value to the corresponding global series.
@@ -115,10 +137,24 @@ impl<'a> DataLog<'a> {
 		}
 	}
 
-	/// Create a particle series and a particle vector series with the same name.
-	pub fn add_particle_vector_series(&mut self, name: &'a str) {
+	/// Create a particle series and a particle vector series with the same name and dimension.
+	pub fn add_particle_vector_series(&mut self, name: &'a str, dimension: Dimension) {
 		self.particle_vector.add_series(name);
 		self.particle.add_series(name);
+		self.dimensions.insert(name, dimension);
+	}
+
+	/// The dimension of a series, dimensionless if it was not given.
+	fn dimension(&self, name: &str) -> Dimension {
+		self.dimensions.get(name).copied().unwrap_or(Dimension::None)
+	}
+
+	/// The unit of a series in the header of to_file(), such as " [K]", or empty for dimensionless series.
+	fn unit(&self, dimension: Dimension) -> String {
+		match self.units.name(dimension) {
+			"" => String::new(),
+			name => format!(" [{}]", name),
+		}
 	}
 
 	/// Simultaneously insert a vector into a ParticleVector series, and insert the length of the vector into the corresponing global series.
@@ -143,56 +179,66 @@ impl<'a> DataLog<'a> {
 		}
 	}
 
-	/// Logs all data to a csv file.
+	/// Logs all data to a csv file, in the units of the system, with the unit of each column in its header.
 	pub fn to_file(&self, filename: &str) -> std::io::Result<()> {
-		let mut file = File::create(filename)?;
+		let mut file = BufWriter::new(File::create(filename)?);
+
+		// Sort the series, so the columns are always in the same order
+		let mut k_global: Vec<_> = self.global.map.keys().collect();
+		let mut k_particle: Vec<_> = self.particle.map.keys().collect();
+		let mut k_vector: Vec<_> = self.particle_vector.map.keys().collect();
+		k_global.sort();
+		k_particle.sort();
+		k_vector.sort();
 
-		let k_global = self.global.map.keys();
-		let k_particle = self.particle.map.keys();
-		let k_vector = self.particle_vector.map.keys();
+		// The factor converting each series to the units of the system, and its unit
+		let factor = |k: &str| self.units.from_internal(1.0, self.dimension(k));
+		let unit = |k: &str| self.unit(self.dimension(k));
 
 		// Write the header line
-		let mut line = String::from("t");
-		for k in k_global.clone() {
+		let mut line = format!("t{}", self.unit(Dimension::Time));
+		for k in k_global.iter() {
 			line.push(',');
 			line.push_str(&k.to_string());
+			line.push_str(&unit(k));
 		}
-		for k in k_particle.clone() {
+		for k in k_particle.iter() {
 			for i in 0..self.particle.particles{
-				line.push_str(&format!(",{}_{}", &k.to_string(), i).to_string());
+				line.push_str(&format!(",{}_{}{}", &k.to_string(), i, unit(k)).to_string());
 			}
 		}
-		for k in k_vector.clone() {
+		for k in k_vector.iter() {
 			for i in 0..self.particle_vector.particles{
-				line.push_str(&format!(",{}_{}_x", &k.to_string(), i).to_string());
-				line.push_str(&format!(",{}_{}_y", &k.to_string(), i).to_string());
-				line.push_str(&format!(",{}_{}_z", &k.to_string(), i).to_string());
+				line.push_str(&format!(",{}_{}_x{}", &k.to_string(), i, unit(k)).to_string());
+				line.push_str(&format!(",{}_{}_y{}", &k.to_string(), i, unit(k)).to_string());
+				line.push_str(&format!(",{}_{}_z{}", &k.to_string(), i, unit(k)).to_string());
 			}
 		}
 		line.push('\n');
 		file.write_all(line.as_bytes())?;
 
 		for t in 0..self.time.len() {
-			let mut line = String::from(&self.time[t].to_string());
+			let mut line = String::from(&self.units.from_internal(self.time[t], Dimension::Time).to_string());
 		
-			for k in k_global.clone() {
+			for k in k_global.iter() {
 				line.push(',');
-				line.push_str(&self.global.get(k)[t].to_string());
+				line.push_str(&(self.global.get(k)[t] * factor(k)).to_string());
 			}
-			for k in k_particle.clone() {
+			for k in k_particle.iter() {
 				for i in 0..self.particle.particles{
 					line.push(',');
-					line.push_str(&self.particle.get(k)[i][t].to_string());
+					line.push_str(&(self.particle.get(k)[i][t] * factor(k)).to_string());
 				}
 			}
-			for k in k_vector.clone() {
+			for k in k_vector.iter() {
 				for i in 0..self.particle_vector.particles{
+					let v = self.particle_vector.get(k)[i][t] * factor(k);
 					line.push(',');
-					line.push_str(&self.particle_vector.get(k)[i][t].x.to_string());
+					line.push_str(&v.x.to_string());
 					line.push(',');
-					line.push_str(&self.particle_vector.get(k)[i][t].y.to_string());
+					line.push_str(&v.y.to_string());
 					line.push(',');
-					line.push_str(&self.particle_vector.get(k)[i][t].z.to_string());
+					line.push_str(&v.z.to_string());
 				}
 			}
 
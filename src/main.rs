@@ -4,30 +4,26 @@
 //TODO: Error checking/handling
 #![warn(missing_docs)]
 
-mod constants;
-mod vectors;
-mod particles;
-mod forcefield;
 mod framebuffer;
-mod log_data;
 mod plots;
 
 mod embedded_gfx;
 
 mod icosphere;
 
-use crate::constants::{W, H, FRAME_RATE, SIM_LEN, TIME_STEP, BORDER_X, BORDER_Y, BORDER_Z};
+use molecular_dynamics::constants::OUTPUT_FILE;
 
-use crate::vectors::Vector;
-use crate::particles::Particle;
-use crate::log_data::DataLog;
-use crate::forcefield::{temperature, vanderwaals, electrostatic, borders};
+use molecular_dynamics::vectors::Vector;
+use molecular_dynamics::units::Dimension;
+use molecular_dynamics::config::Config;
+use molecular_dynamics::simulation::Simulation;
 use crate::framebuffer::FrameBuffer;
 use crate::plots::Plot;
 
 use minifb::{Window, WindowOptions, Key, KeyRepeat};
 use std::borrow::Borrow;
 use std::error::Error;
+use std::process::ExitCode;
 use std::time::SystemTime;
 
 use crate::embedded_gfx::{DrawPrimitive, K3dengine};
@@ -36,16 +32,82 @@ use crate::embedded_gfx::draw::draw;
 use embedded_graphics_core::pixelcolor::Rgb888;
 use nalgebra::Point3;
 
+/// The colours of the particles by species, repeating when there are more species
+const SPECIES_COLOURS: [(u8, u8, u8); 6] = [(255, 0, 0), (0, 255, 0), (0, 0, 255), (255, 255, 0), (0, 255, 255), (255, 0, 255)];
 
-fn main () -> Result<(), Box<dyn Error>> {
-	let mut sim_fb = FrameBuffer::new(W, H);
-	let mut plot = Plot::new(W, H, 10, [0.0, SIM_LEN], [-10.0, 10.0], Rgb888::new(0,0,0), Rgb888::new(0,255,0));
+fn main () -> ExitCode {
+	match run() {
+		Ok(()) => ExitCode::SUCCESS,
+		Err(e) => {
+			eprintln!("Error: {}", e);
+			ExitCode::FAILURE
+		}
+	}
+}
+
+/// Parse the arguments, and run the simulation.
+fn run() -> Result<(), Box<dyn Error>> {
+	let mut config_file = None;
+	let mut integrator = None;
+	let mut headless = false;
+	let mut output = String::from(OUTPUT_FILE);
+	let mut args = std::env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--config" => { config_file = Some(args.next().ok_or("--config needs a value")?); }
+			"--integrator" => { integrator = Some(args.next().ok_or("--integrator needs a value")?); }
+			"--headless" => { headless = true; }
+			"--output" => { output = args.next().ok_or("--output needs a value")?; }
+			_ => { return Err(format!("Unknown argument: {}", arg).into()); }
+		}
+	}
+
+	let mut config = match config_file {
+		Some(filename) => Config::from_file(&filename)?,
+		None => Config::default(),
+	};
+	if let Some(integrator) = integrator {
+		config.simulation.integrator = integrator.parse()?;
+		config.validate()?;
+	}
+
+	let (sim, minimization) = config.simulation()?;
+	if let Some(m) = minimization {
+		let units = &sim.data.units;
+		println!("Minimized the energy in {} steps to {} {}, the largest force is {} {}{}", m.steps,
+		         units.from_internal(m.energy, Dimension::Energy), units.name(Dimension::Energy),
+		         units.from_internal(m.max_force, Dimension::Force), units.name(Dimension::Force),
+		         if m.converged { "" } else { " (not converged)" });
+	}
+
+	if headless {
+		run_headless(sim, &config, &output)
+	} else {
+		run_windowed(sim, &config)
+	}
+}
+
+/// Run the full simulation as fast as possible without a display, and write the data log to a file.
+fn run_headless(mut sim: Simulation, config: &Config, output: &str) -> Result<(), Box<dyn Error>> {
+	sim.run(config.steps())?;
+
+	sim.data.to_file(output)?;
+	println!("Simulated {} steps, data written to {}", sim.steps, output);
+
+	Ok(())
+}
+
+/// Run the simulation while displaying it, and a graph of the temperature.
+fn run_windowed(mut sim: Simulation, config: &Config) -> Result<(), Box<dyn Error>> {
+	let (w, h) = (config.display.width, config.display.height);
+	let size = Vector::new(config.simbox.x, config.simbox.y, config.simbox.z);
+
+	let mut sim_fb = FrameBuffer::new(w, h);
+	let mut plot = Plot::new(w, h, 10, [0.0, config.simulation.length], [-10.0, 10.0], Rgb888::new(0,0,0), Rgb888::new(0,255,0));
 	
-	let mut sim_window = Window::new("MD Sim", W, H, WindowOptions::default(),)?;
-	let mut data_window = Window::new("MD Sim Data", W, H, WindowOptions::default(),)?;
+	let mut sim_window = Window::new("MD Sim", w, h, WindowOptions::default(),)?;
+	let mut data_window = Window::new("MD Sim Data", w, h, WindowOptions::default(),)?;
 	
-	let mut t = 0.0;
-
 	let start_ts = SystemTime::now();
 	let mut last_flushed = 0.0;
 	
@@ -53,18 +115,18 @@ fn main () -> Result<(), Box<dyn Error>> {
 	let mut phi: f64 = 0.0;
 	let mut zoom: f64 = 20.0;
 
-	let mut engine = K3dengine::new(W as u16, H as u16);
+	let mut engine = K3dengine::new(w as u16, h as u16);
 	engine.camera.set_position(Point3::new(0.0, 0.0, zoom));
-	engine.camera.set_target(Point3::new(5.0, 5.0, 5.0));
+	engine.camera.set_target(Point3::new(size.x / 2.0, size.y / 2.0, size.z / 2.0));
 	engine.camera.set_fovy(3.141592 / 4.0);
 	engine.camera.far = 30.0;
 	
-	let mut b = make_xy_plane(0.0);
-	let mut z1 = make_xy_plane(BORDER_Z);
-	let mut x0 = make_yz_plane(0.0);
-	let mut x1 = make_yz_plane(BORDER_X);
-	let mut y0 = make_xz_plane(0.0);
-	let mut y1 = make_xz_plane(BORDER_Y);
+	let mut b = make_xy_plane(0.0, &size);
+	let mut z1 = make_xy_plane(size.z, &size);
+	let mut x0 = make_yz_plane(0.0, &size);
+	let mut x1 = make_yz_plane(size.x, &size);
+	let mut y0 = make_xz_plane(0.0, &size);
+	let mut y1 = make_xz_plane(size.y, &size);
 	b.append(&mut z1);
 	b.append(&mut x0);
 	b.append(&mut x1);
@@ -76,32 +138,6 @@ fn main () -> Result<(), Box<dyn Error>> {
 	});
 	borders.set_color(Rgb888::new(255,255,255));
 
-
-	//TODO: multiple graphs, split this file up
-
-	let mut p = vec![Particle::new(&Vector::new(1.0, 1.0, 1.0),  1.0, 3.0,     0.0),
-			 		 Particle::new(&Vector::new(4.0, 3.0, 1.0),     1.0, 1.0,  0.0),
-			 		 Particle::new(&Vector::new(1.0, 5.0, 1.0),     1.5, 1.0,  0.0)];
-
-	for i in 0..3 {
-		p.push(Particle::new(&Vector::new(1.0 + 2.0 * i as f64, 7.0, 9.0 - 2.0 * i as f64), 1.0, 1.0, 0.0));
-	}
-
-	let mut data = DataLog::new(p.len());
-
-	data.add_particle_vector_series("position");
-	data.add_particle_vector_series("velocity");
-	data.add_particle_vector_series("accelleration");
-	data.add_particle_vector_series("force_electric");
-	data.add_particle_vector_series("force_vdw");
-	data.add_particle_vector_series("force_total");
-	data.add_particle_series("energy_electric");
-	data.add_particle_series("energy_vdw");
-	data.add_particle_series("energy_kinetic");
-	data.add_particle_series("energy_total");
-	data.global.add_series("temperature");
-	data.global.add_series("temperature_scale");
-
 	let s = icosphere::create_icosphere(2);
 	let norms = icosphere::get_normals(&s.0, &s.1);
 	let sphere = K3dMesh::new(Geometry {
@@ -112,81 +148,25 @@ fn main () -> Result<(), Box<dyn Error>> {
 		normals: &norms,
 	});
 
+	let p = &sim.particles;
 	let mut spheres = Vec::new();
 	for i in 0..p.len() {
 		spheres.push(sphere);
 		spheres[i].set_position(p[i].pos.x, p[i].pos.y, p[i].pos.z);
 		spheres[i].set_scale(p[i].r);
 		spheres[i].set_render_mode(RenderMode::SolidLightDir(nalgebra::Vector3::new(0.0, 0.0, 1.0)));
-		spheres[i].set_color(Rgb888::new(255,0,0));
+		let (r, g, b) = SPECIES_COLOURS[p[i].species % SPECIES_COLOURS.len()];
+		spheres[i].set_color(Rgb888::new(r, g, b));
 	}
-	spheres[0].set_color(Rgb888::new(0,255,0));
-	spheres[2].set_color(Rgb888::new(0,0,255));
 
 	let mut last_plotted = 0.0;
+	let steps = config.steps();
 
 	while sim_window.is_open()  &&  !sim_window.is_key_down(Key::Escape) &&
 		  data_window.is_open() && !data_window.is_key_down(Key::Escape) {
 		let epoch = SystemTime::now().duration_since(start_ts).unwrap().as_secs_f64();
-		if epoch - last_flushed <= 1.0 / FRAME_RATE && t < SIM_LEN {
-			data.time.push(t);
-			
-			for i in 0..p.len() {
-				p[i].a = Vector::zero();
-				data.insert_particle_vector_len("position", i, p[i].pos);
-				data.insert_particle_vector_len("velocity", i, p[i].v);	
-				data.insert_particle_vector_len("accelleration", i, p[i].a);	
-			}
-			
-			let scale = if t > 50.0 { temperature::get_scale(&p, 0.0, 5.0) } else { 1.0 };
-
-			data.global.insert_into("temperature", temperature::get_temperature(&p));
-			data.global.insert_into("temperature_scale", scale);
-
-			// Iterate over each pair of particles
-			for i in 0..(p.len()-1) {
-				for j in (i+1)..(p.len()) {
-					let separation = p[i].separation(&p[j]);
-					let sep_dist = separation.len();
-
-					let r = (p[i].r + p[j].r)/2.0;
-
-					let vdw_force = vanderwaals::get_force(r, sep_dist);
-					let vdw_pot = vanderwaals::get_potential(r, sep_dist);
-					let vdw_dir = separation/sep_dist;
-
-					let elec_f = electrostatic::get_force((p[i].q, p[j].q), sep_dist, true);
-					let elec_v = electrostatic::get_energy((p[i].q, p[j].q), sep_dist, true);
-
-					data.add_to_particle_vector_len("force_electric", 	i, vdw_dir * (-elec_f));
-					data.add_to_particle_vector_len("force_electric", 	j, vdw_dir * ( elec_f));
-					data.add_to_particle_vector_len("force_vdw", 		i, vdw_dir * ( vdw_force));
-					data.add_to_particle_vector_len("force_vdw", 		j, vdw_dir * (-vdw_force));
-					data.add_to_particle_vector_len("force_total", 		i, vdw_dir * ( vdw_force - elec_f));
-					data.add_to_particle_vector_len("force_total", 		j, vdw_dir * (-vdw_force + elec_f));
-					for k in [i,j] {
-						data.add_to_particle_add("energy_kinetic", k, p[k].m * p[k].v.sqlen() / 2.0);
-						data.add_to_particle_add("energy_electric", k, elec_v);
-						data.add_to_particle_add("energy_vdw", k, vdw_pot);
-						data.add_to_particle_add("energy_total", k, elec_v + vdw_pot + p[k].m * p[k].v.sqlen() / 2.0);
-					}
-
-					// Technically this stores the forces instead of the accellerations, but it saves dividing by the mass so often
-					p[i].a +=  vdw_dir * ( vdw_force - elec_f);
-					p[j].a +=  vdw_dir * (-vdw_force + elec_f);
-				}
-			}
-
-			for i in 0..p.len() {
-				let f = borders::get_force(p[i].r, &p[i].pos);
-				p[i].a += f;
-
-				p[i].a = p[i].a / p[i].m; // Finally convert the force to accelleration
-				p[i].v = p[i].v * scale; // Scale the temperature
-				p[i].update(TIME_STEP);
-			}
-
-			t += TIME_STEP;
+		if epoch - last_flushed <= 1.0 / config.display.frame_rate && sim.steps < steps {
+			sim.step()?;
 		} else {
 			let keys = sim_window.get_keys_pressed(KeyRepeat::Yes);
 			for key in keys {
@@ -211,6 +191,7 @@ fn main () -> Result<(), Box<dyn Error>> {
 				theta = 3.15;
 			}		
 
+			let p = &sim.particles;
 			sim_fb.clear_buffer();
 			for i in 0..p.len(){
 				spheres[i].set_position(p[i].pos.x, p[i].pos.y, p[i].pos.z);
@@ -234,12 +215,12 @@ fn main () -> Result<(), Box<dyn Error>> {
 				engine.render(&[spheres[i.0]], |p| draw(p, &mut sim_fb));
 			}
 			
-			sim_window.update_with_buffer(sim_fb.borrow(), W, H)?;
+			sim_window.update_with_buffer(sim_fb.borrow(), w, h)?;
 
 
-			data.plot_global("temperature", last_plotted, plot.max_frequency(), |p1, p2| plot.plot_segment(p1, p2, Rgb888::new(255,0,0)));
-			//data.plot_global("energy_total", last_plotted, plot.max_frequency(), |p1, p2| plot.plot_segment(p1, p2, Rgb888::new(255,0,255)));
-			last_plotted = t;
+			sim.data.plot_global("temperature", last_plotted, plot.max_frequency(), |p1, p2| plot.plot_segment(p1, p2, Rgb888::new(255,0,0)));
+			//sim.data.plot_global("energy_total", last_plotted, plot.max_frequency(), |p1, p2| plot.plot_segment(p1, p2, Rgb888::new(255,0,255)));
+			last_plotted = sim.t;
 //			chart.draw_series(LineSeries::new(data.particle_vector_as_iter("position", 0).map(|(t, v)| {(t, v.x)}), &RED,))?;
 			
 //			chart.draw_series(LineSeries::new(data.particle_vector_as_iter("position", 1).map(|(t, v)| {(t, v.x)}), &GREEN,))?;
@@ -260,44 +241,42 @@ fn main () -> Result<(), Box<dyn Error>> {
 //			//chart.draw_series(LineSeries::new(data.particle_vector_as_circles("position", 0, p[0].r, 200), &RED.mix(0.5),))?;
 //			//chart.draw_series(LineSeries::new(data.particle_vector_as_circles("position", 1, p[1].r, 200), &GREEN.mix(0.5),))?;
 //			//chart.draw_series(LineSeries::new(data.particle_vector_as_circles("position", 2, p[2].r, 200), &MAGENTA.mix(0.5),))?;
-			data_window.update_with_buffer(plot.fb.borrow(), W, H)?;
+			data_window.update_with_buffer(plot.fb.borrow(), w, h)?;
 
 			last_flushed = epoch;
 		}
 	}
-	
-//	data.to_file("out.csv")?;
 
 	Ok(())
 }
 
-fn make_xz_plane(y: f64) -> Vec<[f64; 3]> {
-	let step = 1.0;
+fn make_xz_plane(y: f64, size: &Vector) -> Vec<[f64; 3]> {
 	let nsteps = 10;
+	let step = [size.x / nsteps as f64, size.z / nsteps as f64];
 
 	let mut vertices = Vec::new();
 	for i in 0..nsteps {
 		for j in 0..nsteps {
 			vertices.push([
-				i as f64 * step,
+				i as f64 * step[0],
 				y,
-				j as f64 * step,
+				j as f64 * step[1],
 			]);
 		}
 	}
 
 	vertices
 }
-fn make_xy_plane(z: f64) -> Vec<[f64; 3]> {
-	let step = 1.0;
+fn make_xy_plane(z: f64, size: &Vector) -> Vec<[f64; 3]> {
 	let nsteps = 10;
+	let step = [size.x / nsteps as f64, size.y / nsteps as f64];
 
 	let mut vertices = Vec::new();
 	for i in 0..nsteps {
 		for j in 0..nsteps {
 			vertices.push([
-				i as f64 * step,
-				j as f64 * step,
+				i as f64 * step[0],
+				j as f64 * step[1],
 				z,
 			]);
 		}
@@ -305,17 +284,17 @@ fn make_xy_plane(z: f64) -> Vec<[f64; 3]> {
 
 	vertices
 }
-fn make_yz_plane(x: f64) -> Vec<[f64; 3]> {
-	let step = 1.0;
+fn make_yz_plane(x: f64, size: &Vector) -> Vec<[f64; 3]> {
 	let nsteps = 10;
+	let step = [size.y / nsteps as f64, size.z / nsteps as f64];
 
 	let mut vertices = Vec::new();
 	for i in 0..nsteps {
 		for j in 0..nsteps {
 			vertices.push([
 				x,
-				i as f64 * step,
-				j as f64 * step,
+				i as f64 * step[0],
+				j as f64 * step[1],
 			]);
 		}
 	}
@@ -0,0 +1,444 @@
+/*! Energy minimizers, to relax a configuration (for example one with overlapping particles) before the dynamics start.
+
+The minimizers use the same forcefield as the dynamics, and move the particles along the forces until the largest force or the change in energy is small enough.
+The velocities of the particles are not changed.
+*/
+
+use crate::particles::Particle;
+use crate::vectors::Vector;
+use crate::forcefield::ForceField;
+use crate::forcefield::bonded::BondError;
+
+/// The smallest step that is tried, below which a minimizer gives up on the current direction.
+const MIN_STEP: f64 = 1e-12;
+
+/// The criteria at which a minimization stops.
+pub struct Criteria {
+	/// Converged once the largest force on any particle is below this
+	pub max_force: f64,
+	/// Converged once the potential energy changes by at most this in a step
+	pub energy_change: f64,
+	/// The maximum number of steps
+	pub steps: usize,
+}
+
+/// The result of a minimization.
+pub struct Minimization {
+	/// The number of steps taken
+	pub steps: usize,
+	/// The potential energy of the final configuration
+	pub energy: f64,
+	/// The largest force on any particle in the final configuration
+	pub max_force: f64,
+	/// Whether one of the criteria was met before the maximum number of steps
+	pub converged: bool,
+}
+
+/** A scheme to move the particles towards a lower potential energy.
+
+Before each step the forcefield has been computed for the current positions of the particles, and the same must hold after the step.
+*/
+pub trait Minimizer {
+	/// Move the particles towards a lower potential energy.
+	///
+	/// Returns false if no lower energy could be found although the forces are not zero, in which case the particles have not moved.
+	fn step(&mut self, particles: &mut [Particle], forcefield: &mut ForceField) -> bool;
+}
+
+/// The largest force on any particle, as of the last call to ForceField::compute().
+fn get_max_force(forcefield: &ForceField) -> f64 {
+	forcefield.contributions.force_total.iter().map(|f| f.len()).fold(0.0, f64::max)
+}
+
+/// Move each particle from a starting configuration by its displacement times a scale, and compute the forcefield and potential energy there.
+///
+/// The energy is infinite if a bond is stretched beyond the maximum length of its potential.
+fn displace(particles: &mut [Particle], forcefield: &mut ForceField, start: &[Particle], displacements: &[Vector], scale: f64) -> f64 {
+	particles.clone_from_slice(start);
+	for (p, d) in particles.iter_mut().zip(displacements) {
+		p.pos += *d * scale;
+		forcefield.simbox.wrap(p);
+		if let Some(borders) = &forcefield.borders {
+			borders.reflect(&forcefield.simbox.size, p);
+		}
+	}
+	match forcefield.compute(particles) {
+		Ok(()) => forcefield.get_potential_energy(particles),
+		Err(_) => f64::INFINITY,
+	}
+}
+
+/// The sum of the dot products of two sets of vectors.
+fn dot(a: &[Vector], b: &[Vector]) -> f64 {
+	a.iter().zip(b).map(|(a, b)| a.dot(b)).sum()
+}
+
+/// Steepest descent, moving along the forces with a step that grows after each successful step and shrinks after each failed one.
+pub struct SteepestDescent {
+	/// The displacement of the particle with the largest force in the next step
+	pub step: f64,
+	/// The displacement of the first step, to start again from when no lower energy is found
+	initial_step: f64,
+}
+
+impl SteepestDescent {
+	/// Create a new minimizer, with the displacement of the first step.
+	pub fn new(step: f64) -> Self {
+		SteepestDescent{ step, initial_step: step }
+	}
+}
+
+impl Minimizer for SteepestDescent {
+	fn step(&mut self, particles: &mut [Particle], forcefield: &mut ForceField) -> bool {
+		let start = particles.to_vec();
+		let energy = forcefield.get_potential_energy(particles);
+		let forces = forcefield.contributions.force_total.clone();
+		let max_force = get_max_force(forcefield);
+		if max_force == 0.0 {
+			return true;
+		}
+
+		while self.step > MIN_STEP {
+			if displace(particles, forcefield, &start, &forces, self.step / max_force) < energy {
+				self.step *= 1.2;
+				return true;
+			}
+			self.step /= 2.0;
+		}
+		displace(particles, forcefield, &start, &forces, 0.0);
+		self.step = self.initial_step;
+		false
+	}
+}
+
+/** Polak-Ribière conjugate gradient, which converges much faster than steepest descent close to a minimum.
+
+Each step is a line search along the search direction: a trial step is taken, and if the energy has passed its minimum along the direction,
+the minimum is interpolated from the slopes at both ends. The search direction is reset to the forces whenever it does not go downhill,
+and when the line search finds no lower energy along it.
+*/
+pub struct ConjugateGradient {
+	/// The displacement of the particle furthest along the search direction in the next trial step
+	pub step: f64,
+	/// The displacement of the first trial step, to start again from when no lower energy is found
+	initial_step: f64,
+	forces: Vec<Vector>,
+	direction: Vec<Vector>,
+}
+
+impl ConjugateGradient {
+	/// Create a new minimizer, with the displacement of the first trial step.
+	pub fn new(step: f64) -> Self {
+		ConjugateGradient{ step, initial_step: step, forces: Vec::new(), direction: Vec::new() }
+	}
+
+	/// Search for a lower energy along the search direction, returning whether one was found.
+	fn line_search(&mut self, particles: &mut [Particle], forcefield: &mut ForceField, start: &[Particle], energy: f64) -> bool {
+		let longest = self.direction.iter().map(|d| d.len()).fold(0.0, f64::max);
+		if longest == 0.0 {
+			return true;
+		}
+		// The slope of the energy along the direction, negative as the direction goes downhill
+		let slope = -dot(&self.forces, &self.direction);
+
+		while self.step > MIN_STEP {
+			let scale = self.step / longest;
+			let trial = displace(particles, forcefield, start, &self.direction, scale);
+			if trial < energy {
+				let trial_slope = -dot(&forcefield.contributions.force_total, &self.direction);
+				if trial_slope <= 0.0 {
+					// Still going downhill, so try a longer step next time
+					self.step *= 1.2;
+					return true;
+				}
+
+				// The minimum has been passed, interpolate it from the slopes
+				let minimum = scale * slope / (slope - trial_slope);
+				if displace(particles, forcefield, start, &self.direction, minimum) > trial {
+					displace(particles, forcefield, start, &self.direction, scale);
+				}
+				return true;
+			}
+			self.step /= 2.0;
+		}
+
+		displace(particles, forcefield, start, &self.direction, 0.0);
+		self.step = self.initial_step;
+		false
+	}
+}
+
+impl Minimizer for ConjugateGradient {
+	fn step(&mut self, particles: &mut [Particle], forcefield: &mut ForceField) -> bool {
+		let start = particles.to_vec();
+		let energy = forcefield.get_potential_energy(particles);
+		let forces = forcefield.contributions.force_total.clone();
+
+		// beta = F.(F - F_prev) / F_prev.F_prev, restarting along the forces if it is negative
+		let previous = dot(&self.forces, &self.forces);
+		let beta = if self.direction.len() == forces.len() && previous > 0.0 {
+			((dot(&forces, &forces) - dot(&forces, &self.forces)) / previous).max(0.0)
+		} else {
+			0.0
+		};
+		self.direction = forces.iter().zip(self.direction.iter().chain(std::iter::repeat(&Vector::zero())))
+			.map(|(f, d)| *f + *d * beta).collect();
+		let mut along_forces = beta == 0.0;
+		if dot(&self.direction, &forces) <= 0.0 {
+			self.direction = forces.clone();
+			along_forces = true;
+		}
+		self.forces = forces;
+
+		if self.line_search(particles, forcefield, &start, energy) {
+			return true;
+		}
+		// No lower energy along this direction, start again along the forces unless that is where the search went
+		if along_forces {
+			self.direction.clear();
+			return false;
+		}
+		self.direction = self.forces.clone();
+		let found = self.line_search(particles, forcefield, &start, energy);
+		if !found {
+			self.direction.clear();
+		}
+		found
+	}
+}
+
+/** The fast inertial relaxation engine (FIRE) of Bitzek et al., Phys. Rev. Lett. 97, 170201 (2006).
+
+The particles follow the dynamics with their own velocities, which are steered towards the forces.
+The time step grows as long as the velocities go downhill, and the particles are stopped as soon as they go uphill.
+While the particles stand still, such as at the start, they accellerate along the forces without changing the time step.
+*/
+pub struct Fire {
+	/// The current time step
+	pub dt: f64,
+	/// The largest time step
+	pub dt_max: f64,
+	/// The largest displacement of any particle in a step
+	pub max_step: f64,
+	alpha: f64,
+	downhill: usize,
+	velocities: Vec<Vector>,
+}
+
+impl Fire {
+	/// The number of steps going downhill before the time step is increased
+	const DELAY: usize = 5;
+	/// The initial mixing of the velocities and the forces
+	const ALPHA: f64 = 0.1;
+
+	/// Create a new minimizer, with a starting time step and the largest displacement of any particle in a step.
+	pub fn new(dt: f64, max_step: f64) -> Self {
+		Fire{
+			dt,
+			dt_max: 10.0 * dt,
+			max_step,
+			alpha: Self::ALPHA,
+			downhill: 0,
+			velocities: Vec::new(),
+		}
+	}
+}
+
+impl Minimizer for Fire {
+	fn step(&mut self, particles: &mut [Particle], forcefield: &mut ForceField) -> bool {
+		let start = particles.to_vec();
+		let forces = &forcefield.contributions.force_total;
+		self.velocities.resize(particles.len(), Vector::zero());
+
+		let power = dot(forces, &self.velocities);
+		if power > 0.0 {
+			// v = (1 - alpha) v + alpha |v| F / |F|
+			let speed = dot(&self.velocities, &self.velocities).sqrt();
+			let force = dot(forces, forces).sqrt();
+			for (v, f) in self.velocities.iter_mut().zip(forces) {
+				*v = *v * (1.0 - self.alpha) + *f * (self.alpha * speed / force);
+			}
+			if self.downhill > Self::DELAY {
+				self.dt = (self.dt * 1.1).min(self.dt_max);
+				self.alpha *= 0.99;
+			}
+			self.downhill += 1;
+		} else if power < 0.0 {
+			self.velocities.iter_mut().for_each(|v| *v = Vector::zero());
+			self.dt /= 2.0;
+			self.alpha = Self::ALPHA;
+			self.downhill = 0;
+		}
+
+		// A semi-implicit Euler step, limited to the largest displacement
+		for ((v, f), p) in self.velocities.iter_mut().zip(forces).zip(particles.iter()) {
+			*v += *f * (self.dt / p.m);
+		}
+		let longest = self.velocities.iter().map(|v| v.len() * self.dt).fold(0.0, f64::max);
+		let scale = if longest > self.max_step { self.max_step / longest } else { 1.0 };
+		for (p, v) in particles.iter_mut().zip(self.velocities.iter()) {
+			p.pos += *v * (self.dt * scale);
+			forcefield.simbox.wrap(p);
+			if let Some(borders) = &forcefield.borders {
+				borders.reflect(&forcefield.simbox.size, p);
+			}
+		}
+		if forcefield.compute(particles).is_err() {
+			// A bond would be stretched too far, so go back, where the forcefield is still computed, and stop as if going uphill
+			particles.clone_from_slice(&start);
+			self.velocities.iter_mut().for_each(|v| *v = Vector::zero());
+			self.dt /= 2.0;
+			self.alpha = Self::ALPHA;
+			self.downhill = 0;
+		}
+		true
+	}
+}
+
+/// Minimize the potential energy of the particles, until one of the criteria is met or the minimizer finds no lower energy.
+/// The forcefield is left computed for the final positions. Fails if a bond is stretched too far at the start.
+pub fn minimize(minimizer: &mut dyn Minimizer, particles: &mut [Particle], forcefield: &mut ForceField, criteria: &Criteria) -> Result<Minimization, BondError> {
+	forcefield.compute(particles)?;
+	let mut energy = forcefield.get_potential_energy(particles);
+	let mut steps = 0;
+	let mut converged = get_max_force(forcefield) < criteria.max_force;
+
+	while !converged && steps < criteria.steps {
+		let moved = minimizer.step(particles, forcefield);
+		steps += 1;
+		if !moved {
+			// Stuck, which is not the same as not changing the energy any more
+			break;
+		}
+
+		let new_energy = forcefield.get_potential_energy(particles);
+		converged = get_max_force(forcefield) < criteria.max_force || (new_energy - energy).abs() <= criteria.energy_change;
+		energy = new_energy;
+	}
+
+	Ok(Minimization{ steps, energy, max_force: get_max_force(forcefield), converged })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::simbox::SimBox;
+	use crate::random::Random;
+	use crate::forcefield::Electrostatics;
+	use crate::species::{SpeciesTable, Species, MixingRule};
+
+	const METHODS: [&str; 3] = ["steepest_descent", "conjugate_gradient", "fire"];
+
+	/// One of METHODS, with a largest step and a time step of 0.01.
+	fn by_name(method: &str) -> Box<dyn Minimizer> {
+		match method {
+			"steepest_descent" => Box::new(SteepestDescent::new(0.01)),
+			"conjugate_gradient" => Box::new(ConjugateGradient::new(0.01)),
+			_ => Box::new(Fire::new(0.01, 0.01)),
+		}
+	}
+
+	/// A forcefield of Lennard-Jones particles with a sigma of 0.5 in a large closed box without walls.
+	fn forcefield() -> ForceField {
+		let mut species = SpeciesTable::new(MixingRule::LorentzBerthelot);
+		species.add(Species{ name: String::from("lj"), sigma: 0.5, epsilon: 1.0, mass: 1.0, charge: 0.0 });
+		let mut forcefield = ForceField::new();
+		forcefield.simbox = SimBox::new(Vector::new(100.0, 100.0, 100.0), false);
+		forcefield.borders = None;
+		forcefield.electrostatic = Electrostatics::Off;
+		forcefield.species = species;
+		forcefield
+	}
+
+	/// Two particles a distance apart along a diagonal.
+	fn dimer(forcefield: &ForceField, distance: f64) -> Vec<Particle> {
+		let centre = Vector::new(50.0, 50.0, 50.0);
+		let half = Vector::new(1.0, 2.0, 2.0) * (distance / 6.0);
+		vec![forcefield.species.particle(0, &(centre - half)), forcefield.species.particle(0, &(centre + half))]
+	}
+
+	#[test]
+	fn dimer_relaxes_to_the_minimum() {
+		// The potential uses the distance between the surfaces, so the minimum is at 2^(1/6) sigma beyond the sum of the radii
+		let minimum = 0.5 * (1.0 + 2_f64.powf(1.0 / 6.0));
+		// Below this the changes in energy of the line searches are lost in the rounding errors
+		let criteria = Criteria{ max_force: 1e-6, energy_change: 0.0, steps: 10000 };
+		for method in METHODS {
+			for start in [0.95 * minimum, 1.2 * minimum] {
+				let mut forcefield = forcefield();
+				let mut particles = dimer(&forcefield, start);
+				let mut minimizer = by_name(method);
+				let result = minimize(minimizer.as_mut(), &mut particles, &mut forcefield, &criteria).unwrap();
+				let distance = (particles[1].pos - particles[0].pos).len();
+				assert!(result.converged, "{} did not converge from {}", method, start);
+				assert!((distance - minimum).abs() < 1e-7, "{} relaxed to {} instead of {}", method, distance, minimum);
+				assert!((result.energy + 1.0).abs() < 1e-12, "{} has an energy of {}", method, result.energy);
+			}
+		}
+	}
+
+	#[test]
+	fn cluster_converges() {
+		let spacing = 1.1;
+		let mut random = Random::new(1);
+		let mut forcefield = forcefield();
+		let mut start = Vec::new();
+		for i in 0..27 {
+			let pos = Vector::new((i % 3) as f64, (i / 3 % 3) as f64, (i / 9) as f64) * spacing + Vector::new(48.0, 48.0, 48.0)
+				+ Vector::new(random.uniform() - 0.5, random.uniform() - 0.5, random.uniform() - 0.5) * 0.2;
+			start.push(forcefield.species.particle(0, &pos));
+		}
+		forcefield.compute(&mut start).unwrap();
+		let initial = forcefield.get_potential_energy(&start);
+
+		let criteria = Criteria{ max_force: 1e-4, energy_change: 0.0, steps: 100000 };
+		for method in METHODS {
+			let mut particles = start.clone();
+			let mut minimizer = by_name(method);
+			let result = minimize(minimizer.as_mut(), &mut particles, &mut forcefield, &criteria).unwrap();
+			assert!(result.converged && result.max_force < criteria.max_force, "{} did not converge, the largest force is {}", method, result.max_force);
+			assert!(result.energy < initial);
+		}
+	}
+
+	#[test]
+	fn stuck_minimization_is_not_converged() {
+		// No step can meet these criteria at the minimum, so the minimizers must not stop as if they were met
+		let minimum = 0.5 * (1.0 + 2_f64.powf(1.0 / 6.0));
+		let criteria = Criteria{ max_force: 0.0, energy_change: 0.0, steps: 1000 };
+		for method in ["steepest_descent", "conjugate_gradient"] {
+			let mut forcefield = forcefield();
+			let mut particles = dimer(&forcefield, minimum);
+			let mut minimizer = by_name(method);
+			let result = minimize(minimizer.as_mut(), &mut particles, &mut forcefield, &criteria).unwrap();
+			assert!(!result.converged, "{} converged without meeting the criteria", method);
+		}
+	}
+
+	#[test]
+	fn failed_line_search_resets_the_step() {
+		let minimum = 0.5 * (1.0 + 2_f64.powf(1.0 / 6.0));
+		let mut forcefield = forcefield();
+		let mut particles = dimer(&forcefield, minimum);
+		forcefield.compute(&mut particles).unwrap();
+		let mut minimizer = SteepestDescent::new(0.01);
+		if !minimizer.step(&mut particles, &mut forcefield) {
+			assert_eq!(minimizer.step, 0.01);
+		}
+		let moved = dimer(&forcefield, 1.2 * minimum);
+		particles.clone_from_slice(&moved);
+		forcefield.compute(&mut particles).unwrap();
+		assert!(minimizer.step(&mut particles, &mut forcefield));
+		assert!((particles[1].pos - particles[0].pos).len() < 1.2 * minimum);
+	}
+
+	#[test]
+	fn fire_starts_moving_at_the_full_time_step() {
+		let mut forcefield = forcefield();
+		let mut particles = dimer(&forcefield, 1.2 * 0.5 * (1.0 + 2_f64.powf(1.0 / 6.0)));
+		forcefield.compute(&mut particles).unwrap();
+		let mut fire = Fire::new(0.01, 0.1);
+		assert!(fire.step(&mut particles, &mut forcefield));
+		assert_eq!(fire.dt, 0.01);
+	}
+}
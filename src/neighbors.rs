@@ -0,0 +1,267 @@
+/*! Neighbor lists, to only evaluate the pairs of particles that are close to each other.
+
+The list contains all pairs within the cutoff plus a skin distance, and is built using a cell list:
+the box is divided into cells of at least that size, so only particles in neighboring cells have to be compared.
+As long as no particle has moved more than half the skin since the last build, no pair within the cutoff can be missing from the list,
+so the list is only rebuilt once a particle has moved further than that, or the box has shrunk by too much.
+*/
+
+use crate::vectors::Vector;
+use crate::particles::Particle;
+use crate::simbox::SimBox;
+
+/// A Verlet neighbor list, built from a cell list.
+pub struct NeighborList {
+	/// The distance within which pairs interact
+	pub cutoff: f64,
+	/// The extra distance included in the list, so it does not need to be rebuilt every step
+	pub skin: f64,
+	/// The number of times the list has been built
+	pub builds: usize,
+	pairs: Vec<(usize, usize)>,
+	reference: Vec<Vector>,
+	reference_size: Vector,
+}
+
+impl NeighborList {
+	/// Create a new, empty neighbor list. The list is built on the first update().
+	pub fn new(cutoff: f64, skin: f64) -> Self {
+		NeighborList{
+			cutoff,
+			skin,
+			builds: 0,
+			pairs: Vec::new(),
+			reference: Vec::new(),
+			reference_size: Vector::zero(),
+		}
+	}
+
+	/// All pairs (i, j) with i < j that are within the cutoff plus the skin, as of the last build.
+	pub fn pairs(&self) -> &[(usize, usize)] {
+		&self.pairs
+	}
+
+	/// Rebuild the list if any particle may have come within the cutoff of a particle not in its list since the last build.
+	/// Returns whether the list was rebuilt.
+	pub fn update(&mut self, simbox: &SimBox, particles: &[Particle]) -> bool {
+		if self.needs_rebuild(simbox, particles) {
+			self.build(simbox, particles);
+			true
+		} else {
+			false
+		}
+	}
+
+	/// Whether the list is out of date.
+	///
+	/// If the box has been scaled since the last build (by a barostat), the reference positions are scaled along with it,
+	/// and the distances between particles have shrunk by at most the smallest scale factor, which uses up part of the skin.
+	fn needs_rebuild(&self, simbox: &SimBox, particles: &[Particle]) -> bool {
+		if self.reference.len() != particles.len() {
+			return true;
+		}
+
+		let scale = Vector::new(simbox.size.x / self.reference_size.x,
+		                        simbox.size.y / self.reference_size.y,
+		                        simbox.size.z / self.reference_size.z);
+		let smallest = scale.x.min(scale.y).min(scale.z);
+		let max = (smallest * (self.cutoff + self.skin) - self.cutoff) / 2.0;
+		if max.is_nan() || max < 0.0 {
+			return true;
+		}
+
+		let max_sq = max * max;
+		particles.iter().zip(self.reference.iter()).any(|(p, r)| {
+			let r = Vector::new(r.x * scale.x, r.y * scale.y, r.z * scale.z);
+			simbox.separation(&r, &p.pos).sqlen() > max_sq
+		})
+	}
+
+	/// Build the list using a cell list.
+	fn build(&mut self, simbox: &SimBox, particles: &[Particle]) {
+		let range = self.cutoff + self.skin;
+		let range_sq = range * range;
+		let size = [simbox.size.x, simbox.size.y, simbox.size.z];
+		let mut cells = size.map(|l| (l / range).floor().max(1.0));
+		// At most about one cell per particle, so a large and sparse box does not need a huge number of empty cells
+		let limit = particles.len().max(1) as f64;
+		let total: f64 = cells.iter().product();
+		if total > limit {
+			let factor = (total / limit).cbrt();
+			cells = cells.map(|n| (n / factor).floor().max(1.0));
+		}
+		let cells = cells.map(|n| n as usize);
+
+		// The cell of each particle, particles outside of a walled box are put in the outermost cell
+		let cell_of = |pos: &Vector| -> usize {
+			let pos = [pos.x, pos.y, pos.z];
+			let mut index = [0; 3];
+			for k in 0..3 {
+				let c = (pos[k] / size[k] * cells[k] as f64).floor();
+				index[k] = (c.max(0.0) as usize).min(cells[k] - 1);
+			}
+			(index[0] * cells[1] + index[1]) * cells[2] + index[2]
+		};
+
+		let mut members = vec![Vec::new(); cells[0] * cells[1] * cells[2]];
+		for (i, p) in particles.iter().enumerate() {
+			members[cell_of(&p.pos)].push(i);
+		}
+
+		self.pairs.clear();
+		for cx in 0..cells[0] {
+			for cy in 0..cells[1] {
+				for cz in 0..cells[2] {
+					let cell = (cx * cells[1] + cy) * cells[2] + cz;
+
+					// Collect the (unique) neighboring cells, a small periodic box can wrap around to the same cell
+					let mut neighbors = Vec::with_capacity(27);
+					for dx in -1..=1 {
+						for dy in -1..=1 {
+							for dz in -1..=1 {
+								let mut index = [0; 3];
+								let mut valid = true;
+								for (k, (c, d)) in [(cx, dx), (cy, dy), (cz, dz)].into_iter().enumerate() {
+									let n = c as i64 + d;
+									if n >= 0 && n < cells[k] as i64 {
+										index[k] = n as usize;
+									} else if simbox.periodic[k] {
+										index[k] = n.rem_euclid(cells[k] as i64) as usize;
+									} else {
+										valid = false;
+									}
+								}
+								let neighbor = (index[0] * cells[1] + index[1]) * cells[2] + index[2];
+								if valid && neighbor >= cell && !neighbors.contains(&neighbor) {
+									neighbors.push(neighbor);
+								}
+							}
+						}
+					}
+
+					for &neighbor in neighbors.iter() {
+						for &i in members[cell].iter() {
+							for &j in members[neighbor].iter() {
+								if neighbor == cell && j <= i {
+									continue;
+								}
+								if simbox.separation(&particles[i].pos, &particles[j].pos).sqlen() < range_sq {
+									self.pairs.push((i.min(j), i.max(j)));
+								}
+							}
+						}
+					}
+				}
+			}
+		}
+
+		self.reference = particles.iter().map(|p| p.pos).collect();
+		self.reference_size = simbox.size;
+		self.builds += 1;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::random::Random;
+
+	fn particles(count: usize, size: Vector, seed: u64) -> Vec<Particle> {
+		let mut random = Random::new(seed);
+		(0..count).map(|_| {
+			let pos = Vector::new(random.uniform() * size.x, random.uniform() * size.y, random.uniform() * size.z);
+			Particle::new(&pos, 0.5, 1.0, 0.0)
+		}).collect()
+	}
+
+	/// All pairs within a range, comparing every pair.
+	fn brute_force(simbox: &SimBox, particles: &[Particle], range: f64) -> Vec<(usize, usize)> {
+		let mut pairs = Vec::new();
+		for i in 0..particles.len() {
+			for j in (i + 1)..particles.len() {
+				if simbox.separation(&particles[i].pos, &particles[j].pos).len() < range {
+					pairs.push((i, j));
+				}
+			}
+		}
+		pairs
+	}
+
+	fn sorted(list: &NeighborList) -> Vec<(usize, usize)> {
+		let mut pairs = list.pairs().to_vec();
+		pairs.sort_unstable();
+		pairs
+	}
+
+	#[test]
+	fn list_has_all_pairs_within_range() {
+		let size = Vector::new(12.0, 9.0, 10.5);
+		for periodic in [[true; 3], [false; 3], [true, false, true]] {
+			let simbox = SimBox::with_axes(size, periodic);
+			let particles = particles(400, size, 1);
+			let mut list = NeighborList::new(2.5, 0.3);
+			assert!(list.update(&simbox, &particles));
+			let pairs = sorted(&list);
+			assert!(!pairs.is_empty());
+			assert_eq!(pairs, brute_force(&simbox, &particles, 2.8));
+		}
+	}
+
+	#[test]
+	fn small_boxes_have_all_pairs() {
+		// One and two cells along the axes
+		for size in [Vector::new(2.9, 2.9, 2.9), Vector::new(5.7, 4.0, 2.9), Vector::new(6.0, 8.0, 7.0)] {
+			for periodic in [true, false] {
+				let simbox = SimBox::new(size, periodic);
+				let particles = particles(60, size, 2);
+				let mut list = NeighborList::new(2.6, 0.2);
+				list.update(&simbox, &particles);
+				assert_eq!(sorted(&list), brute_force(&simbox, &particles, 2.8));
+			}
+		}
+	}
+
+	#[test]
+	fn list_rebuilds_after_moving_half_the_skin() {
+		let size = Vector::new(10.0, 10.0, 10.0);
+		let simbox = SimBox::new(size, true);
+		let mut particles = particles(100, size, 3);
+		let mut list = NeighborList::new(2.5, 0.4);
+		list.update(&simbox, &particles);
+		assert_eq!(list.builds, 1);
+
+		particles[7].pos.x += 0.19;
+		particles[8].pos.y -= 0.19;
+		assert!(!list.update(&simbox, &particles));
+		particles[7].pos.x += 0.02;
+		assert!(list.update(&simbox, &particles));
+		assert_eq!(list.builds, 2);
+		assert_eq!(sorted(&list), brute_force(&simbox, &particles, 2.9));
+	}
+
+	#[test]
+	fn list_rebuilds_after_the_box_shrinks() {
+		let size = Vector::new(10.0, 10.0, 10.0);
+		let mut simbox = SimBox::new(size, true);
+		let mut particles = particles(100, size, 4);
+		let mut list = NeighborList::new(2.5, 0.4);
+		list.update(&simbox, &particles);
+
+		let scale = |simbox: &mut SimBox, particles: &mut [Particle], factor: f64| {
+			simbox.size = simbox.size * factor;
+			for p in particles.iter_mut() {
+				p.pos = p.pos * factor;
+			}
+		};
+		// Scaling by 0.99 shrinks the distances within the range by less than the skin
+		scale(&mut simbox, &mut particles, 0.99);
+		assert!(!list.update(&simbox, &particles));
+		// A pair at the edge of the list could now be within the cutoff without being in the list
+		scale(&mut simbox, &mut particles, 0.85);
+		assert!(list.update(&simbox, &particles));
+		assert_eq!(sorted(&list), brute_force(&simbox, &particles, 2.9));
+		// Growing the box does not use up the skin
+		scale(&mut simbox, &mut particles, 1.1);
+		assert!(!list.update(&simbox, &particles));
+	}
+}
@@ -4,27 +4,40 @@
 use std::fmt;
 use crate::vectors::Vector;
 
-/// A particle, with associated v, a, radius, mass, and charge
+/// A particle, with associated v, a, radius, mass, charge, and species
+#[derive(Clone)]
 pub struct Particle {
-	pub pos: Vector,	// Position
-	pub r: f64,			// Radius
-	pub m: f64,			// Mass
-	pub q: f64,			// Charge
-	pub v: Vector,		// Velocity
-	pub a: Vector,		// Accelleration
+	/// Position
+	pub pos: Vector,
+	/// Radius
+	pub r: f64,
+	/// Mass
+	pub m: f64,
+	/// Charge
+	pub q: f64,
+	/// The index of the species in the SpeciesTable of the forcefield, which sets the Lennard-Jones parameters
+	pub species: usize,
+	/// Velocity
+	pub v: Vector,
+	/// Accelleration
+	pub a: Vector,
+	/// The number of times the particle has been wrapped around a periodic box, along each axis
+	pub image: [i64; 3],
 }
 
 #[allow(dead_code)]
 impl Particle {
-	/// Create a new particle with given values
+	/// Create a new particle with given values, of the first species
 	pub fn new(pos: &Vector, r: f64, m: f64, q: f64) -> Self {
 		Particle{
 			pos: *pos,
 			r,
 			m,
 			q,
+			species: 0,
 			v: Vector::zero(),
 			a: Vector::zero(),
+			image: [0; 3],
 		}
 	}
 
@@ -0,0 +1,151 @@
+/*! A seeded pseudo-random number generator, so stochastic simulations are reproducible.
+
+Uses xoshiro256** (Blackman and Vigna), with the state initialised from the seed by splitmix64.
+The same seed always results in the same sequence, on any platform.
+*/
+
+/// A xoshiro256** random number generator.
+#[derive(Clone)]
+pub struct Random {
+	state: [u64; 4],
+	/// The second normal number generated by the last Box-Muller transform, if it has not been used yet
+	spare: Option<f64>,
+}
+
+impl Random {
+	/// Create a new generator from a seed.
+	pub fn new(seed: u64) -> Self {
+		// splitmix64, so similar seeds still give unrelated states
+		let mut x = seed;
+		let state = [0; 4].map(|_: u64| {
+			x = x.wrapping_add(0x9e3779b97f4a7c15);
+			let mut z = x;
+			z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+			z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+			z ^ (z >> 31)
+		});
+		Random{ state, spare: None }
+	}
+
+	/// The next 64 random bits.
+	pub fn next_u64(&mut self) -> u64 {
+		let s = &mut self.state;
+		let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+		let t = s[1] << 17;
+		s[2] ^= s[0];
+		s[3] ^= s[1];
+		s[1] ^= s[2];
+		s[0] ^= s[3];
+		s[2] ^= t;
+		s[3] = s[3].rotate_left(45);
+		result
+	}
+
+	/// A uniformly distributed number in [0, 1).
+	pub fn uniform(&mut self) -> f64 {
+		(self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+	}
+
+	/// A normally distributed number with mean 0 and variance 1, using the Box-Muller transform.
+	pub fn normal(&mut self) -> f64 {
+		if let Some(spare) = self.spare.take() {
+			return spare;
+		}
+
+		// 1 - uniform() is in (0, 1], so the logarithm is finite
+		let r = (-2.0 * (1.0 - self.uniform()).ln()).sqrt();
+		let theta = 2.0 * std::f64::consts::PI * self.uniform();
+		self.spare = Some(r * theta.sin());
+		r * theta.cos()
+	}
+
+	/// A gamma distributed number with a given shape and a scale of 1, using the method of Marsaglia and Tsang.
+	pub fn gamma(&mut self, shape: f64) -> f64 {
+		if shape < 1.0 {
+			// Gamma(a) = Gamma(a + 1) U^(1/a)
+			return self.gamma(shape + 1.0) * (1.0 - self.uniform()).powf(1.0 / shape);
+		}
+
+		let d = shape - 1.0 / 3.0;
+		let c = 1.0 / (9.0 * d).sqrt();
+		loop {
+			let x = self.normal();
+			let v = 1.0 + c * x;
+			if v <= 0.0 {
+				continue;
+			}
+			let v = v * v * v;
+			let u = 1.0 - self.uniform();
+			if u.ln() < x * x / 2.0 + d - d * v + d * v.ln() {
+				return d * v;
+			}
+		}
+	}
+
+	/// The sum of the squares of a number of normally distributed numbers, which is chi-squared distributed.
+	pub fn chi_squared(&mut self, degrees_of_freedom: usize) -> f64 {
+		if degrees_of_freedom == 0 { 0.0 } else { 2.0 * self.gamma(degrees_of_freedom as f64 / 2.0) }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// The mean and the variance of a number of samples from a distribution.
+	fn mean_and_variance(count: usize, mut sample: impl FnMut() -> f64) -> (f64, f64) {
+		let samples: Vec<f64> = (0..count).map(|_| sample()).collect();
+		let mean = samples.iter().sum::<f64>() / count as f64;
+		let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / count as f64;
+		(mean, variance)
+	}
+
+	#[test]
+	fn same_seed_gives_the_same_sequence() {
+		let (mut a, mut b) = (Random::new(1), Random::new(1));
+		assert!((0..100).all(|_| a.next_u64() == b.next_u64()));
+		let mut c = Random::new(2);
+		assert!((0..100).any(|_| a.next_u64() != c.next_u64()));
+	}
+
+	#[test]
+	fn uniform_is_in_the_unit_interval() {
+		let mut random = Random::new(3);
+		let (mean, variance) = mean_and_variance(100000, || {
+			let x = random.uniform();
+			assert!((0.0..1.0).contains(&x));
+			x
+		});
+		assert!((mean - 0.5).abs() < 0.01);
+		assert!((variance - 1.0 / 12.0).abs() < 0.01);
+	}
+
+	#[test]
+	fn normal_has_mean_0_and_variance_1() {
+		let mut random = Random::new(4);
+		let (mean, variance) = mean_and_variance(100000, || random.normal());
+		assert!(mean.abs() < 0.01, "{}", mean);
+		assert!((variance - 1.0).abs() < 0.02, "{}", variance);
+	}
+
+	#[test]
+	fn gamma_has_the_mean_and_variance_of_its_shape() {
+		let mut random = Random::new(5);
+		for shape in [0.3, 1.0, 2.5, 14.5] {
+			let (mean, variance) = mean_and_variance(100000, || random.gamma(shape));
+			assert!((mean / shape - 1.0).abs() < 0.02, "{} != {}", mean, shape);
+			assert!((variance / shape - 1.0).abs() < 0.04, "{} != {}", variance, shape);
+		}
+	}
+
+	#[test]
+	fn chi_squared_has_mean_k_and_variance_2k() {
+		let mut random = Random::new(6);
+		for k in [1, 2, 29] {
+			let (mean, variance) = mean_and_variance(100000, || random.chi_squared(k));
+			assert!((mean / k as f64 - 1.0).abs() < 0.02, "{} != {}", mean, k);
+			assert!((variance / (2 * k) as f64 - 1.0).abs() < 0.04, "{} != {}", variance, 2 * k);
+		}
+		assert_eq!(random.chi_squared(0), 0.0);
+	}
+}
@@ -0,0 +1,110 @@
+//! The box containing the system, optionally with periodic boundary conditions along each axis.
+
+use crate::vectors::Vector;
+use crate::particles::Particle;
+
+/** The box containing the system, running from the origin to size along each axis.
+
+Along each periodic axis, separations follow the minimum-image convention, and particles leaving the box re-enter on the opposite side.
+*/
+#[derive(Clone, Copy)]
+pub struct SimBox {
+	/// The size of the box along each axis
+	pub size: Vector,
+	/// Whether the box is periodic along each axis
+	pub periodic: [bool; 3],
+}
+
+impl SimBox {
+	/// Create a new box with a given size, which is either periodic along all axes or along none.
+	pub fn new(size: Vector, periodic: bool) -> Self {
+		SimBox{ size, periodic: [periodic; 3] }
+	}
+
+	/// Create a new box with a given size, periodic along some of the axes.
+	pub fn with_axes(size: Vector, periodic: [bool; 3]) -> Self {
+		SimBox{ size, periodic }
+	}
+
+	/// Whether the box is periodic along all axes.
+	pub fn is_periodic(&self) -> bool {
+		self.periodic.iter().all(|&p| p)
+	}
+
+	/// The volume of the box.
+	pub fn volume(&self) -> f64 {
+		self.size.x * self.size.y * self.size.z
+	}
+
+	/// Separation vector from one position to another, using the nearest periodic image of the other position.
+	pub fn separation(&self, from: &Vector, to: &Vector) -> Vector {
+		let mut d = *to - *from;
+		if self.periodic[0] {
+			d.x -= self.size.x * (d.x / self.size.x).round();
+		}
+		if self.periodic[1] {
+			d.y -= self.size.y * (d.y / self.size.y).round();
+		}
+		if self.periodic[2] {
+			d.z -= self.size.z * (d.z / self.size.z).round();
+		}
+		d
+	}
+
+	/// Move a particle that has left the box along a periodic axis back into the box, keeping track of the number of box lengths it has moved.
+	pub fn wrap(&self, particle: &mut Particle) {
+		if !self.periodic.iter().any(|&p| p) {
+			return;
+		}
+
+		let size = [self.size.x, self.size.y, self.size.z];
+		let mut pos = [particle.pos.x, particle.pos.y, particle.pos.z];
+		for k in (0..3).filter(|&k| self.periodic[k]) {
+			let mut shift = (pos[k] / size[k]).floor();
+			pos[k] -= shift * size[k];
+			// A position just below zero rounds up to exactly the size, which is outside the box
+			if pos[k] >= size[k] {
+				pos[k] -= size[k];
+				shift += 1.0;
+			}
+			particle.image[k] += shift as i64;
+		}
+		particle.pos = Vector::new(pos[0], pos[1], pos[2]);
+	}
+
+	/// The position of a particle as if it had never been wrapped back into the box.
+	pub fn unwrapped(&self, particle: &Particle) -> Vector {
+		particle.pos + Vector::new(particle.image[0] as f64 * self.size.x,
+		                           particle.image[1] as f64 * self.size.y,
+		                           particle.image[2] as f64 * self.size.z)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn wrap_stays_inside_the_box() {
+		let simbox = SimBox::new(Vector::new(10.0, 3.0, 7.0), true);
+		for x in [-1e-17, -1e-300, -0.0, 0.0, 10.0, 25.0, -25.0, 9.999999999999998] {
+			let mut p = Particle::new(&Vector::new(x, x, x), 1.0, 1.0, 0.0);
+			simbox.wrap(&mut p);
+			let pos = [p.pos.x, p.pos.y, p.pos.z];
+			let size = [simbox.size.x, simbox.size.y, simbox.size.z];
+			for k in 0..3 {
+				assert!(pos[k] >= 0.0 && pos[k] < size[k], "{} wraps to {}", x, pos[k]);
+			}
+			assert!((simbox.unwrapped(&p) - Vector::new(x, x, x)).len() < 1e-12);
+		}
+	}
+
+	#[test]
+	fn wrap_keeps_open_axes() {
+		let simbox = SimBox::with_axes(Vector::new(10.0, 10.0, 10.0), [true, false, true]);
+		let mut p = Particle::new(&Vector::new(-1.0, -1.0, 11.0), 1.0, 1.0, 0.0);
+		simbox.wrap(&mut p);
+		assert_eq!([p.pos.x, p.pos.y, p.pos.z], [9.0, -1.0, 1.0]);
+		assert_eq!(p.image, [-1, 0, 1]);
+	}
+}
@@ -0,0 +1,396 @@
+//! A simulation of a system of particles, which can be advanced through time.
+
+use std::fmt;
+use std::error::Error;
+
+use crate::particles::Particle;
+use crate::log_data::DataLog;
+use crate::units::Dimension;
+use crate::forcefield::ForceField;
+use crate::forcefield::bonded::BondError;
+use crate::forcefield::temperature::{self, Thermostat};
+use crate::forcefield::pressure::Barostat;
+use crate::integrators::Integrator;
+use crate::constraints::{Constraints, ConstraintError};
+
+/// An error that stops a simulation.
+#[derive(Debug)]
+pub enum SimulationError {
+	/// The constraints could not be satisfied
+	Constraint(ConstraintError),
+	/// A bond was stretched beyond the maximum length of its potential
+	Bond(BondError),
+	/// The barostat shrank the box along a periodic axis below twice the range of the interactions, with the size of the box and the range
+	BoxTooSmall(f64, f64),
+	/// The integrator was not given a friction coefficient for each particle, with the number of coefficients and of particles
+	Friction(usize, usize),
+}
+
+impl fmt::Display for SimulationError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SimulationError::Constraint(e) => e.fmt(f),
+			SimulationError::Bond(e) => e.fmt(f),
+			SimulationError::BoxTooSmall(size, range) => write!(f, "The box shrank to a size of {}, below twice the range of the interactions ({})", size, range),
+			SimulationError::Friction(count, particles) => write!(f, "There are {} friction coefficients for {} particles", count, particles),
+		}
+	}
+}
+
+impl Error for SimulationError {}
+
+impl From<ConstraintError> for SimulationError {
+	fn from(e: ConstraintError) -> Self {
+		SimulationError::Constraint(e)
+	}
+}
+
+impl From<BondError> for SimulationError {
+	fn from(e: BondError) -> Self {
+		SimulationError::Bond(e)
+	}
+}
+
+/// A function that is called after each step of a simulation.
+pub type Observer<'a> = Box<dyn FnMut(&Simulation<'a>) + 'a>;
+
+/** A simulation, owning the particles, the forcefield, the integrator and the log of all data.
+
+The initial forces are calculated and the initial state is logged on creation,
+after which each step() advances the system by one time step.
+*/
+pub struct Simulation<'a> {
+	/// The particles in the system
+	pub particles: Vec<Particle>,
+	/// The force terms acting on the particles
+	pub forcefield: ForceField,
+	/// The integrator used to step through time
+	pub integrator: Box<dyn Integrator>,
+	/// The thermostat, if any
+	pub thermostat: Option<Box<dyn Thermostat>>,
+	/// The barostat, if any
+	pub barostat: Option<Box<dyn Barostat>>,
+	/// The constraints, if any
+	pub constraints: Option<Constraints>,
+	/// The degrees of freedom removed besides those of the constraints, such as the momentum removed from the initial velocities, which do not count towards the temperature
+	pub removed_degrees_of_freedom: usize,
+	/// All logged data of the simulation
+	pub data: DataLog<'a>,
+	/// The current time
+	pub t: f64,
+	/// The time step
+	pub dt: f64,
+	/// The number of steps taken
+	pub steps: usize,
+	observers: Vec<Observer<'a>>,
+}
+
+impl<'a> Simulation<'a> {
+	/// Create a new simulation, calculating the initial forces and logging the initial state.
+	///
+	/// The particles are first moved onto the constraints, which fails if they cannot be satisfied, as does a bond that is stretched too far.
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(mut particles: Vec<Particle>, mut forcefield: ForceField, mut integrator: Box<dyn Integrator>, thermostat: Option<Box<dyn Thermostat>>, barostat: Option<Box<dyn Barostat>>, mut constraints: Option<Constraints>, removed_degrees_of_freedom: usize, dt: f64) -> Result<Self, SimulationError> {
+		let mut data = DataLog::new(particles.len());
+
+		data.add_particle_vector_series("position", Dimension::Length);
+		data.add_particle_vector_series("position_unwrapped", Dimension::Length);
+		data.add_particle_vector_series("velocity", Dimension::Velocity);
+		data.add_particle_vector_series("accelleration", Dimension::Accelleration);
+		forcefield.add_series(&mut data);
+		integrator.add_series(&mut data);
+		data.add_particle_series("energy_kinetic", Dimension::Energy);
+		data.add_particle_series("energy_total", Dimension::Energy);
+		data.add_global_series("temperature", Dimension::Temperature);
+		data.add_global_series("temperature_scale", Dimension::None);
+		data.add_global_series("pressure", Dimension::Pressure);
+		data.add_global_series("volume", Dimension::Volume);
+		data.add_global_series("energy_thermostat", Dimension::Energy);
+		data.add_global_series("energy_barostat", Dimension::Energy);
+		data.add_global_series("energy_conserved", Dimension::Energy);
+
+		data.time.push(0.0);
+		if let Some(c) = &mut constraints {
+			c.project(&forcefield.simbox, &mut particles, dt)?;
+		}
+		forcefield.compute(&mut particles)?;
+		if let Some(c) = &mut constraints {
+			// The virial of the constraint forces of a half kick, as in the steps
+			let mut kicked = particles.clone();
+			for p in kicked.iter_mut() {
+				p.update_v(dt / 2.0);
+			}
+			c.constrain_velocities(&forcefield.simbox, &mut kicked, dt)?;
+			forcefield.contributions.virial += c.virial;
+		}
+		forcefield.log(&particles, &mut data);
+		integrator.init(&mut particles, dt)?;
+		integrator.log(&mut data);
+		data.global.insert_into("temperature_scale", 1.0);
+
+		let mut sim = Simulation{
+			particles,
+			forcefield,
+			integrator,
+			thermostat,
+			barostat,
+			constraints,
+			removed_degrees_of_freedom,
+			data,
+			t: 0.0,
+			dt,
+			steps: 0,
+			observers: Vec::new(),
+		};
+		sim.log_state();
+		Ok(sim)
+	}
+
+	/// Add a function that is called after each step.
+	pub fn add_observer<F>(&mut self, observer: F) where F: FnMut(&Simulation<'a>) + 'a {
+		self.observers.push(Box::new(observer));
+	}
+
+	/// Advance the simulation by one time step, which fails if the constraints cannot be satisfied, a bond is stretched too far, or the barostat shrinks the box too far.
+	pub fn step(&mut self) -> Result<(), SimulationError> {
+		let mut scale = self.with_current_velocities(|sim| {
+			let scale = match &mut sim.thermostat {
+				Some(thermostat) => thermostat.pre_step(&mut sim.particles, sim.t, sim.dt),
+				None => 1.0,
+			};
+			if let Some(barostat) = &mut sim.barostat {
+				let pressure = sim.forcefield.get_pressure(&sim.particles);
+				barostat.pre_step(&mut sim.particles, &mut sim.forcefield.simbox, pressure, sim.t, sim.dt);
+			}
+			scale
+		});
+
+		if let Some(constraints) = &mut self.constraints {
+			constraints.save(&self.particles);
+		}
+		self.integrator.pre_force(&mut self.particles, self.dt);
+		if let Some(barostat) = &mut self.barostat {
+			barostat.rescale(&mut self.particles, &mut self.forcefield.simbox, self.t, self.dt);
+			self.check_box()?;
+		}
+		if let Some(constraints) = &mut self.constraints {
+			constraints.constrain_positions(&self.forcefield.simbox, &mut self.particles, self.dt)?;
+		}
+		for p in self.particles.iter_mut() {
+			self.forcefield.simbox.wrap(p);
+			if let Some(borders) = &self.forcefield.borders {
+				borders.reflect(&self.forcefield.simbox.size, p);
+			}
+		}
+
+		self.t += self.dt;
+		self.steps += 1;
+
+		self.forcefield.t = self.t;
+		self.forcefield.compute(&mut self.particles)?;
+		self.integrator.post_force(&mut self.particles, self.dt);
+		if let Some(constraints) = &mut self.constraints {
+			constraints.constrain_velocities(&self.forcefield.simbox, &mut self.particles, self.dt)?;
+			self.forcefield.contributions.virial += constraints.virial;
+		}
+
+		scale *= self.with_current_velocities(|sim| {
+			if let Some(barostat) = &mut sim.barostat {
+				let pressure = sim.forcefield.get_pressure(&sim.particles);
+				barostat.post_step(&mut sim.particles, &sim.forcefield.simbox, pressure, sim.t, sim.dt);
+			}
+			match &mut sim.thermostat {
+				Some(thermostat) => thermostat.post_step(&mut sim.particles, sim.t, sim.dt),
+				None => 1.0,
+			}
+		});
+
+		self.data.time.push(self.t);
+		self.forcefield.log(&self.particles, &mut self.data);
+		self.integrator.log(&mut self.data);
+		self.data.global.insert_into("temperature_scale", scale);
+		self.log_state();
+
+		// Take the observers out, so they can borrow the simulation
+		let mut observers = std::mem::take(&mut self.observers);
+		for observer in observers.iter_mut() {
+			observer(self);
+		}
+		self.observers = observers;
+		Ok(())
+	}
+
+	/// Advance the simulation by a number of time steps, stopping at the first step that fails.
+	pub fn run(&mut self, steps: usize) -> Result<(), SimulationError> {
+		for _ in 0..steps {
+			self.step()?;
+		}
+		Ok(())
+	}
+
+	/// Check that the box is still at least twice the range of the interactions along each periodic axis.
+	fn check_box(&self) -> Result<(), SimulationError> {
+		let simbox = &self.forcefield.simbox;
+		let range = self.forcefield.get_range();
+		let sizes = [simbox.size.x, simbox.size.y, simbox.size.z];
+		match (0..3).find(|&k| simbox.periodic[k] && sizes[k] < 2.0 * range) {
+			Some(k) => Err(SimulationError::BoxTooSmall(sizes[k], range)),
+			None => Ok(()),
+		}
+	}
+
+	/// Call a function with the velocities of the particles at the current time, and store the velocities it leaves back in the form of the integrator.
+	///
+	/// Integrators such as leapfrog store the velocities half a step away from the positions, which would bias the temperature and the pressure.
+	fn with_current_velocities<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+		for p in self.particles.iter_mut() {
+			p.v = self.integrator.velocity(p, self.dt);
+		}
+		let result = f(self);
+		for p in self.particles.iter_mut() {
+			let v = p.v;
+			self.integrator.set_velocity(p, v, self.dt);
+		}
+		result
+	}
+
+	/// Log the positions, velocities, accellerations, and kinetic energies of all particles, and the temperature, pressure, volume and conserved energy of the system.
+	/// Must be called after the forcefield has been logged for the same time step.
+	fn log_state(&mut self) {
+		self.with_current_velocities(Self::log_current_state);
+	}
+
+	/// log_state() with the velocities at the current time.
+	fn log_current_state(&mut self) {
+		for i in 0..self.particles.len() {
+			let p = &self.particles[i];
+			let v = p.v;
+			let kinetic = p.m * v.sqlen() / 2.0;
+			self.data.insert_particle_vector_len("position", i, p.pos);
+			self.data.insert_particle_vector_len("position_unwrapped", i, self.forcefield.simbox.unwrapped(p));
+			self.data.insert_particle_vector_len("velocity", i, v);
+			self.data.insert_particle_vector_len("accelleration", i, p.a);
+			self.data.add_to_particle_add("energy_kinetic", i, kinetic);
+			self.data.add_to_particle_add("energy_total", i, kinetic);
+		}
+
+		let removed = self.constraints.as_ref().map_or(0, |c| c.count()) + self.removed_degrees_of_freedom;
+		self.data.global.insert_into("temperature", temperature::get_temperature(&self.particles, removed));
+		self.data.global.insert_into("pressure", self.forcefield.get_pressure(&self.particles));
+		self.data.global.insert_into("volume", self.forcefield.volume());
+
+		// The total energy of the system plus the thermostat and barostat, which is conserved
+		let thermostat = self.thermostat.as_ref().map_or(0.0, |t| t.energy());
+		let barostat = self.barostat.as_ref().map_or(0.0, |b| b.energy(&self.forcefield.simbox));
+		let total = *self.data.global.get("energy_total").last().expect("The forcefield has not been logged");
+		self.data.global.insert_into("energy_thermostat", thermostat);
+		self.data.global.insert_into("energy_barostat", barostat);
+		self.data.global.insert_into("energy_conserved", total + thermostat + barostat);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::vectors::Vector;
+	use crate::simbox::SimBox;
+	use crate::neighbors::NeighborList;
+	use crate::forcefield::Electrostatics;
+	use crate::forcefield::vanderwaals::{Cutoff, CutoffScheme};
+	use crate::forcefield::pressure::Berendsen;
+	use crate::integrators::VelocityVerlet;
+	use crate::constraints::Constraint;
+	use crate::forcefield::bonded::{Bond, BondPotential};
+
+	/// A forcefield without any interactions, in a periodic box.
+	fn ideal_gas(size: f64) -> ForceField {
+		let mut forcefield = ForceField::new();
+		forcefield.vanderwaals = false;
+		forcefield.electrostatic = Electrostatics::Off;
+		forcefield.borders = None;
+		forcefield.simbox = SimBox::new(Vector::new(size, size, size), true);
+		forcefield
+	}
+
+	#[test]
+	fn unsatisfiable_constraints_are_an_error() {
+		// SHAKE cannot move particles on top of each other apart, as there is no direction to move them along
+		let particles = vec![Particle::new(&Vector::new(1.0, 1.0, 1.0), 0.5, 1.0, 0.0), Particle::new(&Vector::new(1.0, 1.0, 1.0), 0.5, 1.0, 0.0)];
+		let mut constraints = Constraints::new(1e-10, 100);
+		constraints.pairs.push(Constraint{ particles: [0, 1], length: 1.0 });
+		let result = Simulation::new(particles, ideal_gas(10.0), Box::new(VelocityVerlet), None, None, Some(constraints), 0, 0.01);
+		assert!(matches!(result, Err(SimulationError::Constraint(ConstraintError::Failed([0, 1])))));
+	}
+
+	#[test]
+	fn overstretched_bond_is_an_error() {
+		let mut forcefield = ideal_gas(10.0);
+		forcefield.topology.add_bond(Bond{ particles: [0, 1], potential: BondPotential::Fene{ k: 1.0, max_length: 1.5 } });
+		let mut particles = vec![Particle::new(&Vector::new(4.0, 5.0, 5.0), 0.5, 1.0, 0.0), Particle::new(&Vector::new(5.0, 5.0, 5.0), 0.5, 1.0, 0.0)];
+		particles[0].v = Vector::new(-100.0, 0.0, 0.0);
+		particles[1].v = Vector::new(100.0, 0.0, 0.0);
+		let mut sim = Simulation::new(particles, forcefield, Box::new(VelocityVerlet), None, None, None, 0, 0.01).unwrap();
+		assert!(matches!(sim.step(), Err(SimulationError::Bond(BondError{ particles: [0, 1], .. }))));
+	}
+
+	#[test]
+	fn barostat_cannot_shrink_box_below_range() {
+		let mut forcefield = ideal_gas(10.0);
+		forcefield.neighbors = Some(NeighborList::new(2.0, 0.5));
+		let particles = (0..8).map(|i| {
+			let mut p = Particle::new(&Vector::new(1.0 + (i % 2) as f64 * 5.0, 1.0 + (i / 2 % 2) as f64 * 5.0, 1.0 + (i / 4) as f64 * 5.0), 0.5, 1.0, 0.0);
+			p.v = Vector::new(0.1, -0.2, 0.3);
+			p
+		}).collect();
+		let barostat = Berendsen::new(20.0, 1.0, 1.0, 0.0);
+		let mut sim = Simulation::new(particles, forcefield, Box::new(VelocityVerlet), None, Some(Box::new(barostat)), None, 0, 0.01).unwrap();
+
+		match sim.run(100) {
+			Err(SimulationError::BoxTooSmall(size, range)) => {
+				assert_eq!(range, 2.5);
+				assert!(size < 5.0 && sim.forcefield.simbox.size.x < 5.0);
+			},
+			_ => panic!("The box was not too small"),
+		}
+	}
+
+	#[test]
+	fn energy_total_is_the_kinetic_plus_the_potential_energy() {
+		let mut forcefield = ideal_gas(20.0);
+		forcefield.vanderwaals = true;
+		forcefield.cutoff = Some(Cutoff{ radius: 4.0, scheme: CutoffScheme::Truncated, tail_correction: true });
+		let positions = [Vector::new(5.0, 5.0, 5.0), Vector::new(7.5, 5.0, 5.0), Vector::new(5.0, 8.0, 5.0)];
+		let particles = positions.iter().enumerate().map(|(i, pos)| {
+			let mut p = Particle::new(pos, 0.5, 1.0 + i as f64, 0.0);
+			p.v = Vector::new(0.5, -1.0, 0.0) * i as f64;
+			p
+		}).collect();
+		let sim = Simulation::new(particles, forcefield, Box::new(VelocityVerlet), None, None, None, 0, 0.01).unwrap();
+
+		// The shifted Lennard-Jones potential of the default species, a function of x = r - sigma with sigma = 1 and 4 epsilon = 4
+		let lj = |x: f64| 4.0 * (x.powi(-12) - x.powi(-6));
+		let u01 = lj(1.5);
+		let u02 = lj(2.0);
+		let u12 = lj(2.5_f64.hypot(3.0) - 1.0);
+		// The integral of r^2 U from the cutoff at x = 3 is 4 times that of (x + 1)^2 (x^-12 - x^-6) = x^-10 + 2 x^-11 + x^-12 - x^-4 - 2 x^-5 - x^-6
+		let integral = |n: i32| 3.0_f64.powi(1 - n) / (n - 1) as f64;
+		let pair_tail = 4.0 * (integral(10) + 2.0 * integral(11) + integral(12) - integral(4) - 2.0 * integral(5) - integral(6));
+		let tail = 3.0 * 4.0 * std::f64::consts::PI / 8000.0 * pair_tail;
+		let kinetic = [0.0, 2.0 * 1.25 / 2.0, 3.0 * 4.0 * 1.25 / 2.0];
+
+		// Each particle has half of the energy of each of its pairs, and a third of the tail correction
+		let expected = [
+			(u01 + u02) / 2.0 + tail / 3.0 + kinetic[0],
+			(u01 + u12) / 2.0 + tail / 3.0 + kinetic[1],
+			(u02 + u12) / 2.0 + tail / 3.0 + kinetic[2],
+		];
+		let energies = sim.data.particle.get("energy_total");
+		for i in 0..3 {
+			assert!((energies[i][0] - expected[i]).abs() < 1e-12, "{} != {}", energies[i][0], expected[i]);
+		}
+		let total = u01 + u02 + u12 + tail + kinetic.iter().sum::<f64>();
+		assert!((sim.data.global.get("energy_total")[0] - total).abs() < 1e-12);
+		assert!((sim.data.global.get("energy_tail")[0] - tail).abs() < 1e-15);
+		assert!((sim.forcefield.get_potential_energy(&sim.particles) + kinetic.iter().sum::<f64>() - total).abs() < 1e-12);
+	}
+}
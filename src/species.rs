@@ -0,0 +1,203 @@
+/*! Particle species, and the pair potential of each pair of species.
+
+Each particle has the index of its species in a SpeciesTable.
+The Lennard-Jones parameters of a pair of different species follow from a mixing rule, unless they are given explicitly,
+and each pair uses the Lennard-Jones potential, unless another pair potential is set.
+*/
+
+use std::rc::Rc;
+
+use crate::vectors::Vector;
+use crate::particles::Particle;
+use crate::forcefield::potentials::{PairPotential, LennardJones};
+
+/// A type of particle.
+#[derive(Clone)]
+pub struct Species {
+	/// The name of the species
+	pub name: String,
+	/// The size of the particles in the Lennard-Jones potential, which is also their radius
+	pub sigma: f64,
+	/// The well depth of the Lennard-Jones potential between two particles of this species
+	pub epsilon: f64,
+	/// Mass
+	pub mass: f64,
+	/// Charge, in elementary charges
+	pub charge: f64,
+}
+
+/// How the Lennard-Jones parameters of a pair of different species follow from those of each species.
+#[derive(Clone, Copy, PartialEq)]
+pub enum MixingRule {
+	/// The arithmetic mean of the sigmas, and the geometric mean of the epsilons
+	LorentzBerthelot,
+	/// The geometric means of both the sigmas and the epsilons
+	Geometric,
+}
+
+/// The Lennard-Jones parameters of a pair of particles.
+#[derive(Clone, Copy)]
+pub struct PairParameters {
+	/// The size of the pair
+	pub sigma: f64,
+	/// 4 * the well depth of the pair
+	pub epsilon_4: f64,
+}
+
+/// All species in a system, and the parameters and potential of each pair of them.
+#[derive(Clone)]
+pub struct SpeciesTable {
+	mixing: MixingRule,
+	species: Vec<Species>,
+	overrides: Vec<(usize, usize, PairParameters)>,
+	potential_overrides: Vec<(usize, usize, Rc<dyn PairPotential>)>,
+	pairs: Vec<PairParameters>,
+	potentials: Vec<Rc<dyn PairPotential>>,
+}
+
+impl SpeciesTable {
+	/// Create an empty table, with a given mixing rule.
+	pub fn new(mixing: MixingRule) -> Self {
+		SpeciesTable{
+			mixing,
+			species: Vec::new(),
+			overrides: Vec::new(),
+			potential_overrides: Vec::new(),
+			pairs: Vec::new(),
+			potentials: Vec::new(),
+		}
+	}
+
+	/// Add a species, returning its index.
+	pub fn add(&mut self, species: Species) -> usize {
+		self.species.push(species);
+		self.update();
+		self.species.len() - 1
+	}
+
+	/// Set the Lennard-Jones parameters of a pair of species explicitly, instead of using the mixing rule.
+	pub fn set_pair(&mut self, a: usize, b: usize, parameters: PairParameters) {
+		self.overrides.retain(|&(i, j, _)| (i, j) != (a, b) && (i, j) != (b, a));
+		self.overrides.push((a, b, parameters));
+		self.update();
+	}
+
+	/// Set the potential of a pair of species, instead of the Lennard-Jones potential.
+	pub fn set_potential(&mut self, a: usize, b: usize, potential: Rc<dyn PairPotential>) {
+		self.potential_overrides.retain(|(i, j, _)| (*i, *j) != (a, b) && (*i, *j) != (b, a));
+		self.potential_overrides.push((a, b, potential));
+		self.update();
+	}
+
+	/// All species, by index.
+	pub fn species(&self) -> &[Species] {
+		&self.species
+	}
+
+	/// The index of the species with a given name, if any.
+	pub fn find(&self, name: &str) -> Option<usize> {
+		self.species.iter().position(|s| s.name == name)
+	}
+
+	/// The Lennard-Jones parameters of a pair of species. Panics if either index is not in the table.
+	pub fn get(&self, a: usize, b: usize) -> PairParameters {
+		assert!(a < self.species.len() && b < self.species.len(), "Species {} or {} is not in the table of {} species", a, b, self.species.len());
+		self.pairs[a * self.species.len() + b]
+	}
+
+	/// The potential of a pair of species. Panics if either index is not in the table.
+	pub fn get_potential(&self, a: usize, b: usize) -> &dyn PairPotential {
+		assert!(a < self.species.len() && b < self.species.len(), "Species {} or {} is not in the table of {} species", a, b, self.species.len());
+		self.potentials[a * self.species.len() + b].as_ref()
+	}
+
+	/// Create a particle of a species at rest at a position, with the radius, mass and charge of the species.
+	pub fn particle(&self, species: usize, pos: &Vector) -> Particle {
+		let s = &self.species[species];
+		let mut p = Particle::new(pos, s.sigma, s.mass, s.charge);
+		p.species = species;
+		p
+	}
+
+	/// Recalculate the parameters and potentials of all pairs.
+	fn update(&mut self) {
+		let n = self.species.len();
+		self.pairs = Vec::with_capacity(n * n);
+		for a in self.species.iter() {
+			for b in self.species.iter() {
+				let sigma = match self.mixing {
+					MixingRule::LorentzBerthelot => (a.sigma + b.sigma) / 2.0,
+					MixingRule::Geometric => (a.sigma * b.sigma).sqrt(),
+				};
+				self.pairs.push(PairParameters{ sigma, epsilon_4: 4.0 * (a.epsilon * b.epsilon).sqrt() });
+			}
+		}
+		for &(a, b, parameters) in self.overrides.iter().filter(|&&(a, b, _)| a < n && b < n) {
+			self.pairs[a * n + b] = parameters;
+			self.pairs[b * n + a] = parameters;
+		}
+
+		self.potentials = self.pairs.iter().map(|p| Rc::new(LennardJones{ epsilon_4: p.epsilon_4, sigma: p.sigma }) as Rc<dyn PairPotential>).collect();
+		for (a, b, potential) in self.potential_overrides.iter().filter(|(a, b, _)| *a < n && *b < n) {
+			self.potentials[a * n + b] = potential.clone();
+			self.potentials[b * n + a] = potential.clone();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::forcefield::potentials::Wca;
+
+	/// A table of two species of different sizes and well depths.
+	fn table(mixing: MixingRule) -> SpeciesTable {
+		let mut table = SpeciesTable::new(mixing);
+		table.add(Species{ name: "a".to_string(), sigma: 1.0, epsilon: 0.5, mass: 1.0, charge: 0.0 });
+		table.add(Species{ name: "b".to_string(), sigma: 4.0, epsilon: 2.0, mass: 1.0, charge: 0.0 });
+		table
+	}
+
+	#[test]
+	fn lorentz_berthelot_cross_terms() {
+		let table = table(MixingRule::LorentzBerthelot);
+		for (a, b) in [(0, 1), (1, 0)] {
+			let pair = table.get(a, b);
+			assert!((pair.sigma - 2.5).abs() < 1e-12);
+			assert!((pair.epsilon_4 - 4.0).abs() < 1e-12);
+		}
+		assert!((table.get(1, 1).sigma - 4.0).abs() < 1e-12);
+		assert!((table.get(1, 1).epsilon_4 - 8.0).abs() < 1e-12);
+	}
+
+	#[test]
+	fn geometric_cross_terms() {
+		let table = table(MixingRule::Geometric);
+		for (a, b) in [(0, 1), (1, 0)] {
+			let pair = table.get(a, b);
+			assert!((pair.sigma - 2.0).abs() < 1e-12);
+			assert!((pair.epsilon_4 - 4.0).abs() < 1e-12);
+		}
+		assert!((table.get(0, 0).sigma - 1.0).abs() < 1e-12);
+	}
+
+	#[test]
+	fn explicit_pairs_override_the_mixing_rule() {
+		let mut table = table(MixingRule::LorentzBerthelot);
+		table.set_pair(1, 0, PairParameters{ sigma: 3.0, epsilon_4: 1.0 });
+		// Kept when the parameters are recalculated for a new species
+		table.add(Species{ name: "c".to_string(), sigma: 2.0, epsilon: 1.0, mass: 1.0, charge: 0.0 });
+		for (a, b) in [(0, 1), (1, 0)] {
+			assert_eq!((table.get(a, b).sigma, table.get(a, b).epsilon_4), (3.0, 1.0));
+			assert!((table.get_potential(a, b).get_energy(3.5) - LennardJones{ epsilon_4: 1.0, sigma: 3.0 }.get_energy(3.5)).abs() < 1e-12);
+		}
+		assert!((table.get(0, 2).sigma - 1.5).abs() < 1e-12);
+
+		// A potential overrides the Lennard-Jones potential, but not its parameters
+		let wca = Wca{ epsilon_4: 2.0, sigma: 1.0 };
+		let energy = wca.get_energy(1.5);
+		table.set_potential(0, 2, Rc::new(wca));
+		assert!((table.get_potential(2, 0).get_energy(1.5) - energy).abs() < 1e-12);
+		assert!((table.get(2, 0).sigma - 1.5).abs() < 1e-12);
+	}
+}
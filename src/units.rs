@@ -0,0 +1,263 @@
+/*! The unit systems in which values are given and written, and their conversion to the internal units.
+
+The simulation itself works in internal units, which are those of the chosen system except for:
+- temperatures, which are given as energies, so the Boltzmann constant is 1
+- time, which is measured in sqrt(mass length^2 / energy), so that F = m a holds without a conversion factor
+- charges, which are given in elementary charges, with the elementary_charge of the forcefield including the Coulomb constant
+- electric and magnetic fields, which follow from the internal charges and time
+
+Values are converted to the internal units when the configuration is read, and back to the units of the system when the data is written.
+*/
+
+use crate::constants::{BOLTZMANN_SI, ELEMENTARY_CHARGE_SI, PERMITTIVITY_VACUUM_SI, AVOGADRO, KCAL};
+
+/// A system of units.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Units {
+	/// Lennard-Jones reduced units, in sigma, epsilon and the mass of a particle, with reduced charges q / sqrt(4 pi e0 sigma epsilon)
+	#[default]
+	Lj,
+	/// Angstrom, femtoseconds, kcal/mol, g/mol, kelvin and elementary charges
+	Real,
+	/// Metres, seconds, joules, kilograms and kelvin, with charges in elementary charges
+	Si,
+}
+
+/// The physical dimension of a value, which sets how it is converted.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Dimension {
+	/// A dimensionless value
+	None,
+	/// A length
+	Length,
+	/// A time
+	Time,
+	/// A mass
+	Mass,
+	/// An energy
+	Energy,
+	/// A temperature
+	Temperature,
+	/// A charge
+	Charge,
+	/// A velocity
+	Velocity,
+	/// An accelleration
+	Accelleration,
+	/// A force
+	Force,
+	/// A pressure
+	Pressure,
+	/// A volume
+	Volume,
+	/// A rate or angular frequency, per unit time
+	Rate,
+	/// An electric field, a force per charge
+	ElectricField,
+	/// A magnetic field, a force per charge and velocity
+	MagneticField,
+}
+
+impl Units {
+	/// The Boltzmann constant, in energy per kelvin.
+	pub fn boltzmann(&self) -> f64 {
+		match self {
+			Units::Lj => 1.0,
+			Units::Real => BOLTZMANN_SI * AVOGADRO / KCAL,
+			Units::Si => BOLTZMANN_SI,
+		}
+	}
+
+	/// The Coulomb energy of two elementary charges at a unit distance, e^2 / (4 pi e0).
+	pub fn coulomb(&self) -> f64 {
+		let si = ELEMENTARY_CHARGE_SI * ELEMENTARY_CHARGE_SI / (4.0 * std::f64::consts::PI * PERMITTIVITY_VACUUM_SI);
+		match self {
+			Units::Lj => 1.0,
+			// J m to kcal/mol A
+			Units::Real => si * AVOGADRO / KCAL * 1e10,
+			Units::Si => si,
+		}
+	}
+
+	/// The elementary charge in the internal units, sqrt(4 pi) times the square root of coulomb(), so that the Coulomb energy is q_i q_j e^2 / (4 pi r).
+	pub fn elementary_charge(&self) -> f64 {
+		(4.0 * std::f64::consts::PI * self.coulomb()).sqrt()
+	}
+
+	/// The internal unit of time, sqrt(mass length^2 / energy), in the unit of time of the system.
+	pub fn time(&self) -> f64 {
+		match self {
+			Units::Lj | Units::Si => 1.0,
+			// sqrt(g/mol A^2 / (kcal/mol)) in fs
+			Units::Real => (1e-3 * 1e-20 / KCAL).sqrt() * 1e15,
+		}
+	}
+
+	/// The elementary charge in the unit of charge of the electric and magnetic fields.
+	fn field_charge(&self) -> f64 {
+		match self {
+			Units::Lj | Units::Real => 1.0,
+			Units::Si => ELEMENTARY_CHARGE_SI,
+		}
+	}
+
+	/// The size of the internal unit of a dimension, in the units of the system.
+	pub fn factor(&self, dimension: Dimension) -> f64 {
+		match dimension {
+			Dimension::Time => self.time(),
+			Dimension::Velocity | Dimension::Rate => 1.0 / self.time(),
+			Dimension::Accelleration => 1.0 / (self.time() * self.time()),
+			Dimension::Temperature => 1.0 / self.boltzmann(),
+			Dimension::ElectricField => self.elementary_charge() / self.field_charge(),
+			Dimension::MagneticField => self.elementary_charge() / self.field_charge() * self.time(),
+			_ => 1.0,
+		}
+	}
+
+	/// Convert a value from the units of the system to the internal units.
+	pub fn to_internal(&self, value: f64, dimension: Dimension) -> f64 {
+		value / self.factor(dimension)
+	}
+
+	/// Convert a value from the internal units to the units of the system.
+	pub fn from_internal(&self, value: f64, dimension: Dimension) -> f64 {
+		value * self.factor(dimension)
+	}
+
+	/// The name of the unit of a dimension, empty for dimensionless values.
+	pub fn name(&self, dimension: Dimension) -> &'static str {
+		match self {
+			Units::Lj => match dimension {
+				Dimension::None => "",
+				Dimension::Length => "sigma",
+				Dimension::Time => "tau",
+				Dimension::Mass => "m",
+				Dimension::Energy => "epsilon",
+				Dimension::Temperature => "epsilon/k_B",
+				Dimension::Charge => "q*",
+				Dimension::Velocity => "sigma/tau",
+				Dimension::Accelleration => "sigma/tau^2",
+				Dimension::Force => "epsilon/sigma",
+				Dimension::Pressure => "epsilon/sigma^3",
+				Dimension::Volume => "sigma^3",
+				Dimension::Rate => "1/tau",
+				Dimension::ElectricField => "epsilon/(sigma q*)",
+				Dimension::MagneticField => "epsilon tau/(sigma^2 q*)",
+			},
+			Units::Real => match dimension {
+				Dimension::None => "",
+				Dimension::Length => "A",
+				Dimension::Time => "fs",
+				Dimension::Mass => "g/mol",
+				Dimension::Energy => "kcal/mol",
+				Dimension::Temperature => "K",
+				Dimension::Charge => "e",
+				Dimension::Velocity => "A/fs",
+				Dimension::Accelleration => "A/fs^2",
+				Dimension::Force => "kcal/(mol A)",
+				Dimension::Pressure => "kcal/(mol A^3)",
+				Dimension::Volume => "A^3",
+				Dimension::Rate => "1/fs",
+				Dimension::ElectricField => "kcal/(mol A e)",
+				Dimension::MagneticField => "kcal fs/(mol A^2 e)",
+			},
+			Units::Si => match dimension {
+				Dimension::None => "",
+				Dimension::Length => "m",
+				Dimension::Time => "s",
+				Dimension::Mass => "kg",
+				Dimension::Energy => "J",
+				Dimension::Temperature => "K",
+				Dimension::Charge => "e",
+				Dimension::Velocity => "m/s",
+				Dimension::Accelleration => "m/s^2",
+				Dimension::Force => "N",
+				Dimension::Pressure => "Pa",
+				Dimension::Volume => "m^3",
+				Dimension::Rate => "1/s",
+				Dimension::ElectricField => "V/m",
+				Dimension::MagneticField => "T",
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const DIMENSIONS: [Dimension; 15] = [
+		Dimension::None, Dimension::Length, Dimension::Time, Dimension::Mass, Dimension::Energy,
+		Dimension::Temperature, Dimension::Charge, Dimension::Velocity, Dimension::Accelleration, Dimension::Force,
+		Dimension::Pressure, Dimension::Volume, Dimension::Rate, Dimension::ElectricField, Dimension::MagneticField,
+	];
+
+	fn assert_close(value: f64, expected: f64, tolerance: f64) {
+		assert!(((value - expected) / expected).abs() < tolerance, "{} != {}", value, expected);
+	}
+
+	#[test]
+	fn lj_units_are_internal() {
+		let charge = (4.0 * std::f64::consts::PI).sqrt();
+		for dimension in DIMENSIONS {
+			match dimension {
+				// the internal charges include sqrt(4 pi), so the fields are divided by it
+				Dimension::ElectricField | Dimension::MagneticField => assert_close(Units::Lj.factor(dimension), charge, 1e-15),
+				_ => assert_eq!(Units::Lj.factor(dimension), 1.0),
+			}
+		}
+		assert_close(Units::Lj.elementary_charge(), charge, 1e-15);
+	}
+
+	#[test]
+	fn real_units_match_known_constants() {
+		assert_close(Units::Real.boltzmann(), 0.00198720426, 1e-9);
+		assert_close(Units::Real.coulomb(), 332.0637, 1e-6);
+		assert_close(Units::Real.time(), 48.88821, 1e-6);
+	}
+
+	#[test]
+	fn si_units_match_known_constants() {
+		assert_eq!(Units::Si.boltzmann(), BOLTZMANN_SI);
+		assert_close(Units::Si.coulomb(), 2.307077e-28, 1e-6);
+		assert_eq!(Units::Si.time(), 1.0);
+	}
+
+	#[test]
+	fn conversions_are_inverse() {
+		for units in [Units::Lj, Units::Real, Units::Si] {
+			for dimension in DIMENSIONS {
+				let value = 1.7;
+				assert_close(units.from_internal(units.to_internal(value, dimension), dimension), value, 1e-14);
+			}
+			assert_close(units.factor(Dimension::Velocity) * units.factor(Dimension::Time), 1.0, 1e-14);
+			assert_close(units.factor(Dimension::Accelleration) * units.factor(Dimension::Time).powi(2), 1.0, 1e-14);
+			assert_close(units.to_internal(units.boltzmann(), Dimension::Temperature), units.boltzmann() * units.boltzmann(), 1e-14);
+		}
+	}
+
+	#[test]
+	fn field_forces_are_in_force_units() {
+		// one elementary charge in a unit field feels a force of one charge unit times the field unit
+		let force = Units::Real.elementary_charge() * Units::Real.to_internal(1.0, Dimension::ElectricField);
+		assert_close(Units::Real.from_internal(force, Dimension::Force), 1.0, 1e-14);
+		let force = Units::Si.elementary_charge() * Units::Si.to_internal(1.0, Dimension::ElectricField);
+		assert_close(Units::Si.from_internal(force, Dimension::Force), ELEMENTARY_CHARGE_SI, 1e-14);
+		// q v B with a velocity of one length per time unit
+		let velocity = Units::Si.to_internal(1.0, Dimension::Velocity);
+		let force = Units::Si.elementary_charge() * velocity * Units::Si.to_internal(1.0, Dimension::MagneticField);
+		assert_close(Units::Si.from_internal(force, Dimension::Force), ELEMENTARY_CHARGE_SI, 1e-14);
+		let velocity = Units::Real.to_internal(1.0, Dimension::Velocity);
+		let force = Units::Real.elementary_charge() * velocity * Units::Real.to_internal(1.0, Dimension::MagneticField);
+		assert_close(Units::Real.from_internal(force, Dimension::Force), 1.0, 1e-14);
+	}
+
+	#[test]
+	fn dimensions_have_names() {
+		for units in [Units::Lj, Units::Real, Units::Si] {
+			for dimension in DIMENSIONS {
+				assert_eq!(units.name(dimension).is_empty(), dimension == Dimension::None);
+			}
+		}
+	}
+}
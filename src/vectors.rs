@@ -1,5 +1,5 @@
 
-//! A 3D vector, with associated functions
+//! A 3D vector and a 3x3 tensor, with associated functions
 
 use std::fmt;
 use std::ops;
@@ -7,8 +7,11 @@ use std::ops;
 /// A 3-dimensional vector.
 #[derive(Copy, Clone)]
 pub struct Vector {
+	/// The x component
 	pub x: f64,
+	/// The y component
 	pub y: f64,
+	/// The z component
 	pub z: f64,
 }
 
@@ -107,6 +110,14 @@ impl ops::Sub<Vector> for Vector {
 	}
 }
 
+impl ops::SubAssign for Vector {
+	fn sub_assign(&mut self, other: Self) {
+		self.x -= other.x;
+		self.y -= other.y;
+		self.z -= other.z;
+	}
+}
+
 impl ops::Mul<f64> for Vector {
 	type Output = Vector;
 	fn mul(self, other: f64) -> Vector {
@@ -139,3 +150,83 @@ impl ops::Neg for Vector {
 		}
 	}
 }
+
+/// A 3x3 tensor, stored as its rows.
+#[derive(Copy, Clone)]
+pub struct Tensor {
+	/// The first row
+	pub x: Vector,
+	/// The second row
+	pub y: Vector,
+	/// The third row
+	pub z: Vector,
+}
+
+impl Tensor {
+	/// Create a new zero tensor.
+	pub fn zero() -> Self {
+		Tensor{ x: Vector::zero(), y: Vector::zero(), z: Vector::zero() }
+	}
+
+	/// Create a new diagonal tensor, with the same value along the diagonal.
+	pub fn diagonal(value: f64) -> Self {
+		Tensor{ x: Vector::unit_x() * value, y: Vector::unit_y() * value, z: Vector::unit_z() * value }
+	}
+
+	/// The outer product of two vectors, with elements a_i * b_j.
+	pub fn outer(a: &Vector, b: &Vector) -> Self {
+		Tensor{ x: *b * a.x, y: *b * a.y, z: *b * a.z }
+	}
+
+	/// The sum of the diagonal elements.
+	pub fn trace(&self) -> f64 {
+		self.x.x + self.y.y + self.z.z
+	}
+}
+
+impl Default for Tensor {
+	fn default() -> Self {
+		Self::zero()
+	}
+}
+
+impl ops::Add<Tensor> for Tensor {
+	type Output = Tensor;
+	fn add(self, other: Tensor) -> Tensor {
+		Tensor{
+			x: self.x + other.x,
+			y: self.y + other.y,
+			z: self.z + other.z,
+		}
+	}
+}
+
+impl ops::AddAssign for Tensor {
+	fn add_assign(&mut self, other: Self) {
+		self.x += other.x;
+		self.y += other.y;
+		self.z += other.z;
+	}
+}
+
+impl ops::Mul<f64> for Tensor {
+	type Output = Tensor;
+	fn mul(self, other: f64) -> Tensor {
+		Tensor{
+			x: self.x * other,
+			y: self.y * other,
+			z: self.z * other,
+		}
+	}
+}
+
+impl ops::Div<f64> for Tensor {
+	type Output = Tensor;
+	fn div(self, other: f64) -> Tensor {
+		Tensor{
+			x: self.x / other,
+			y: self.y / other,
+			z: self.z / other,
+		}
+	}
+}